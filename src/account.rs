@@ -0,0 +1,291 @@
+use crate::config::Config;
+use crate::http;
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+
+/// The account page, under `archive_url`.
+pub const ACCOUNT_PATH: &str = "/account/";
+const KEYRING_SERVICE: &str = "anna-dl";
+const KEYRING_USER: &str = "account";
+
+/// Cookie holding the logged-in session on Anna's Archive.
+pub const SESSION_COOKIE: &str = "aa_account_id2";
+
+/// A secret key for machines without a keyring, such as servers and CI.
+/// It is enough for fast downloads, which don't need a session.
+pub const SECRET_KEY_ENV: &str = "ANNADL_SECRET_KEY";
+
+/// Account details kept in the OS keyring, never in the config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub secret_key: String,
+    pub session: String,
+}
+
+impl Credentials {
+    /// Just a secret key, without a session from logging in.
+    pub fn from_secret_key(secret_key: &str) -> Self {
+        Self { secret_key: secret_key.trim().to_string(), session: String::new() }
+    }
+
+    /// Value for a `Cookie` request header; None without a session.
+    pub fn cookie_header(&self) -> Option<String> {
+        (!self.session.is_empty()).then(|| format!("{}={}", SESSION_COOKIE, self.session))
+    }
+}
+
+/// Membership details shown on the account page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountStatus {
+    /// Membership tier name; None without a membership.
+    pub tier: Option<String>,
+    pub fast_downloads_left: Option<u32>,
+    pub fast_downloads_per_day: Option<u32>,
+}
+
+impl std::fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.tier.as_deref().unwrap_or("no membership"))?;
+        match (self.fast_downloads_left, self.fast_downloads_per_day) {
+            (Some(left), Some(per_day)) => write!(f, ", {}/{} fast downloads left", left, per_day),
+            (Some(left), None) => write!(f, ", {} fast downloads left", left),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reads the membership tier and fast-download quota from the account page.
+pub fn parse_account_status(html: &str) -> AccountStatus {
+    let document = scraper::Html::parse_document(html);
+    let lines: Vec<&str> = document
+        .root_element()
+        .text()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    // "Membership: <tier>" may be split across elements
+    let tier = lines.iter().enumerate().find_map(|(i, line)| {
+        let label = "membership";
+        let rest = line.get(label.len()..).filter(|_| line[..label.len()].eq_ignore_ascii_case(label))?;
+        let rest = rest.trim_start_matches(':').trim();
+        let value = if rest.is_empty() {
+            lines.get(i + 1)?.to_string()
+        } else {
+            rest.to_string()
+        };
+        let value = value.split(['(', '|']).next()?.split(" until ").next()?.trim().to_string();
+        Some(value)
+    });
+    let tier = tier.filter(|t| !t.is_empty() && !t.eq_ignore_ascii_case("none"));
+
+    let text = lines.join(" ");
+    let quota = regex::Regex::new(r"(?i)(\d+)\s*(?:/|of|out of)\s*(\d+)\s+fast downloads").ok()
+        .and_then(|re| re.captures(&text))
+        .map(|caps| (caps[1].parse().ok(), caps[2].parse().ok()));
+    let (fast_downloads_left, fast_downloads_per_day) = quota.unwrap_or_else(|| {
+        let left = regex::Regex::new(r"(?i)(\d+)\s+fast downloads (?:left|remaining)").ok()
+            .and_then(|re| re.captures(&text))
+            .and_then(|caps| caps[1].parse().ok());
+        (left, None)
+    });
+
+    AccountStatus {
+        tier,
+        fast_downloads_left,
+        fast_downloads_per_day,
+    }
+}
+
+/// Exchanges a secret key for a session cookie.
+pub async fn login(config: &Config, secret_key: &str) -> Result<Credentials> {
+    login_at(config, &format!("{}{}", config.archive_url(), ACCOUNT_PATH), secret_key).await
+}
+
+async fn login_at(config: &Config, url: &str, secret_key: &str) -> Result<Credentials> {
+    // The session cookie is set on a redirect, which must not be followed
+    let client = http::client_builder(config, config.scraper_timeouts())?
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .post(url)
+        .form(&[("key", secret_key)])
+        .send()
+        .await
+        .context("Failed to reach the login page")?;
+
+    let session = session_from_headers(response.headers())
+        .context("Login failed: the secret key was not accepted")?;
+
+    Ok(Credentials {
+        secret_key: secret_key.to_string(),
+        session,
+    })
+}
+
+fn session_from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|cookie| {
+            let pair = cookie.split(';').next()?;
+            let (name, value) = pair.split_once('=')?;
+            (name.trim() == SESSION_COOKIE && !value.trim().is_empty()).then(|| value.trim().to_string())
+        })
+}
+
+// Keyring access blocks, and the secret-service backend deadlocks when it
+// shares a thread with the runtime, so every call gets a blocking thread.
+
+/// The secret key in `ANNADL_SECRET_KEY`, else the stored credentials if
+/// the user has logged in.
+pub async fn load() -> Result<Option<Credentials>> {
+    if let Some(credentials) = from_env(std::env::var(SECRET_KEY_ENV).ok()) {
+        return Ok(Some(credentials));
+    }
+    let Some(json) = load_password(KEYRING_USER).await? else {
+        return Ok(None);
+    };
+    Ok(Some(serde_json::from_str(&json).context("Stored credentials are corrupt")?))
+}
+
+fn from_env(value: Option<String>) -> Option<Credentials> {
+    value.filter(|key| !key.trim().is_empty()).map(|key| Credentials::from_secret_key(&key))
+}
+
+pub async fn store(credentials: &Credentials) -> Result<()> {
+    let json = serde_json::to_string(credentials)?;
+    store_password(KEYRING_USER, "credentials", json).await
+}
+
+/// The secret kept under `entry` in the keyring, None if there is none.
+/// Other features keep their passwords beside the account session this way.
+pub async fn load_password(entry: &'static str) -> Result<Option<String>> {
+    tokio::task::spawn_blocking(move || {
+        match keyring::Entry::new(KEYRING_SERVICE, entry)?.get_password() {
+            Ok(password) => Ok(Some(password)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await?
+}
+
+/// Saves `password` under `entry` in the keyring. `what` names it in the
+/// error, e.g. "the SMTP password".
+pub async fn store_password(entry: &'static str, what: &'static str, password: String) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        keyring::Entry::new(KEYRING_SERVICE, entry)?
+            .set_password(&password)
+            .with_context(|| format!("Failed to save {} to the OS keyring", what))
+    })
+    .await?
+}
+
+/// Forgets stored credentials; returns whether there were any.
+pub async fn delete() -> Result<bool> {
+    tokio::task::spawn_blocking(|| {
+        match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(anyhow::Error::new(e).context("Failed to remove credentials from the OS keyring")),
+        }
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_session_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("other=1; Path=/"));
+        assert_eq!(session_from_headers(&headers), None);
+
+        headers.append(SET_COOKIE, HeaderValue::from_static("aa_account_id2=abc.def; Path=/; HttpOnly"));
+        assert_eq!(session_from_headers(&headers), Some("abc.def".to_string()));
+
+        let mut cleared = HeaderMap::new();
+        cleared.append(SET_COOKIE, HeaderValue::from_static("aa_account_id2=; Max-Age=0"));
+        assert_eq!(session_from_headers(&cleared), None);
+    }
+
+    #[test]
+    fn test_parse_account_status() {
+        let html = r#"
+            <html><body>
+                <div><strong>Membership:</strong> <span>Brilliant Bookworm (until 2026-12-01)</span></div>
+                <p>You have 23 / 25 fast downloads left today.</p>
+            </body></html>
+        "#;
+        let status = parse_account_status(html);
+        assert_eq!(status.tier.as_deref(), Some("Brilliant Bookworm"));
+        assert_eq!(status.fast_downloads_left, Some(23));
+        assert_eq!(status.fast_downloads_per_day, Some(25));
+        assert_eq!(status.to_string(), "Brilliant Bookworm, 23/25 fast downloads left");
+
+        let html = "<p>Membership: None</p><p>0 fast downloads remaining</p>";
+        let status = parse_account_status(html);
+        assert_eq!(status.tier, None);
+        assert_eq!(status.fast_downloads_left, Some(0));
+        assert_eq!(status.to_string(), "no membership, 0 fast downloads left");
+
+        assert_eq!(parse_account_status("<p>Log in</p>"), AccountStatus::default());
+
+        // Letters that change length when lowercased stay intact
+        let status = parse_account_status("<p>MEMBERSHIP: İyi İnsan</p>");
+        assert_eq!(status.tier.as_deref(), Some("İyi İnsan"));
+        assert_eq!(parse_account_status("<p>Üye</p>").tier, None);
+    }
+
+    #[test]
+    fn test_cookie_header() {
+        let credentials = Credentials {
+            secret_key: "secret".to_string(),
+            session: "abc".to_string(),
+        };
+        assert_eq!(credentials.cookie_header().as_deref(), Some("aa_account_id2=abc"));
+        assert_eq!(Credentials::from_secret_key("secret").cookie_header(), None);
+    }
+
+    #[test]
+    fn test_secret_key_from_env() {
+        assert_eq!(from_env(Some(" secret\n".to_string())), Some(Credentials::from_secret_key("secret")));
+        assert_eq!(from_env(Some("secret".to_string())).unwrap().secret_key, "secret");
+        assert_eq!(from_env(Some("  ".to_string())), None);
+        assert_eq!(from_env(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_login_reads_session_from_redirect() {
+        let (base, server) = test_server::serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /account/\r\nSet-Cookie: aa_account_id2=session123; Path=/\r\nContent-Length: 0\r\n\r\n",
+        ]).await;
+        let url = format!("{}/account/", base);
+
+        let credentials = login_at(&Config::default(), &url, "my-secret").await.unwrap();
+        assert_eq!(credentials.session, "session123");
+        assert_eq!(credentials.secret_key, "my-secret");
+
+        let request = &server.await.unwrap()[0];
+        assert!(request.starts_with("POST /account/"));
+        assert!(request.contains("key=my-secret"));
+    }
+
+    #[tokio::test]
+    async fn test_login_rejected_key() {
+        let (base, _server) = test_server::serve(vec!["HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"]).await;
+        let url = format!("{}/account/", base);
+
+        let err = login_at(&Config::default(), &url, "wrong").await.unwrap_err();
+        assert!(err.to_string().contains("not accepted"));
+    }
+}
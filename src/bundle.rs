@@ -0,0 +1,121 @@
+//! `annadl bundle`: zipping downloaded books with their metadata sidecars
+//! for moving them to another machine.
+
+use crate::opf;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+/// Downloads in `dir` whose file names contain every term, ignoring case;
+/// all of them when no terms are given. Unfinished downloads and sidecars
+/// are skipped.
+pub fn select<S: AsRef<str>>(dir: &Path, terms: &[S]) -> Result<Vec<PathBuf>> {
+    let terms: Vec<String> = terms.iter().map(|t| t.as_ref().to_lowercase()).collect();
+    let mut books: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            !matches!(extension.as_str(), "opf" | "part" | "crdownload" | "zip")
+        })
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+            !name.starts_with('.') && terms.iter().all(|term| name.contains(term))
+        })
+        .collect();
+    books.sort();
+    Ok(books)
+}
+
+/// Writes `books` and any `.opf` sidecars next to them into a zip at
+/// `output`; returns the number of files added.
+pub fn write(output: &Path, books: &[PathBuf]) -> Result<usize> {
+    let file = File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    // Books are already compressed; deflating them again only costs time
+    let stored = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    let deflated = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    // Formats of one title share a sidecar; zip names must be unique
+    let mut names = HashSet::new();
+    for book in books {
+        let sidecar = opf::sidecar_path(book);
+        let entries = std::iter::once((book.as_path(), stored))
+            .chain(sidecar.is_file().then_some((sidecar.as_path(), deflated)));
+        for (path, options) in entries {
+            let name = path.file_name().context("Not a file")?.to_string_lossy().into_owned();
+            if !names.insert(name.clone()) {
+                continue;
+            }
+            zip.start_file(name, options)?;
+            let mut reader = BufReader::new(File::open(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?);
+            io::copy(&mut reader, &mut zip)?;
+        }
+    }
+    zip.finish().context("Failed to finish the zip")?;
+    Ok(names.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn temp_dir() -> PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_bundle_{}", nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_select() {
+        let dir = temp_dir();
+        for name in ["Dune - Frank Herbert.epub", "Dune - Frank Herbert.opf", "Dune Messiah - Frank Herbert.pdf", "Emma - Jane Austen.epub", "Emma.epub.part", ".DS_Store"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        std::fs::create_dir(dir.join("quarantine")).unwrap();
+
+        let all = select::<&str>(&dir, &[]).unwrap();
+        assert_eq!(all.len(), 3);
+        let herbert = select(&dir, &["HERBERT"]).unwrap();
+        assert_eq!(herbert.len(), 2);
+        let messiah = select(&dir, &["dune", "messiah"]).unwrap();
+        assert_eq!(messiah, vec![dir.join("Dune Messiah - Frank Herbert.pdf")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_includes_sidecars() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("Dune.epub"), b"PK epub").unwrap();
+        std::fs::write(dir.join("Dune.pdf"), b"%PDF dune").unwrap();
+        std::fs::write(dir.join("Dune.opf"), b"<package/>").unwrap();
+        std::fs::write(dir.join("Emma.pdf"), b"%PDF").unwrap();
+        let output = dir.join("books.zip");
+
+        // Both Dune formats point at the one sidecar, which is added once
+        let added = write(&output, &select::<&str>(&dir, &[]).unwrap()).unwrap();
+        assert_eq!(added, 4);
+
+        let mut archive = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, vec!["Dune.epub", "Dune.opf", "Dune.pdf", "Emma.pdf"]);
+        let mut contents = String::new();
+        archive.by_name("Dune.opf").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "<package/>");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+//! What happens to a finished download: the metadata sidecar, the copy to an
+//! e-reader, Send to Kindle and uploads. The CLI and TUI only report results.
+
+use crate::config::Config;
+use crate::device;
+use crate::kindle;
+use crate::opf;
+use crate::scraper::Book;
+use crate::upload;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// The outcome of each enabled step; `None` for steps that are off.
+#[derive(Debug, Default)]
+pub struct Delivery {
+    pub opf: Option<Result<PathBuf>>,
+    pub device: Option<Result<device::Sent>>,
+    pub kindle: Option<Result<()>>,
+    pub upload: Option<Result<Vec<String>>>,
+}
+
+/// Runs the steps enabled in `config` for `book` downloaded to `path`. A
+/// failed step doesn't stop the ones after it.
+pub async fn deliver(config: &Config, book: &Book, path: &Path) -> Delivery {
+    let mut delivery = Delivery::default();
+    if config.write_opf {
+        delivery.opf = Some(opf::write_sidecar(path, book));
+    }
+    if config.send_to_device {
+        delivery.device = Some(device::send(config, path).await);
+    }
+    if config.send_to_kindle {
+        delivery.kindle = Some(kindle::send(config, path).await);
+    }
+    if upload::enabled(config) {
+        delivery.upload = Some(upload::upload(config, path).await);
+    }
+    delivery
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deliver_runs_enabled_steps() {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_delivery_{}", nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("Dune.epub");
+        std::fs::write(&file, b"PK epub").unwrap();
+        let book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+
+        let delivery = deliver(&Config::default(), &book, &file).await;
+        assert!(delivery.opf.is_none() && delivery.device.is_none() && delivery.kindle.is_none() && delivery.upload.is_none());
+
+        // A missing device is reported without skipping the other steps
+        let config = Config { write_opf: true, send_to_device: true, device_mounts: vec![dir.join("no-device").display().to_string()], ..Config::default() };
+        let delivery = deliver(&config, &book, &file).await;
+        assert_eq!(delivery.opf.unwrap().unwrap(), dir.join("Dune.opf"));
+        assert!(delivery.device.unwrap().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+//! Copying downloads onto a mounted Kindle or Kobo.
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Kindle,
+    Kobo,
+}
+
+impl DeviceKind {
+    /// Extensions the device opens without conversion.
+    fn native_formats(self) -> &'static [&'static str] {
+        match self {
+            Self::Kindle => &["azw3", "azw", "mobi", "pdf", "txt"],
+            Self::Kobo => &["epub", "kepub", "pdf", "mobi", "txt", "rtf", "html", "cbz", "cbr"],
+        }
+    }
+
+    /// What other formats are converted to.
+    fn preferred_format(self) -> &'static str {
+        match self {
+            Self::Kindle => "azw3",
+            Self::Kobo => "epub",
+        }
+    }
+}
+
+/// A mounted e-reader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub kind: DeviceKind,
+    pub root: PathBuf,
+}
+
+impl Device {
+    /// Recognises a mount point by the folders each device keeps at its root.
+    fn probe(root: &Path) -> Option<Self> {
+        let kind = if root.join(".kobo").is_dir() {
+            DeviceKind::Kobo
+        } else if root.join("documents").is_dir() && root.join("system").is_dir() {
+            DeviceKind::Kindle
+        } else {
+            return None;
+        };
+        Some(Self { kind, root: root.to_path_buf() })
+    }
+
+    /// Where books go: Kindles only index `documents`, Kobos scan everything.
+    pub fn books_dir(&self) -> PathBuf {
+        match self.kind {
+            DeviceKind::Kindle => self.root.join("documents"),
+            DeviceKind::Kobo => self.root.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at {}", self.kind, self.root.display())
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of characters.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &name[i..]))
+        }
+    }
+}
+
+/// Existing directories matching a path pattern with `*` components, such
+/// as `/media/*/*`.
+fn expand(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let mut found = vec![PathBuf::new()];
+    for component in path.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains('*') {
+            found.iter_mut().for_each(|p| p.push(component));
+            continue;
+        }
+        found = found.iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.flatten())
+            .filter(|entry| wildcard_match(&part, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+    }
+    found.into_iter().filter(|p| p.is_dir()).collect()
+}
+
+/// E-readers mounted at any of `patterns`.
+pub fn detect<S: AsRef<str>>(patterns: &[S]) -> Vec<Device> {
+    let mut devices = Vec::new();
+    for root in patterns.iter().flat_map(|p| expand(p.as_ref())) {
+        if let Some(device) = Device::probe(&root) {
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+    }
+    devices
+}
+
+/// The result of sending one file.
+#[derive(Debug)]
+pub struct Sent {
+    pub device: Device,
+    pub path: PathBuf,
+    pub converted: bool,
+}
+
+/// Copies `file` onto the first detected e-reader, converting it with
+/// Calibre's `ebook-convert` first when the device can't open it and
+/// `device_convert` is on.
+pub async fn send(config: &Config, file: &Path) -> Result<Sent> {
+    let device = detect(&config.device_mounts).into_iter().next()
+        .context("No Kindle or Kobo found; connect it or add its mount point to device_mounts")?;
+    let books_dir = device.books_dir();
+    let name = file.file_name().context("Not a file")?;
+    let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    if !config.device_convert || device.kind.native_formats().contains(&extension.as_str()) {
+        let target = books_dir.join(name);
+        tokio::fs::copy(file, &target).await
+            .with_context(|| format!("Failed to copy to {}", target.display()))?;
+        return Ok(Sent { device, path: target, converted: false });
+    }
+
+    let target = books_dir.join(name).with_extension(device.kind.preferred_format());
+    let converter = config.ebook_convert_path.clone().unwrap_or_else(|| PathBuf::from("ebook-convert"));
+    let output = tokio::process::Command::new(&converter)
+        .arg(file)
+        .arg(&target)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} (is Calibre installed?)", converter.display()))?;
+    if !output.status.success() {
+        // ebook-convert can leave a partial file behind
+        let _ = tokio::fs::remove_file(&target).await;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ebook-convert exited with {}: {}", output.status, stderr.lines().last().unwrap_or_default());
+    }
+    Ok(Sent { device, path: target, converted: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_device_{}_{}", name, nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "Kindle"));
+        assert!(wildcard_match("KOBO*", "KOBOeReader"));
+        assert!(wildcard_match("*eReader", "KOBOeReader"));
+        assert!(!wildcard_match("Kindle", "KOBOeReader"));
+        assert!(!wildcard_match("K*e", "Kobo"));
+    }
+
+    #[test]
+    fn test_detect() {
+        let media = temp_dir("media");
+        std::fs::create_dir_all(media.join("alice/Kindle/documents")).unwrap();
+        std::fs::create_dir_all(media.join("alice/Kindle/system")).unwrap();
+        std::fs::create_dir_all(media.join("alice/KOBOeReader/.kobo")).unwrap();
+        std::fs::create_dir_all(media.join("alice/USB stick")).unwrap();
+
+        let pattern = format!("{}/*/*", media.display());
+        let mut devices = detect(&[pattern.as_str(), pattern.as_str()]);
+        devices.sort_by_key(|d| d.root.clone());
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].kind, DeviceKind::Kobo);
+        assert_eq!(devices[0].books_dir(), media.join("alice/KOBOeReader"));
+        assert_eq!(devices[1].kind, DeviceKind::Kindle);
+        assert_eq!(devices[1].books_dir(), media.join("alice/Kindle/documents"));
+
+        assert!(detect(&[format!("{}/nobody/*", media.display())]).is_empty());
+        std::fs::remove_dir_all(&media).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_copies_native_formats() {
+        let kindle = temp_dir("kindle");
+        std::fs::create_dir_all(kindle.join("documents")).unwrap();
+        std::fs::create_dir_all(kindle.join("system")).unwrap();
+        let file = temp_dir("download").join("Dune.pdf");
+        std::fs::write(&file, b"%PDF-1.7").unwrap();
+
+        let config = Config {
+            device_mounts: vec![kindle.display().to_string()],
+            device_convert: true,
+            ebook_convert_path: Some(PathBuf::from("/nonexistent/ebook-convert")),
+            ..Config::default()
+        };
+        let sent = send(&config, &file).await.unwrap();
+        assert!(!sent.converted);
+        assert_eq!(std::fs::read(kindle.join("documents/Dune.pdf")).unwrap(), b"%PDF-1.7");
+
+        // EPUB needs converting for a Kindle
+        let epub = file.with_extension("epub");
+        std::fs::write(&epub, b"PK").unwrap();
+        let err = send(&config, &epub).await.unwrap_err();
+        assert!(err.to_string().contains("is Calibre installed"));
+
+        std::fs::remove_dir_all(&kindle).unwrap();
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_without_device() {
+        let config = Config { device_mounts: vec!["/nonexistent/*".to_string()], ..Config::default() };
+        let err = send(&config, Path::new("Dune.epub")).await.unwrap_err();
+        assert!(err.to_string().contains("No Kindle or Kobo found"));
+    }
+}
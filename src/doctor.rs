@@ -0,0 +1,324 @@
+use crate::config::Config;
+use crate::http;
+use crate::term;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Download hosts checked when no `allowed_domains` are configured.
+const DOWNLOAD_HOSTS: &[&str] = &["libgen.li"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Failed,
+}
+
+/// The outcome of one diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = match self.status {
+            Status::Ok => "✓",
+            Status::Warning => "!",
+            Status::Failed => "✗",
+        };
+        write!(f, "[{}] {}: {}", mark, self.name, self.detail)
+    }
+}
+
+/// Loads the config file without creating it, falling back to defaults so
+/// the remaining checks still run when it is broken.
+pub fn load_config() -> (Config, Check) {
+    match Config::config_path() {
+        Ok(path) => check_config_file(&path),
+        Err(e) => (Config::default(), Check::new("Config", Status::Failed, e.to_string())),
+    }
+}
+
+fn check_config_file(path: &Path) -> (Config, Check) {
+    let name = format!("Config {}", path.display());
+    if !path.exists() {
+        return (Config::default(), Check::new(name, Status::Warning, "not created yet, using defaults"));
+    }
+    let parsed = std::fs::read_to_string(path)
+        .map_err(|e| format!("unreadable ({})", e))
+        .and_then(|contents| serde_json::from_str::<Config>(&contents).map_err(|e| format!("invalid JSON ({})", e)));
+    match parsed {
+        Ok(config) => (config, Check::new(name, Status::Ok, "valid")),
+        Err(e) => (Config::default(), Check::new(name, Status::Failed, format!("{}, using defaults", e))),
+    }
+}
+
+/// Runs every check, printing each result as it completes; returns the
+/// number of failures.
+pub async fn run(config: &Config, config_check: Check, download_path: &Path) -> usize {
+    println!("Checking anna-dl setup...\n");
+    let mut checks = vec![config_check, check_download_dir(download_path)];
+    for check in &checks {
+        term::outln!("{}", check);
+    }
+
+    let client_check = match http::client_builder(config, config.scraper_timeouts()) {
+        Ok(_) => Check::new("TLS settings", Status::Ok, tls_summary(config)),
+        Err(e) => Check::new("TLS settings", Status::Failed, format!("{:#}", e)),
+    };
+    term::outln!("{}", client_check);
+    let client_ok = client_check.status != Status::Failed;
+    checks.push(client_check);
+
+    if client_ok {
+        for url in endpoints(config) {
+            let check = check_endpoint(config, &url).await;
+            term::outln!("{}", check);
+            checks.push(check);
+        }
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Failed).count();
+    let warnings = checks.iter().filter(|c| c.status == Status::Warning).count();
+    println!();
+    match (failed, warnings) {
+        (0, 0) => println!("No issues found."),
+        (0, w) => println!("No problems, {} warning(s).", w),
+        (f, _) => println!("{} check(s) failed.", f),
+    }
+    failed
+}
+
+fn tls_summary(config: &Config) -> String {
+    let trust = match config.ca_cert {
+        Some(ref path) => format!("system roots plus {}", path.display()),
+        None => "system roots".to_string(),
+    };
+    if config.insecure {
+        format!("{}, verification DISABLED", trust)
+    } else {
+        trust
+    }
+}
+
+/// Leaves the filesystem as it was: a missing directory is reported, not
+/// created, and its nearest existing parent is probed instead.
+fn check_download_dir(path: &Path) -> Check {
+    let name = format!("Download directory {}", path.display());
+    if path.is_dir() {
+        return match probe_writable(path) {
+            Ok(()) => Check::new(name, Status::Ok, "writable"),
+            Err(e) => Check::new(name, Status::Failed, format!("not writable ({})", e)),
+        };
+    }
+    if path.exists() {
+        return Check::new(name, Status::Failed, "not a directory");
+    }
+    // A relative path's last ancestor is empty, meaning the current directory
+    let parent = path.ancestors().skip(1)
+        .map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+        .find(|p| p.exists());
+    match parent {
+        Some(parent) if !parent.is_dir() => {
+            Check::new(name, Status::Failed, format!("missing, and {} is not a directory", parent.display()))
+        }
+        Some(parent) => match probe_writable(parent) {
+            Ok(()) => Check::new(name, Status::Warning, "missing, created on the first download"),
+            Err(e) => Check::new(name, Status::Failed, format!("missing, and {} is not writable ({})", parent.display(), e)),
+        },
+        None => Check::new(name, Status::Warning, "missing, and none of its parents exist"),
+    }
+}
+
+/// Writes and removes an empty file in `dir`.
+fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(".annadl_doctor");
+    std::fs::write(&probe, b"")?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// The archive plus the download hosts links may point to.
+fn endpoints(config: &Config) -> Vec<String> {
+    let mut urls = vec![format!("{}/", config.archive_url())];
+    let hosts: Vec<&str> = if config.allowed_domains.is_empty() {
+        DOWNLOAD_HOSTS.to_vec()
+    } else {
+        config.allowed_domains.iter().map(String::as_str).collect()
+    };
+    for host in hosts {
+        let host = host.trim().trim_start_matches('.').to_lowercase();
+        let blocked = config.blocked_domains.iter().any(|b| b.trim().eq_ignore_ascii_case(&host));
+        let url = format!("https://{}/", host);
+        if !host.is_empty() && !blocked && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// Requests one URL with the same client, and so the same proxy or Tor,
+/// as a search, reporting how far the request got. No lookup or connection
+/// is made outside the proxy.
+async fn check_endpoint(config: &Config, url: &str) -> Check {
+    let Some((host, https)) = reqwest::Url::parse(url).ok().and_then(|u| {
+        Some((u.host_str()?.to_string(), u.scheme() == "https"))
+    }) else {
+        return Check::new(url, Status::Failed, "invalid URL");
+    };
+    let route = match config.proxy_url() {
+        Some(_) if config.tor => "via Tor, ",
+        Some(_) => "via proxy, ",
+        None => "",
+    };
+
+    // Redirects are fine; the host answered
+    let client = match http::client_builder(config, config.scraper_timeouts())
+        .and_then(|b| Ok(b.redirect(reqwest::redirect::Policy::none()).build()?))
+    {
+        Ok(client) => client,
+        Err(e) => return Check::new(&host, Status::Failed, format!("{:#}", e)),
+    };
+    let started = std::time::Instant::now();
+    let response = client.get(url).headers(http::UserAgents::from_config(config).headers()).send().await;
+    let tls = if https { "TLS ok, " } else { "" };
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            let detail = format!("{}{}HTTP {} in {}", route, tls, status.as_u16(), format_elapsed(started.elapsed()));
+            if status.is_server_error() || status.as_u16() == 403 || status.as_u16() == 429 {
+                Check::new(&host, Status::Warning, detail)
+            } else {
+                Check::new(&host, Status::Ok, detail)
+            }
+        }
+        Err(e) if e.is_timeout() => Check::new(&host, Status::Failed, format!("{}request timed out", route)),
+        // DNS, the TCP connection, the proxy and the TLS handshake all fail
+        // as connect errors; the cause tells them apart
+        Err(e) if e.is_connect() => Check::new(&host, Status::Failed, format!("{}connection failed ({})", route, root_cause(&e))),
+        Err(e) => Check::new(&host, Status::Failed, format!("{}{}HTTP request failed ({})", route, tls, root_cause(&e))),
+    }
+}
+
+fn root_cause(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut source = error;
+    while let Some(next) = source.source() {
+        source = next;
+    }
+    source.to_string()
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("{}ms", elapsed.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("annadl_doctor_{}_{}", name, nanos))
+    }
+
+    #[test]
+    fn test_check_config_file() {
+        let path = temp_path("config.json");
+
+        let (_, check) = check_config_file(&path);
+        assert_eq!(check.status, Status::Warning);
+
+        std::fs::write(&path, r#"{"default_num_results": 7}"#).unwrap();
+        let (config, check) = check_config_file(&path);
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(config.default_num_results, 7);
+
+        std::fs::write(&path, "{ not json").unwrap();
+        let (config, check) = check_config_file(&path);
+        assert_eq!(check.status, Status::Failed);
+        assert!(check.detail.contains("invalid JSON"));
+        assert_eq!(config.default_num_results, Config::default().default_num_results);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_download_dir() {
+        let dir = temp_path("downloads");
+        let missing = dir.join("books");
+        std::fs::create_dir(&dir).unwrap();
+        let check = check_download_dir(&dir);
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // A missing directory is a warning and is left uncreated
+        let check = check_download_dir(&missing.join("new"));
+        assert_eq!((check.status, check.detail.as_str()), (Status::Warning, "missing, created on the first download"));
+        assert!(!missing.exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+
+        let file = temp_path("file");
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(check_download_dir(&file).status, Status::Failed);
+        assert_eq!(check_download_dir(&file.join("books")).status, Status::Failed);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let urls = endpoints(&Config::default());
+        assert_eq!(urls, vec!["https://annas-archive.org/".to_string(), "https://libgen.li/".to_string()]);
+
+        let config = Config {
+            allowed_domains: vec!["Mirror.example".to_string(), "blocked.example".to_string()],
+            blocked_domains: vec!["blocked.example".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(endpoints(&config), vec!["https://annas-archive.org/".to_string(), "https://mirror.example/".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_reachable() {
+        let (base, _server) = test_server::serve(vec!["HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"]).await;
+
+        let check = check_endpoint(&Config::default(), &format!("{}/", base)).await;
+        assert_eq!(check.status, Status::Ok, "{}", check);
+        assert_eq!(check.detail.split(" in ").next(), Some("HTTP 200"));
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_through_proxy() {
+        // The proxy gets the whole request, so the host is never resolved here
+        let (proxy, proxied) = test_server::serve(vec!["HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"]).await;
+
+        let config = Config { proxy: Some(proxy), ..Config::default() };
+        let check = check_endpoint(&config, "http://archive.invalid/").await;
+        assert_eq!(check.status, Status::Ok, "{}", check);
+        assert!(check.detail.starts_with("via proxy, HTTP 200"));
+        assert!(proxied.await.unwrap()[0].starts_with("GET http://archive.invalid/"));
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_refused() {
+        let dead = test_server::unused_url().await;
+        let check = check_endpoint(&Config::default(), &format!("{}/", dead)).await;
+        assert_eq!(check.status, Status::Failed);
+        assert!(check.detail.starts_with("connection failed"), "{}", check);
+    }
+}
@@ -1,129 +1,1324 @@
+use crate::config::{Config, DownloaderBackend, LinkCheck};
+use crate::http::{self, UserAgents};
+use crate::locale;
+use crate::reliability::History;
+use crate::term;
+use crate::retry::{self, RetryPolicy, Stalled};
 use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use md5::{Digest, Md5};
+use reqwest::header::HeaderMap;
+use reqwest::{redirect, Url};
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, Semaphore};
 use futures::StreamExt;
 
+/// Minimum time between two progress events for the same download.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How far back `TransferStats` looks when computing the current speed.
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// Spacing of the throughput samples behind the speed sparkline.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Throughput samples kept per download, one per `SAMPLE_INTERVAL`.
+const HISTORY_LEN: usize = 120;
+
+/// Weight of the newest sample in the smoothed speed used for the ETA.
+const SMOOTHING: f64 = 0.3;
+
+/// Empty samples in a row after which a download counts as stalled.
+const STALL_SAMPLES: usize = 3;
+
+/// Subfolder of the download path that receives files failing validation.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// URL fragments of the pages mirrors redirect to once the quota is used up.
+const LIMIT_URL_MARKERS: &[&str] = &["download_limit", "limit_reached", "daily_limit"];
+
+/// Phrases identifying a "download limit reached" HTML page.
+const LIMIT_PAGE_MARKERS: &[&str] = &["download limit", "limit reached", "too many downloads"];
+
+/// File extensions for the Content-Types book mirrors commonly send.
+const CONTENT_TYPE_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/epub+zip", "epub"),
+    ("application/pdf", "pdf"),
+    ("application/x-mobipocket-ebook", "mobi"),
+    ("application/vnd.amazon.ebook", "azw"),
+    ("application/vnd.amazon.mobi8-ebook", "azw3"),
+    ("image/vnd.djvu", "djvu"),
+    ("image/x-djvu", "djvu"),
+    ("application/x-fictionbook+xml", "fb2"),
+    ("application/msword", "doc"),
+    ("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "docx"),
+    ("application/rtf", "rtf"),
+    ("text/plain", "txt"),
+    ("application/zip", "zip"),
+    ("application/vnd.comicbook+zip", "cbz"),
+    ("application/x-cbz", "cbz"),
+    ("application/vnd.comicbook-rar", "cbr"),
+    ("application/x-cbr", "cbr"),
+    ("application/x-cb7", "cb7"),
+    ("audio/x-m4b", "m4b"),
+    ("audio/mp4", "m4b"),
+    ("audio/mpeg", "mp3"),
+    ("audio/flac", "flac"),
+    ("audio/x-flac", "flac"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/x-7z-compressed", "7z"),
+];
+
+/// Failures where a mirror answered, but not with the requested file, and
+/// local failures that end a download. Returned inside `anyhow::Error`;
+/// callers branch with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Too many redirects (limit is {0})")]
+    TooManyRedirects(usize),
+    #[error("Redirect loop detected at {0}")]
+    RedirectLoop(String),
+    #[error("Download limit reached ({0})")]
+    LimitReached(String),
+    #[error("Expected a file but got an HTML page ({0})")]
+    HtmlPage(String),
+    /// A slow-download queue longer than `waitlist_max_secs`.
+    #[error("The mirror's waitlist is too long ({wait_secs}s, {url})")]
+    Waitlisted { url: String, wait_secs: u64 },
+    /// Rate limiting or bot protection (HTTP 403 or 429).
+    #[error("The mirror refused the download (HTTP {status}, {url})")]
+    Blocked { status: u16, url: String },
+    #[error("The mirror no longer has the file ({0})")]
+    NotFound(String),
+    #[error("HTTP error: {status} ({url})")]
+    Http { status: u16, url: String },
+    #[error("No space left on the disk holding {0}")]
+    DiskFull(PathBuf),
+    #[error("{0} is already being downloaded")]
+    InProgress(String),
+}
+
+impl DownloadError {
+    /// The error for a non-success response to `url`.
+    fn from_status(status: u16, url: &str) -> Self {
+        match status {
+            403 | 429 => Self::Blocked { status, url: url.to_string() },
+            404 | 410 => Self::NotFound(url.to_string()),
+            _ => Self::Http { status, url: url.to_string() },
+        }
+    }
+
+    /// Whether another mirror may succeed where this one failed. A full disk
+    /// or a download already writing the same file fails every mirror alike.
+    pub fn is_mirror_fault(&self) -> bool {
+        !matches!(self, Self::DiskFull(_) | Self::InProgress(_))
+    }
+}
+
+/// Files below this are almost always error or captcha pages.
+const SUSPICIOUS_SIZE: u64 = 20 * 1024;
+
+/// What a mirror reports about a file before it is downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RemoteSize {
+    pub bytes: Option<u64>,
+    /// The server answered with a web page rather than a file.
+    pub is_html: bool,
+}
+
+impl RemoteSize {
+    /// Whether the link likely serves an error page instead of the book:
+    /// HTML, tiny, or far smaller than the listing's size.
+    pub fn looks_wrong(&self, listed: Option<u64>) -> bool {
+        if self.is_html {
+            return true;
+        }
+        match self.bytes {
+            Some(bytes) => bytes < SUSPICIOUS_SIZE || listed.is_some_and(|listed| bytes < listed / 4),
+            None => false,
+        }
+    }
+}
+
+/// What a pre-flight check of a link found.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkHealth {
+    /// The status after redirects; None when the mirror didn't answer.
+    pub status: Option<u16>,
+    pub size: RemoteSize,
+    /// Time until the mirror's first reply.
+    pub latency: Duration,
+}
+
+impl LinkHealth {
+    /// Unreachable, or answering with an error status.
+    pub fn is_dead(&self) -> bool {
+        !self.status.is_some_and(|status| (200..400).contains(&status))
+    }
+}
+
+impl std::fmt::Display for LinkHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            None => return f.write_str("no answer"),
+            Some(status) if self.is_dead() => return write!(f, "HTTP {}", status),
+            Some(_) => {}
+        }
+        match (self.size.bytes, self.size.is_html) {
+            (_, true) => f.write_str("web page")?,
+            (Some(bytes), false) => f.write_str(&locale::current().size(bytes))?,
+            (None, false) => f.write_str("size unknown")?,
+        }
+        write!(f, ", {} ms", self.latency.as_millis())
+    }
+}
+
+/// Puts checked links in the order they are offered: as they came, but
+/// with dead ones last, or left out when hiding. Nothing is hidden when
+/// every link is dead, so the list never ends up empty.
+pub fn arrange_links<T>(links: Vec<(T, LinkHealth)>, mode: LinkCheck) -> Vec<(T, LinkHealth)> {
+    if mode == LinkCheck::Off {
+        return links;
+    }
+    let (live, dead): (Vec<_>, Vec<_>) = links.into_iter().partition(|(_, health)| !health.is_dead());
+    if mode == LinkCheck::Hide && !live.is_empty() {
+        return live;
+    }
+    live.into_iter().chain(dead).collect()
+}
+
+/// A progress update emitted by a running download.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadProgress {
+    pub id: usize,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    pub finished: bool,
+    /// Seconds left in a mirror's slow-download queue; no bytes have
+    /// arrived yet.
+    pub waiting: Option<u64>,
+}
+
+/// What a mirror's slow-download page asks of the client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Waitlist {
+    /// Ask again after this many seconds.
+    Wait(u64),
+    /// The countdown is over; the page links the file.
+    Ready(String),
+}
+
+impl Waitlist {
+    /// Reads a slow-download page: the countdown while queued, or the
+    /// absolute file link once it's our turn. None for any other page.
+    pub fn parse(body: &str, page_url: &str) -> Option<Self> {
+        let document = scraper::Html::parse_document(body);
+        let countdown = scraper::Selector::parse(".js-partner-countdown").ok()?;
+        let seconds = document
+            .select(&countdown)
+            .next()
+            .and_then(|span| span.text().collect::<String>().trim().parse().ok())
+            .or_else(|| {
+                let text = document.root_element().text().collect::<Vec<_>>().join(" ");
+                let re = regex::Regex::new(r"(?i)\bwait\s+(\d{1,5})\s+seconds?\b").ok()?;
+                re.captures(&text)?[1].parse().ok()
+            });
+        if let Some(seconds) = seconds {
+            return Some(Self::Wait(seconds));
+        }
+
+        let anchors = scraper::Selector::parse("a[href]").ok()?;
+        let href = document
+            .select(&anchors)
+            .find(|a| a.text().collect::<String>().to_lowercase().contains("download now"))?
+            .value()
+            .attr("href")?;
+        let url = Url::parse(page_url).ok()?.join(href).ok()?;
+        matches!(url.scheme(), "http" | "https").then(|| Self::Ready(url.to_string()))
+    }
+}
+
+/// Result of one request for a file.
+enum Fetched {
+    File(PathBuf),
+    Waitlist(Waitlist),
+}
+
+/// Sends progress events for one download and always reports it as finished
+/// when dropped, so listeners don't keep failed transfers around.
+struct ProgressReporter {
+    id: usize,
+    tx: mpsc::UnboundedSender<DownloadProgress>,
+    downloaded: u64,
+    total: Option<u64>,
+    last_sent: Option<Instant>,
+}
+
+impl ProgressReporter {
+    fn update(&mut self, downloaded: u64, total: Option<u64>) {
+        self.downloaded = downloaded;
+        self.total = total;
+
+        let due = self.last_sent.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL);
+        if due {
+            self.last_sent = Some(Instant::now());
+            self.send(false);
+        }
+    }
+
+    fn send(&self, finished: bool) {
+        let _ = self.tx.send(DownloadProgress {
+            id: self.id,
+            downloaded: self.downloaded,
+            total: self.total,
+            finished,
+            waiting: None,
+        });
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.send(true);
+    }
+}
+
+#[derive(Debug)]
+struct Transfer {
+    downloaded: u64,
+    total: Option<u64>,
+    samples: VecDeque<(Instant, u64)>,
+    /// Bytes per second over each `SAMPLE_INTERVAL`, oldest first.
+    history: VecDeque<u64>,
+    /// When the last history sample was taken, and the byte count then.
+    last_sample: (Instant, u64),
+    /// Moving average of `history`; None until the first sample.
+    smoothed: Option<f64>,
+    /// Seconds left in a slow-download queue.
+    waiting: Option<u64>,
+}
+
+impl Transfer {
+    fn speed(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((t0, b0)), Some((t1, b1))) if t1 > t0 => {
+                (b1 - b0) as f64 / t1.duration_since(*t0).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The smoothed speed once sampled, the windowed speed before that.
+    fn steady_speed(&self) -> f64 {
+        self.smoothed.unwrap_or_else(|| self.speed())
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.downloaded);
+        let speed = self.steady_speed();
+        // A speed decaying towards zero gives an ETA too large to represent
+        (speed > 0.0).then(|| Duration::try_from_secs_f64(remaining as f64 / speed).ok()).flatten()
+    }
+
+    fn stalled(&self) -> bool {
+        self.history.len() >= STALL_SAMPLES && self.history.iter().rev().take(STALL_SAMPLES).all(|&s| s == 0)
+    }
+
+    fn sample(&mut self, now: Instant) {
+        let (then, bytes) = self.last_sample;
+        let elapsed = now.duration_since(then);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let rate = self.downloaded.saturating_sub(bytes) as f64 / elapsed.as_secs_f64();
+        self.history.push_back(rate as u64);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => SMOOTHING * rate + (1.0 - SMOOTHING) * smoothed,
+            None => rate,
+        });
+        self.last_sample = (now, self.downloaded);
+    }
+}
+
+/// One active download as shown on the download screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferView {
+    pub id: usize,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// Bytes per second, one sample per second, oldest first.
+    pub history: Vec<u64>,
+    /// Smoothed bytes per second.
+    pub speed: f64,
+    pub eta: Option<Duration>,
+    /// No bytes arrived for the last few seconds.
+    pub stalled: bool,
+    /// Seconds left in a slow-download queue.
+    pub waiting: Option<u64>,
+}
+
+/// Aggregates `DownloadProgress` events from all active downloads.
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    active: HashMap<usize, Transfer>,
+}
+
+impl TransferStats {
+    pub fn update(&mut self, progress: DownloadProgress) {
+        self.update_at(progress, Instant::now());
+    }
+
+    fn update_at(&mut self, progress: DownloadProgress, now: Instant) {
+        if progress.finished {
+            self.active.remove(&progress.id);
+            return;
+        }
+
+        let transfer = self.active.entry(progress.id).or_insert_with(|| Transfer {
+            downloaded: progress.downloaded,
+            total: None,
+            samples: VecDeque::new(),
+            history: VecDeque::new(),
+            last_sample: (now, progress.downloaded),
+            smoothed: None,
+            waiting: None,
+        });
+        transfer.waiting = progress.waiting;
+        transfer.downloaded = progress.downloaded;
+        transfer.total = progress.total;
+        transfer.samples.push_back((now, progress.downloaded));
+
+        while let Some((t, _)) = transfer.samples.front() {
+            if now.duration_since(*t) > SPEED_WINDOW && transfer.samples.len() > 2 {
+                transfer.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Takes a throughput sample from every download whose last one is at
+    /// least `SAMPLE_INTERVAL` old. Called on every UI tick, so a mirror
+    /// that stops sending shows up as a run of empty samples.
+    pub fn tick(&mut self) {
+        self.tick_at(Instant::now());
+    }
+
+    fn tick_at(&mut self, now: Instant) {
+        for transfer in self.active.values_mut() {
+            transfer.sample(now);
+        }
+    }
+
+    /// The active downloads in the order they started.
+    pub fn transfers(&self) -> Vec<TransferView> {
+        let mut views: Vec<TransferView> = self.active.iter()
+            .map(|(&id, t)| TransferView {
+                id,
+                downloaded: t.downloaded,
+                total: t.total,
+                history: t.history.iter().copied().collect(),
+                speed: t.steady_speed(),
+                eta: t.eta(),
+                stalled: t.waiting.is_none() && t.stalled(),
+                waiting: t.waiting,
+            })
+            .collect();
+        views.sort_by_key(|view| view.id);
+        views
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Combined speed of all active downloads in bytes per second.
+    pub fn speed(&self) -> f64 {
+        self.active.values().map(Transfer::speed).sum()
+    }
+
+    /// Bytes left across all downloads, or `None` if any size is unknown.
+    pub fn remaining(&self) -> Option<u64> {
+        self.active
+            .values()
+            .map(|t| t.total.map(|total| total.saturating_sub(t.downloaded)))
+            .sum()
+    }
+
+    /// Time left for all downloads at their smoothed speeds.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.remaining()?;
+        let speed: f64 = self.active.values().map(Transfer::steady_speed).sum();
+        if speed <= 0.0 {
+            return None;
+        }
+        Duration::try_from_secs_f64(remaining as f64 / speed).ok()
+    }
+}
+
 pub struct Downloader {
     client: reqwest::Client,
     download_path: PathBuf,
+    read_timeout: Duration,
+    user_agents: UserAgents,
+    polite: bool,
+    backend: DownloaderBackend,
+    aria2c_path: PathBuf,
+    proxy: Option<String>,
+    ca_cert: Option<PathBuf>,
+    insecure: bool,
+    retry: RetryPolicy,
+    waitlist_max: u64,
+    link_checks: usize,
+    /// The database download outcomes are recorded in, if any.
+    history: Option<PathBuf>,
+    progress: Option<(usize, mpsc::UnboundedSender<DownloadProgress>)>,
+    /// `.part` files whose transfer broke off, by the URL they came from,
+    /// so a retry asks only for the rest.
+    interrupted: Mutex<HashMap<String, Interrupted>>,
+}
+
+/// A `.part` file a broken-off transfer left behind and its length.
+#[derive(Debug, Clone, PartialEq)]
+struct Interrupted {
+    partial: PathBuf,
+    bytes: u64,
 }
 
 impl Downloader {
-    pub fn new(download_path: PathBuf) -> Result<Self> {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(300))
+    pub fn new(download_path: PathBuf, config: &Config) -> Result<Self> {
+        // Bodies are decoded transparently; `content_length()` is then None
+        // because the header only describes the encoded size.
+        let timeouts = config.download_timeouts();
+        let client = http::client_builder(config, timeouts)?
+            .redirect(Self::redirect_policy(config.max_redirects))
             .build()
             .context("Failed to create HTTP client")?;
         
-        Ok(Self { client, download_path })
+        Ok(Self {
+            client,
+            download_path,
+            read_timeout: timeouts.read,
+            user_agents: UserAgents::from_config(config),
+            polite: config.polite,
+            backend: config.downloader_backend,
+            aria2c_path: config.aria2c_path.clone().unwrap_or_else(|| PathBuf::from("aria2c")),
+            proxy: config.proxy_url().map(str::to_string),
+            ca_cert: config.ca_cert.clone(),
+            insecure: config.insecure,
+            retry: config.retry_policy(),
+            waitlist_max: config.waitlist_max_secs,
+            link_checks: config.link_check_concurrency.max(1),
+            history: None,
+            progress: None,
+            interrupted: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Records whether each mirror tried by `download_verified` worked in
+    /// the link history at `db`, for ranking links later.
+    pub fn with_history(mut self, db: PathBuf) -> Self {
+        self.history = Some(db);
+        self
+    }
+
+    /// Reports progress as `DownloadProgress` events tagged with `id` instead
+    /// of drawing a terminal progress bar.
+    pub fn with_progress(mut self, id: usize, tx: mpsc::UnboundedSender<DownloadProgress>) -> Self {
+        self.progress = Some((id, tx));
+        self
     }
     
-    pub async fn download(&self, url: &str, filename: Option<&str>) -> Result<PathBuf> {
+    /// Asks the server for the file's status and size without downloading
+    /// it. Mirrors that reject HEAD are asked for the first byte instead.
+    /// An unreachable mirror has no status.
+    pub async fn check_link(&self, url: &str) -> LinkHealth {
+        self.probe(url).await.unwrap_or_default()
+    }
+
+    async fn probe(&self, url: &str) -> Result<LinkHealth> {
+        let _turn = if self.polite { http::polite_turn(url).await } else { None };
+        let started = Instant::now();
+        let response = self.client
+            .head(url)
+            .headers(self.user_agents.headers())
+            .send()
+            .await
+            .context("Failed to reach the mirror")?;
+        let latency = started.elapsed();
+        if response.status().is_success() {
+            let size = Self::remote_size(response.headers());
+            if size.bytes.is_some() || size.is_html {
+                return Ok(LinkHealth { status: Some(response.status().as_u16()), size, latency });
+            }
+        }
+
         let response = self.client
             .get(url)
+            .headers(self.user_agents.headers())
+            .header(reqwest::header::RANGE, "bytes=0-0")
             .send()
             .await
-            .context("Failed to start download")?;
-        
-        let total_size = response
-            .content_length()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get content length"))?;
+            .context("Failed to reach the mirror")?;
+        // An error page's length says nothing about the file
+        let size = if response.status().is_success() { Self::remote_size(response.headers()) } else { RemoteSize::default() };
+        // Dropping the response without reading it aborts a full-body reply
+        Ok(LinkHealth { status: Some(response.status().as_u16()), size, latency })
+    }
+
+    /// Checks every link at once, `link_check_concurrency` at a time, so a
+    /// list of mirrors is checked in about one round trip. Results come as
+    /// each mirror answers, paired with its index in `urls`.
+    pub fn check_links<'a>(&'a self, urls: &'a [String]) -> impl futures::Stream<Item = (usize, LinkHealth)> + 'a {
+        let permits = Arc::new(Semaphore::new(self.link_checks));
+        urls.iter()
+            .enumerate()
+            .map(move |(i, url)| {
+                let permits = permits.clone();
+                async move {
+                    let _permit = permits.acquire_owned().await;
+                    (i, self.check_link(url).await)
+                }
+            })
+            .collect::<futures::stream::FuturesUnordered<_>>()
+    }
+
+    /// Checks every link at once, like `check_links`, and returns the
+    /// results in the order of `urls`.
+    pub async fn check_all(&self, urls: &[String]) -> Vec<LinkHealth> {
+        let mut health = vec![LinkHealth::default(); urls.len()];
+        for (i, checked) in self.check_links(urls).collect::<Vec<_>>().await {
+            health[i] = checked;
+        }
+        health
+    }
+
+    fn remote_size(headers: &reqwest::header::HeaderMap) -> RemoteSize {
+        let header = |name| headers.get(name).and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok());
+        // "bytes 0-0/12345" on a ranged reply, otherwise the plain length
+        let bytes = header(reqwest::header::CONTENT_RANGE)
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, total)| total.trim().parse().ok())
+            .or_else(|| header(reqwest::header::CONTENT_LENGTH).and_then(|len| len.trim().parse().ok()));
+        let is_html = header(reqwest::header::CONTENT_TYPE)
+            .is_some_and(|t| t.to_lowercase().starts_with("text/html"));
+        RemoteSize { bytes, is_html }
+    }
+
+    /// Where a ranged reply starts: 100 for "bytes 100-199/200".
+    fn range_start(headers: &HeaderMap) -> Option<u64> {
+        let range = headers.get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?;
+        range.trim().strip_prefix("bytes ")?.split_once('-')?.0.trim().parse().ok()
+    }
+
+    fn redirect_policy(max_redirects: usize) -> redirect::Policy {
+        redirect::Policy::custom(move |attempt| {
+            match Self::check_redirect(attempt.url(), attempt.previous(), max_redirects) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        })
+    }
+
+    /// `previous` holds every URL requested so far, starting with the original.
+    fn check_redirect(url: &Url, previous: &[Url], max_redirects: usize) -> std::result::Result<(), DownloadError> {
+        if previous.contains(url) {
+            return Err(DownloadError::RedirectLoop(url.to_string()));
+        }
+        if LIMIT_URL_MARKERS.iter().any(|m| url.as_str().to_lowercase().contains(m)) {
+            return Err(DownloadError::LimitReached(url.to_string()));
+        }
+        if previous.len() > max_redirects {
+            return Err(DownloadError::TooManyRedirects(max_redirects));
+        }
+        Ok(())
+    }
+
+    /// Digs the typed error raised by the redirect policy out of reqwest's error.
+    fn redirect_error(err: &reqwest::Error) -> Option<DownloadError> {
+        let mut source = std::error::Error::source(err);
+        while let Some(e) = source {
+            if let Some(download_err) = e.downcast_ref::<DownloadError>() {
+                return Some(download_err.clone());
+            }
+            source = e.source();
+        }
+        None
+    }
+
+    fn is_limit_page(body: &str) -> bool {
+        let body = body.to_lowercase();
+        LIMIT_PAGE_MARKERS.iter().any(|m| body.contains(m))
+    }
+
+    /// Downloads `url`, first sitting out a slow-download queue if the
+    /// mirror puts us in one.
+    pub async fn download(&self, url: &str, filename: Option<&str>) -> Result<PathBuf> {
+        let mut url = url.to_string();
+        let mut waited = 0;
+        // A ready page links the file; a second ready page would be a loop
+        let mut followed = false;
+        loop {
+            match self.fetch(&url, filename).await? {
+                Fetched::File(path) => return Ok(path),
+                Fetched::Waitlist(Waitlist::Ready(file_url)) if !followed => {
+                    followed = true;
+                    url = file_url;
+                }
+                Fetched::Waitlist(Waitlist::Ready(_)) => return Err(DownloadError::HtmlPage(url).into()),
+                Fetched::Waitlist(Waitlist::Wait(seconds)) => {
+                    // Counted as at least a second so a page stuck at zero
+                    // still runs into the limit
+                    waited += seconds.max(1);
+                    if waited > self.waitlist_max {
+                        return Err(DownloadError::Waitlisted { url, wait_secs: seconds }.into());
+                    }
+                    self.wait_in_line(seconds).await;
+                }
+            }
+        }
+    }
+
+    /// Counts down a slow-download queue, on a progress bar or as
+    /// `DownloadProgress::waiting` events.
+    async fn wait_in_line(&self, seconds: u64) {
+        let pb = if self.progress.is_some() { ProgressBar::hidden() } else { ProgressBar::new(seconds) };
+        let mut style = ProgressStyle::default_bar();
+        if term::ascii() {
+            style = style.tick_chars("|/-\\ ");
+        }
+        pb.set_style(style.template("{spinner} Waiting in line [{bar:40.yellow/blue}] {msg}").unwrap().progress_chars("=>-"));
+
+        let send = |waiting: Option<u64>| {
+            if let Some((id, tx)) = &self.progress {
+                let _ = tx.send(DownloadProgress { id: *id, downloaded: 0, total: None, finished: waiting.is_none(), waiting });
+            }
+        };
+        for left in (1..=seconds).rev() {
+            pb.set_message(format!("{}s left", left));
+            send(Some(left));
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            pb.inc(1);
+        }
+        pb.finish_and_clear();
+        send(None);
+    }
+
+    async fn fetch(&self, url: &str, filename: Option<&str>) -> Result<Fetched> {
+        // Polite mode keeps the host's connection slot for the whole transfer
+        let _turn = if self.polite { http::polite_turn(url).await } else { None };
+        // aria2c fetches the file itself, with the same headers, so only the
+        // response's headers are asked for here
+        let aria2c = self.backend == DownloaderBackend::Aria2c;
+        let headers = self.user_agents.headers();
+        let resume = if aria2c { None } else { self.interrupted.lock().unwrap().get(url).cloned() };
+        let mut request = if aria2c { self.client.head(url) } else { self.client.get(url) }.headers(headers.clone());
+        if let Some(ref part) = resume {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", part.bytes));
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => match Self::redirect_error(&e) {
+                Some(download_err) => return Err(download_err.into()),
+                None => return Err(anyhow::Error::new(e).context("Failed to start download")),
+            },
+        };
+
+        let final_url = response.url().to_string();
+        let head_refused = aria2c && matches!(response.status().as_u16(), 405 | 501);
+        if !response.status().is_success() && !head_refused {
+            return Err(DownloadError::from_status(response.status().as_u16(), &final_url).into());
+        }
+        // A refused HEAD's headers describe the refusal, not the file
+        let file_headers = if head_refused { HeaderMap::new() } else { response.headers().clone() };
+        let is_html = file_headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.to_lowercase().starts_with("text/html"));
+        if is_html {
+            let body = if aria2c {
+                // A page is small; its body says whether it is a waitlist
+                match self.client.get(&final_url).headers(headers.clone()).send().await {
+                    Ok(page) => page.text().await.unwrap_or_default(),
+                    Err(_) => String::new(),
+                }
+            } else {
+                response.text().await.unwrap_or_default()
+            };
+            if Self::is_limit_page(&body) {
+                return Err(DownloadError::LimitReached(final_url).into());
+            }
+            if let Some(waitlist) = Waitlist::parse(&body, &final_url) {
+                return Ok(Fetched::Waitlist(waitlist));
+            }
+            return Err(DownloadError::HtmlPage(final_url).into());
+        }
         
-        let filename = self.determine_filename(url, filename, &response)?;
+        let filename = Self::determine_filename(url, filename, &file_headers);
         let filepath = self.download_path.join(&filename);
+        // The .part left by this URL's last attempt is carried on if the
+        // server sends the rest; any other one belongs to another download
+        let partial = self.download_path.join(format!("{}.part", filename));
+        let resume = resume.filter(|part| part.partial == partial);
+        let offset = match resume {
+            Some(ref part) if response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+                && Self::range_start(&file_headers) == Some(part.bytes) => part.bytes,
+            _ => 0,
+        };
+        if resume.is_none() && self.part_in_use(&filename) {
+            return Err(DownloadError::InProgress(filename).into());
+        }
+        if offset == 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            self.discard_interrupted().await;
+            anyhow::bail!("{} sent part of the file from an unexpected offset", final_url);
+        }
+        let total_size = response.content_length().map(|len| offset + len);
         
         tokio::fs::create_dir_all(&self.download_path)
             .await
             .context("Failed to create download directory")?;
+
+        if aria2c {
+            // The link is already resolved and named; aria2c only moves the
+            // bytes. A server that refuses HEAD leaves the redirects to it
+            self.download_with_aria2c(&final_url, &filename, &headers).await?;
+            return Ok(Fetched::File(filepath));
+        }
         
-        let pb = ProgressBar::new(total_size);
+        let mut reporter = self.progress.as_ref().map(|(id, tx)| ProgressReporter {
+            id: *id,
+            tx: tx.clone(),
+            downloaded: offset,
+            total: total_size,
+            last_sent: None,
+        });
+
+        let pb = match (reporter.is_some(), total_size) {
+            (true, _) => ProgressBar::hidden(),
+            (false, Some(total)) => ProgressBar::new(total),
+            (false, None) => ProgressBar::new_spinner(),
+        };
+        let template = if total_size.is_some() {
+            "{spinner} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}"
+        } else {
+            "{spinner} [{elapsed_precise}] {bytes} ({bytes_per_sec}) {msg}"
+        };
+        let mut style = ProgressStyle::default_bar();
+        if term::ascii() {
+            style = style.tick_chars("|/-\\ ");
+        }
         pb.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "{spinner} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}"
-                )
+            style
+                .template(template)
                 .unwrap()
-                .progress_chars("=>-"),
+                .progress_chars("=>-")
+                .with_key("bytes", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    let _ = w.write_str(&locale::current().size(state.pos()));
+                })
+                .with_key("total_bytes", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    let _ = w.write_str(&locale::current().size(state.len().unwrap_or_default()));
+                })
+                .with_key("bytes_per_sec", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    let _ = write!(w, "{}/s", locale::current().size(state.per_sec() as u64));
+                }),
         );
         pb.set_message(format!("Downloading {}", filename));
+        pb.set_position(offset);
+        if final_url != url {
+            pb.println(format!("Resolved to {}", final_url));
+        }
+        if offset > 0 {
+            pb.println(format!("Resuming at {}", locale::current().size(offset)));
+        }
         
-        let mut file = File::create(&filepath)
-            .await
-            .context("Failed to create file")?;
+        // The file only gets its name once complete; until then the .part
+        // marks it as in progress
+        let opened = if offset > 0 {
+            tokio::fs::OpenOptions::new().append(true).open(&partial).await
+        } else {
+            File::create(&partial).await
+        };
+        let mut file = opened.map_err(|e| self.write_error(e, "Failed to create file"))?;
         
         let mut stream = response.bytes_stream();
-        let mut downloaded = 0;
+        let mut written = offset;
+        let mut downloaded = offset;
         
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.context("Failed to download chunk")?;
-            file.write_all(&chunk).await.context("Failed to write chunk")?;
-            
-            downloaded = std::cmp::min(downloaded + chunk.len() as u64, total_size);
-            pb.set_position(downloaded);
+        let transfer = async {
+            loop {
+                let next = tokio::time::timeout(self.read_timeout, stream.next())
+                    .await
+                    .map_err(|_| Stalled(self.read_timeout.as_secs()))?;
+                let Some(chunk) = next else {
+                    break;
+                };
+                let chunk = chunk.context("Failed to download chunk")?;
+                file.write_all(&chunk).await.map_err(|e| self.write_error(e, "Failed to write chunk"))?;
+                
+                written += chunk.len() as u64;
+                downloaded += chunk.len() as u64;
+                if let Some(total) = total_size {
+                    downloaded = downloaded.min(total);
+                }
+                pb.set_position(downloaded);
+                if let Some(reporter) = reporter.as_mut() {
+                    reporter.update(downloaded, total_size);
+                }
+            }
+            file.flush().await.map_err(|e| self.write_error(e, "Failed to write chunk"))
+        };
+        let transferred = transfer.await;
+        drop(file);
+        self.interrupted.lock().unwrap().remove(url);
+        if let Err(e) = transferred {
+            // A connection that broke off or stalled is worth resuming; a
+            // failed write may have left the file damaged
+            if written > 0 && retry::classify(&e).is_some() {
+                self.interrupted.lock().unwrap().insert(url.to_string(), Interrupted { partial, bytes: written });
+            } else {
+                let _ = tokio::fs::remove_file(&partial).await;
+            }
+            return Err(e);
         }
+        tokio::fs::rename(&partial, &filepath)
+            .await
+            .context("Failed to move the finished download into place")?;
         
         pb.finish_with_message(format!("Downloaded {}", filename));
-        Ok(filepath)
+        Ok(Fetched::File(filepath))
     }
     
+    /// A full disk becomes `DownloadError::DiskFull`; other write errors keep
+    /// the I/O error with `context`.
+    fn write_error(&self, error: std::io::Error, context: &'static str) -> anyhow::Error {
+        if error.kind() == std::io::ErrorKind::StorageFull {
+            DownloadError::DiskFull(self.download_path.clone()).into()
+        } else {
+            anyhow::Error::new(error).context(context)
+        }
+    }
+
+    async fn download_with_aria2c(&self, url: &str, filename: &str, headers: &HeaderMap) -> Result<()> {
+        // Keep aria2c's console output out of the TUI
+        let quiet = self.progress.is_some();
+        let status = tokio::process::Command::new(&self.aria2c_path)
+            .args(self.aria2c_args(url, filename, quiet, headers)?)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {} (is aria2c installed?)", self.aria2c_path.display()))?;
+
+        if !status.success() {
+            anyhow::bail!("aria2c exited with {}", status);
+        }
+        Ok(())
+    }
+
+    fn aria2c_args(&self, url: &str, filename: &str, quiet: bool, headers: &HeaderMap) -> Result<Vec<String>> {
+        let connections = if self.polite { 1 } else { 4 };
+        let mut args = vec![
+            format!("--dir={}", self.download_path.display()),
+            format!("--out={}", filename),
+            "--continue=true".to_string(),
+            "--allow-overwrite=true".to_string(),
+            "--auto-file-renaming=false".to_string(),
+            format!("--max-connection-per-server={}", connections),
+            format!("--split={}", connections),
+            "--summary-interval=0".to_string(),
+        ];
+        if quiet {
+            args.push("--quiet=true".to_string());
+        }
+        if let Some(proxy) = self.proxy.as_deref() {
+            if !proxy.trim().starts_with("http") {
+                anyhow::bail!("aria2c only supports HTTP proxies; set downloader_backend to \"builtin\" to use a SOCKS proxy");
+            }
+            args.push(format!("--all-proxy={}", proxy.trim()));
+        }
+        // The same TLS trust the built-in client uses
+        if let Some(ca_cert) = &self.ca_cert {
+            args.push(format!("--ca-certificate={}", ca_cert.display()));
+        }
+        if self.insecure {
+            args.push("--check-certificate=false".to_string());
+        }
+        // The same browser headers the link was resolved with
+        for (name, value) in headers {
+            if let Ok(value) = value.to_str() {
+                args.push(format!("--header={}: {}", name, value));
+            }
+        }
+        args.push(url.to_string());
+        Ok(args)
+    }
+
+    /// Picks a filename from, in order: the caller, the URL, and the
+    /// Content-Disposition header. Names lacking an extension borrow one from
+    /// the other sources or, failing that, from the Content-Type.
     fn determine_filename(
-        &self,
         url: &str,
         provided_name: Option<&str>,
-        response: &reqwest::Response,
-    ) -> Result<String> {
+        headers: &reqwest::header::HeaderMap,
+    ) -> String {
+        let url_name = Self::extract_filename_from_url(url);
+        let disposition_name = headers
+            .get(reqwest::header::CONTENT_DISPOSITION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse_content_disposition);
+        let candidates = [url_name, disposition_name];
+
+        let inferred_extension = || {
+            candidates.iter()
+                .flatten()
+                .find_map(|name| Self::file_extension(name))
+                .or_else(|| {
+                    headers
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(Self::extension_for_content_type)
+                })
+        };
+
         if let Some(name) = provided_name {
-            return Ok(name.to_string());
+            return match (Self::file_extension(name), inferred_extension()) {
+                (None, Some(ext)) => format!("{}.{}", name, ext),
+                _ => name.to_string(),
+            };
         }
-        
-        if let Some(filename) = Self::extract_filename_from_url(url) {
-            return Ok(filename);
-        }
-        
-        if let Some(disposition) = response.headers().get("content-disposition") {
-            if let Ok(disposition_str) = disposition.to_str() {
-                if let Some(name) = Self::parse_content_disposition(disposition_str) {
-                    return Ok(name);
-                }
-            }
+
+        if let Some(name) = candidates.iter().flatten().find(|name| Self::file_extension(name).is_some()) {
+            return name.clone();
         }
-        
-        Ok(format!("downloaded_file_{}.tmp", 
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        ))
+
+        let base = candidates.iter().flatten().next().cloned().unwrap_or_else(|| {
+            format!(
+                "downloaded_file_{}",
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            )
+        });
+        format!("{}.{}", base, inferred_extension().unwrap_or("tmp"))
+    }
+
+    /// A short alphanumeric extension, so "Dr. Who - Smith" doesn't count as one.
+    fn file_extension(name: &str) -> Option<&str> {
+        let (_, ext) = name.rsplit_once('.')?;
+        (!ext.is_empty() && ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric())).then_some(ext)
+    }
+
+    fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+        let mime = content_type.split(';').next()?.trim().to_lowercase();
+        CONTENT_TYPE_EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == mime)
+            .map(|(_, ext)| *ext)
     }
     
+    /// The decoded last path segment of `url`, ignoring the query string,
+    /// fragment, and `;param` suffixes such as session ids.
     fn extract_filename_from_url(url: &str) -> Option<String> {
-        url.split('/').last()
-            .filter(|s| !s.is_empty() && !s.contains('?'))
-            .map(|s| {
-                urlencoding::decode(s)
-                    .unwrap_or_default()
-                    .to_string()
-            })
+        let parsed = Url::parse(url).ok()?;
+        let segment = parsed.path_segments()?.next_back()?;
+        let segment = segment.split(';').next().unwrap_or(segment);
+
+        let decoded = urlencoding::decode(segment).ok()?;
+        Self::sanitize_filename(&decoded)
     }
     
+    /// Parses a Content-Disposition header (RFC 6266), preferring the
+    /// RFC 5987 `filename*` parameter over plain `filename`.
     fn parse_content_disposition(disposition: &str) -> Option<String> {
-        for part in disposition.split(';') {
-            let part = part.trim();
-            if part.starts_with("filename=") {
-                let filename = part.strip_prefix("filename=")?;
-                let filename = filename.trim_matches('"');
-                return Some(urlencoding::decode(filename).unwrap_or_default().to_string());
+        let mut filename = None;
+        let mut extended = None;
+
+        for param in Self::split_header_params(disposition) {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+            match key.trim().to_lowercase().as_str() {
+                "filename" => {
+                    let value = Self::unquote(value.trim());
+                    filename = Some(urlencoding::decode(&value).map(|v| v.into_owned()).unwrap_or(value));
+                }
+                "filename*" => extended = Self::decode_ext_value(value.trim()),
+                _ => {}
+            }
+        }
+
+        extended
+            .and_then(|name| Self::sanitize_filename(&name))
+            .or_else(|| filename.and_then(|name| Self::sanitize_filename(&name)))
+    }
+
+    /// Splits header parameters on `;`, ignoring separators inside quotes.
+    fn split_header_params(header: &str) -> Vec<String> {
+        let mut params = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        let mut escaped = false;
+
+        for c in header.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                ';' if !in_quotes => {
+                    params.push(std::mem::take(&mut current).trim().to_string());
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+        params.push(current.trim().to_string());
+
+        params
+    }
+
+    fn unquote(value: &str) -> String {
+        let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+            return value.to_string();
+        };
+
+        let mut result = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                if let Some(next) = chars.next() {
+                    result.push(next);
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    /// Decodes an RFC 5987 `charset'language'percent-encoded` value.
+    fn decode_ext_value(value: &str) -> Option<String> {
+        let mut parts = value.splitn(3, '\'');
+        let charset = parts.next()?.trim().to_lowercase();
+        let _language = parts.next()?;
+        let encoded = parts.next()?;
+
+        let bytes = urlencoding::decode_binary(encoded.as_bytes());
+        match charset.as_str() {
+            "utf-8" => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            // ISO-8859-1 bytes map directly onto the first 256 code points
+            "iso-8859-1" | "latin1" => Some(bytes.iter().map(|&b| b as char).collect()),
+            _ => None,
+        }
+    }
+
+    /// Drops any directory part and rejects names that end up empty.
+    fn sanitize_filename(name: &str) -> Option<String> {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name).trim();
+        if name.is_empty() || name == "." || name == ".." {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    }
+    
+    /// Tries each URL in turn, at most `max_attempts`, until one downloads
+    /// and passes `verify`; a non-mirror fault such as a full disk stops
+    /// early.
+    pub async fn download_verified(
+        &self,
+        urls: &[&str],
+        filename: Option<&str>,
+        expected_md5: Option<&str>,
+        max_attempts: u32,
+    ) -> Result<PathBuf> {
+        let mut last_error = None;
+
+        for url in urls.iter().take(max_attempts.max(1) as usize) {
+            // Any failure, from a refused connection to a page instead of the
+            // file, counts against this mirror once transient failures have
+            // been retried. Only a full disk would fail the others too
+            let downloaded = self.retry.run(|| self.download(url, filename)).await;
+            if downloaded.is_err() {
+                // Another mirror's file can't be trusted to continue this one
+                self.discard_interrupted().await;
             }
-            if part.starts_with("filename*=") {
-                let filename = part.strip_prefix("filename*=UTF-8''")?;
-                return Some(urlencoding::decode(filename).unwrap_or_default().to_string());
+            let path = match downloaded {
+                Ok(path) => path,
+                Err(e) if e.downcast_ref::<DownloadError>().is_some_and(|e| !e.is_mirror_fault()) => return Err(e),
+                Err(e) => {
+                    self.record_outcome(url, false);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match self.verify(&path, expected_md5).await {
+                Ok(()) => {
+                    self.record_outcome(url, true);
+                    return Ok(path);
+                }
+                Err(e) => {
+                    self.record_outcome(url, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No download link available")))
+    }
+
+    /// Deletes the `.part` files broken-off transfers left for resuming.
+    async fn discard_interrupted(&self) {
+        let parts: Vec<Interrupted> = self.interrupted.lock().unwrap().drain().map(|(_, part)| part).collect();
+        for part in parts {
+            let _ = tokio::fs::remove_file(&part.partial).await;
+        }
+    }
+
+    /// A history that can't be written only costs the ranking some data.
+    fn record_outcome(&self, url: &str, success: bool) {
+        if let Some(ref db) = self.history {
+            let _ = History::open(db).and_then(|history| history.record(url, success));
+        }
+    }
+
+    /// Checks a finished download against its expected MD5 (when known) and
+    /// the magic bytes for its extension. Files that fail are moved into the
+    /// quarantine folder next to a `.reason.txt` file and an error is returned.
+    pub async fn verify(&self, path: &Path, expected_md5: Option<&str>) -> Result<()> {
+        let Some(reason) = Self::validation_failure(path, expected_md5).await? else {
+            return Ok(());
+        };
+
+        let quarantined = self.quarantine(path, &reason).await?;
+        anyhow::bail!("{} (moved to {})", reason, quarantined.display())
+    }
+
+    async fn validation_failure(path: &Path, expected_md5: Option<&str>) -> Result<Option<String>> {
+        let mut file = File::open(path)
+            .await
+            .context("Failed to open downloaded file")?;
+
+        let mut hasher = Md5::new();
+        let mut header = Vec::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).await.context("Failed to read downloaded file")?;
+            if n == 0 {
+                break;
+            }
+            if header.len() < 128 {
+                header.extend_from_slice(&buf[..n.min(128 - header.len())]);
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if let Err(reason) = Self::check_magic_bytes(extension, &header) {
+            return Ok(Some(reason));
+        }
+
+        if let Some(expected) = expected_md5 {
+            let actual = format!("{:x}", hasher.finalize());
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Ok(Some(format!("Checksum mismatch: expected {}, got {}", expected, actual)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn check_magic_bytes(extension: &str, header: &[u8]) -> std::result::Result<(), String> {
+        if header.is_empty() {
+            return Err("Downloaded file is empty".to_string());
+        }
+
+        let extension = extension.to_lowercase();
+        let start = String::from_utf8_lossy(&header[..header.len().min(64)]).trim_start().to_lowercase();
+        if extension != "html" && (start.starts_with("<!doctype html") || start.starts_with("<html")) {
+            return Err("Downloaded file is an HTML page, not a book".to_string());
+        }
+
+        let valid = match extension.as_str() {
+            "pdf" => header.starts_with(b"%PDF"),
+            "epub" | "docx" | "cbz" | "zip" => header.starts_with(b"PK\x03\x04"),
+            "cbr" | "rar" => header.starts_with(b"Rar!\x1a\x07"),
+            "cb7" | "7z" => header.starts_with(b"7z\xbc\xaf\x27\x1c"),
+            "m4b" | "m4a" => header.get(4..8) == Some(b"ftyp".as_slice()),
+            // An ID3 tag, or straight into an MPEG frame sync
+            "mp3" => header.starts_with(b"ID3") || (header.len() > 1 && header[0] == 0xff && header[1] & 0xe0 == 0xe0),
+            "flac" => header.starts_with(b"fLaC"),
+            "mobi" | "azw" | "azw3" => header.get(60..68) == Some(b"BOOKMOBI".as_slice()),
+            "djvu" => header.starts_with(b"AT&TFORM"),
+            _ => true,
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("File contents do not match the .{} format", extension))
+        }
+    }
+
+    async fn quarantine(&self, path: &Path, reason: &str) -> Result<PathBuf> {
+        let quarantine_dir = self.download_path.join(QUARANTINE_DIR);
+        tokio::fs::create_dir_all(&quarantine_dir)
+            .await
+            .context("Failed to create quarantine directory")?;
+
+        let filename = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Invalid download path"))?;
+        let (target, reason_path) = Self::quarantine_target(&quarantine_dir, Path::new(filename));
+        tokio::fs::rename(path, &target)
+            .await
+            .context("Failed to move file to quarantine")?;
+
+        tokio::fs::write(reason_path, format!("{}\n", reason))
+            .await
+            .context("Failed to write quarantine reason")?;
+
+        Ok(target)
+    }
+
+    /// The first free name in the quarantine for `filename` and its reason
+    /// file: "book.pdf", then "book (2).pdf" and so on, so an earlier bad
+    /// download of the same book is kept.
+    fn quarantine_target(dir: &Path, filename: &Path) -> (PathBuf, PathBuf) {
+        let stem = filename.file_stem().unwrap_or(filename.as_os_str()).to_string_lossy();
+        let ext = filename.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+        let mut n = 1;
+        loop {
+            let target = match n {
+                1 => dir.join(filename),
+                n => dir.join(format!("{} ({}){}", stem, n, ext)),
+            };
+            let mut reason = target.clone().into_os_string();
+            reason.push(".reason.txt");
+            let reason = PathBuf::from(reason);
+            if !target.exists() && !reason.exists() {
+                return (target, reason);
             }
+            n += 1;
         }
-        None
     }
-    
+
+    /// Whether a `.part` or browser `.crdownload` file for `filename` is
+    /// still being written.
+    #[allow(dead_code)]
     pub fn is_download_in_progress(&self, filename: &str) -> bool {
         let temp_path = self.download_path.join(format!("{}.crdownload", filename));
         let partial_path = self.download_path.join(format!("{}.part", filename));
@@ -131,6 +1326,19 @@ impl Downloader {
         temp_path.exists() || partial_path.exists()
     }
     
+    /// Whether another download is still writing `filename`: its `.part`
+    /// or `.crdownload` file changed within the read timeout. A live
+    /// transfer quiet for longer gives up as stalled, so an older file was
+    /// left by an interrupted run and is written over.
+    fn part_in_use(&self, filename: &str) -> bool {
+        ["part", "crdownload"].iter().any(|suffix| {
+            std::fs::metadata(self.download_path.join(format!("{}.{}", filename, suffix)))
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| modified.elapsed().map_or(true, |age| age < self.read_timeout))
+        })
+    }
+
+    #[allow(dead_code)]
     pub async fn cleanup_partial_downloads(&self) -> Result<()> {
         let mut entries = tokio::fs::read_dir(&self.download_path).await?;
         
@@ -150,6 +1358,7 @@ impl Downloader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server;
     
     #[test]
     fn test_extract_filename_from_url() {
@@ -180,7 +1389,7 @@ mod tests {
         let temp_dir = std::env::temp_dir().join(format!("annadl_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
         tokio::fs::create_dir_all(&temp_dir).await.unwrap();
 
-        let downloader = Downloader::new(temp_dir.clone()).unwrap();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
 
         let filename = "test_file.pdf";
         let part_file = temp_dir.join(format!("{}.part", filename));
@@ -201,7 +1410,7 @@ mod tests {
         let temp_dir = std::env::temp_dir().join(format!("annadl_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
         tokio::fs::create_dir_all(&temp_dir).await.unwrap();
 
-        let downloader = Downloader::new(temp_dir.clone()).unwrap();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
 
         let filename = "test_file.epub";
         let crdownload_file = temp_dir.join(format!("{}.crdownload", filename));
@@ -221,10 +1430,29 @@ mod tests {
     fn test_extract_filename_from_url_with_query_params() {
         assert_eq!(
             Downloader::extract_filename_from_url("https://example.com/file.pdf?token=abc123"),
-            None // Query params should cause this to return None
+            Some("file.pdf".to_string())
+        );
+        assert_eq!(
+            Downloader::extract_filename_from_url("https://example.com/dl/My%20Book.epub?utm_source=x&utm_medium=y#page=2"),
+            Some("My Book.epub".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_filename_from_url_strips_path_params() {
+        assert_eq!(
+            Downloader::extract_filename_from_url("https://example.com/file.pdf;jsessionid=ABC123"),
+            Some("file.pdf".to_string())
         );
     }
 
+    #[test]
+    fn test_extract_filename_from_url_invalid() {
+        assert_eq!(Downloader::extract_filename_from_url("not a url"), None);
+        assert_eq!(Downloader::extract_filename_from_url("https://example.com"), None);
+        assert_eq!(Downloader::extract_filename_from_url("https://example.com/?file=book.pdf"), None);
+    }
+
     #[test]
     fn test_extract_filename_from_url_trailing_slash() {
         assert_eq!(
@@ -285,10 +1513,56 @@ mod tests {
     fn test_parse_content_disposition_both_formats() {
         // When both filename and filename* are present, filename* should take precedence
         let disposition = "attachment; filename=\"fallback.pdf\"; filename*=UTF-8''actual%20file.pdf";
-        let result = Downloader::parse_content_disposition(disposition);
-        // Current implementation returns first match, so it would return "fallback.pdf"
-        // But ideally it should prioritize filename*
-        assert!(result.is_some());
+        assert_eq!(
+            Downloader::parse_content_disposition(disposition),
+            Some("actual file.pdf".to_string())
+        );
+
+        let reversed = "attachment; filename*=UTF-8''actual%20file.pdf; filename=\"fallback.pdf\"";
+        assert_eq!(
+            Downloader::parse_content_disposition(reversed),
+            Some("actual file.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_content_disposition_charset_and_language() {
+        assert_eq!(
+            Downloader::parse_content_disposition("attachment; filename*=utf-8'en'%E2%82%AC%20rates.pdf"),
+            Some("€ rates.pdf".to_string())
+        );
+        assert_eq!(
+            Downloader::parse_content_disposition("attachment; filename*=iso-8859-1'de'M%FCller.epub"),
+            Some("Müller.epub".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_content_disposition_quoted_semicolon() {
+        assert_eq!(
+            Downloader::parse_content_disposition("attachment; filename=\"part one; part two.pdf\"; size=123"),
+            Some("part one; part two.pdf".to_string())
+        );
+        assert_eq!(
+            Downloader::parse_content_disposition(r#"attachment; filename="say \"hi\".txt""#),
+            Some("say \"hi\".txt".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_content_disposition_falls_back_on_bad_extended_value() {
+        assert_eq!(
+            Downloader::parse_content_disposition("attachment; filename*=UTF-8''; filename=\"plain.pdf\""),
+            Some("plain.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_content_disposition_strips_directories() {
+        assert_eq!(
+            Downloader::parse_content_disposition("attachment; filename=\"../../etc/book.pdf\""),
+            Some("book.pdf".to_string())
+        );
     }
 
     #[test]
@@ -301,9 +1575,9 @@ mod tests {
 
     #[test]
     fn test_parse_content_disposition_malformed() {
-        // Current implementation returns empty string for "filename="
-        let result = Downloader::parse_content_disposition("filename=");
-        assert_eq!(result, Some("".to_string()));
+        // An empty filename is never returned
+        assert_eq!(Downloader::parse_content_disposition("filename="), None);
+        assert_eq!(Downloader::parse_content_disposition("attachment; filename=\"\""), None);
     }
 
     #[tokio::test]
@@ -311,7 +1585,7 @@ mod tests {
         let temp_dir = std::env::temp_dir().join(format!("annadl_cleanup_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
         tokio::fs::create_dir_all(&temp_dir).await.unwrap();
 
-        let downloader = Downloader::new(temp_dir.clone()).unwrap();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
 
         // Create some partial download files
         File::create(temp_dir.join("file1.pdf.part")).await.unwrap();
@@ -332,12 +1606,700 @@ mod tests {
         tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
     }
 
+    fn progress(id: usize, downloaded: u64, total: Option<u64>) -> DownloadProgress {
+        DownloadProgress { id, downloaded, total, finished: false, waiting: None }
+    }
+
+    #[test]
+    fn test_transfer_stats_aggregates_active_downloads() {
+        let mut stats = TransferStats::default();
+        let start = Instant::now();
+
+        stats.update_at(progress(0, 0, Some(1000)), start);
+        stats.update_at(progress(1, 0, Some(500)), start);
+        stats.update_at(progress(0, 200, Some(1000)), start + Duration::from_secs(1));
+        stats.update_at(progress(1, 100, Some(500)), start + Duration::from_secs(1));
+
+        assert_eq!(stats.active_count(), 2);
+        assert_eq!(stats.remaining(), Some(1200));
+        assert_eq!(stats.speed(), 300.0);
+        assert_eq!(stats.eta(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_transfer_stats_history_and_stalls() {
+        let mut stats = TransferStats::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        stats.update_at(progress(0, 0, Some(10_000)), start);
+        stats.tick_at(start + Duration::from_millis(500));
+        assert!(stats.transfers()[0].history.is_empty());
+
+        stats.update_at(progress(0, 1000, Some(10_000)), at(1));
+        stats.tick_at(at(1));
+        stats.update_at(progress(0, 3000, Some(10_000)), at(2));
+        stats.tick_at(at(2));
+        let view = &stats.transfers()[0];
+        assert_eq!(view.history, vec![1000, 2000]);
+        // 0.3 * 2000 + 0.7 * 1000
+        assert!((view.speed - 1300.0).abs() < 1e-6);
+        assert_eq!(view.eta, Some(Duration::from_secs_f64(7000.0 / 1300.0)));
+        assert!(!view.stalled);
+
+        // No progress events while the mirror is silent
+        for secs in 3..=5 {
+            stats.tick_at(at(secs));
+        }
+        let view = &stats.transfers()[0];
+        assert_eq!(view.history, vec![1000, 2000, 0, 0, 0]);
+        assert!(view.stalled);
+
+        for secs in 6..(6 + HISTORY_LEN as u64) {
+            stats.tick_at(at(secs));
+        }
+        assert_eq!(stats.transfers()[0].history.len(), HISTORY_LEN);
+    }
+
+    #[test]
+    fn test_transfer_stats_unknown_size() {
+        let mut stats = TransferStats::default();
+        let start = Instant::now();
+
+        stats.update_at(progress(0, 0, Some(1000)), start);
+        stats.update_at(progress(1, 0, None), start);
+
+        assert_eq!(stats.remaining(), None);
+        assert_eq!(stats.eta(), None);
+    }
+
+    #[test]
+    fn test_transfer_stats_removes_finished() {
+        let mut stats = TransferStats::default();
+        let start = Instant::now();
+
+        stats.update_at(progress(0, 0, Some(1000)), start);
+        assert!(!stats.is_empty());
+
+        let done = DownloadProgress { finished: true, ..progress(0, 1000, Some(1000)) };
+        stats.update_at(done, start + Duration::from_secs(1));
+        assert!(stats.is_empty());
+        assert_eq!(stats.speed(), 0.0);
+    }
+
+    #[test]
+    fn test_progress_reporter_sends_finished_on_drop() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        {
+            let mut reporter = ProgressReporter {
+                id: 7,
+                tx,
+                downloaded: 0,
+                total: Some(10),
+                last_sent: None,
+            };
+            reporter.update(5, Some(10));
+        }
+
+        assert_eq!(rx.try_recv().unwrap(), progress(7, 5, Some(10)));
+        let last = rx.try_recv().unwrap();
+        assert!(last.finished);
+        assert_eq!(last.downloaded, 5);
+    }
+
+    #[test]
+    fn test_parse_waitlist() {
+        let page = "https://mirror.example/slow_download/abc/0/0";
+        let countdown = r#"<p>Please wait <span class="js-partner-countdown">38</span> seconds to download this file.</p>"#;
+        assert_eq!(Waitlist::parse(countdown, page), Some(Waitlist::Wait(38)));
+        assert_eq!(Waitlist::parse("<p>Please wait 12 seconds.</p>", page), Some(Waitlist::Wait(12)));
+
+        let ready = r#"<p><a href="/files/abc.epub">📚 Download now</a></p>"#;
+        assert_eq!(Waitlist::parse(ready, page), Some(Waitlist::Ready("https://mirror.example/files/abc.epub".to_string())));
+
+        assert_eq!(Waitlist::parse("<p>Download limit reached</p>", page), None);
+        assert_eq!(Waitlist::parse(r#"<a href="javascript:void(0)">Download now</a>"#, page), None);
+    }
+
+    #[tokio::test]
+    async fn test_download_waits_out_waitlist() {
+        let (url, server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<p>Please wait <span class=\"js-partner-countdown\">1</span> seconds</p>",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<a href=\"/files/book.pdf\">Download now</a>",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4",
+        ]).await;
+        let temp_dir = std::env::temp_dir().join(format!("annadl_waitlist_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap().with_progress(0, tx);
+
+        let path = downloader.download(&url, Some("book.pdf")).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        let requests = server.await.unwrap();
+        assert!(requests[2].starts_with("GET /files/book.pdf "));
+        assert_eq!(rx.try_recv().unwrap().waiting, Some(1));
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_gives_up_on_long_waitlist() {
+        let (url, _server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<p>Please wait 900 seconds</p>",
+        ]).await;
+        let downloader = Downloader::new(std::env::temp_dir(), &Config::default()).unwrap();
+
+        let err = downloader.download(&url, None).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<DownloadError>(), Some(&DownloadError::Waitlisted { url, wait_secs: 900 }));
+    }
+
+    #[test]
+    fn test_transfer_stats_waiting() {
+        let mut stats = TransferStats::default();
+        let start = Instant::now();
+        stats.update_at(DownloadProgress { waiting: Some(30), ..progress(0, 0, None) }, start);
+        stats.tick_at(start + Duration::from_secs(10));
+        let view = &stats.transfers()[0];
+        assert_eq!(view.waiting, Some(30));
+        assert!(!view.stalled);
+    }
+
+    #[tokio::test]
+    async fn test_check_link_head() {
+        let (url, server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/epub+zip\r\nContent-Length: 2202009\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let downloader = Downloader::new(std::env::temp_dir(), &Config::default()).unwrap();
+
+        let health = downloader.check_link(&url).await;
+        assert_eq!(health.status, Some(200));
+        assert_eq!(health.size, RemoteSize { bytes: Some(2202009), is_html: false });
+        assert!(server.await.unwrap()[0].starts_with("HEAD "));
+    }
+
+    #[tokio::test]
+    async fn test_check_links() {
+        let (small, _) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 1000\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let (large, _) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 2000\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let (gone, _) = test_server::serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let config = Config { link_check_concurrency: 2, ..Config::default() };
+        let downloader = Downloader::new(std::env::temp_dir(), &config).unwrap();
+
+        let urls = vec![small, gone, large];
+        let checked: Vec<_> = downloader.check_all(&urls).await.into_iter().map(|health| (health.status, health.size.bytes)).collect();
+        assert_eq!(checked, [(Some(200), Some(1000)), (Some(404), None), (Some(200), Some(2000))]);
+    }
+
+    #[test]
+    fn test_arrange_links() {
+        let live = LinkHealth { status: Some(200), ..LinkHealth::default() };
+        let gone = LinkHealth { status: Some(404), ..LinkHealth::default() };
+        let links = vec![("a", gone), ("b", live), ("c", LinkHealth::default()), ("d", live)];
+        let order = |mode| arrange_links(links.clone(), mode).into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order(LinkCheck::Off), ["a", "b", "c", "d"]);
+        assert_eq!(order(LinkCheck::Demote), ["b", "d", "a", "c"]);
+        assert_eq!(order(LinkCheck::Hide), ["b", "d"]);
+        // Hiding every link would leave nothing to try
+        assert_eq!(arrange_links(vec![("a", gone)], LinkCheck::Hide).len(), 1);
+
+        assert_eq!(gone.to_string(), "HTTP 404");
+        assert_eq!(LinkHealth::default().to_string(), "no answer");
+        assert!(live.to_string().starts_with("size unknown, "));
+    }
+
+    #[tokio::test]
+    async fn test_check_link_falls_back_to_range() {
+        let (url, server) = test_server::serve(vec![
+            "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-0/5000000\r\nContent-Length: 1\r\nConnection: close\r\n\r\nx",
+        ]).await;
+        let downloader = Downloader::new(std::env::temp_dir(), &Config::default()).unwrap();
+
+        let health = downloader.check_link(&url).await;
+        assert_eq!(health.status, Some(206));
+        assert_eq!(health.size.bytes, Some(5000000));
+        let requests = server.await.unwrap();
+        assert!(requests[1].starts_with("GET "));
+        assert!(requests[1].to_lowercase().contains("range: bytes=0-0"));
+    }
+
+    #[test]
+    fn test_remote_size_looks_wrong() {
+        let size = |bytes| RemoteSize { bytes: Some(bytes), is_html: false };
+        assert!(!size(2_000_000).looks_wrong(None));
+        assert!(!size(2_000_000).looks_wrong(Some(2_200_000)));
+        assert!(size(300_000).looks_wrong(Some(2_200_000)));
+        assert!(size(4_000).looks_wrong(None));
+        assert!(RemoteSize { bytes: Some(2_000_000), is_html: true }.looks_wrong(None));
+        assert!(!RemoteSize::default().looks_wrong(Some(1_000_000)));
+    }
+
+    #[tokio::test]
+    async fn test_download_decodes_compressed_response() {
+        let temp_dir = std::env::temp_dir().join(format!("annadl_gzip_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        let body: &[u8] = b"The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog.";
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, body).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let mut response = format!("HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", encoded.len()).into_bytes();
+        response.extend_from_slice(&encoded);
+        let (base, _server) = test_server::serve(vec![response]).await;
+        let url = format!("{}/book.txt", base);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap().with_progress(0, tx);
+        let path = downloader.download(&url, None).await.unwrap();
+
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), body);
+
+        // The encoded Content-Length must not be reported as the file size
+        let mut last = None;
+        while let Ok(progress) = rx.try_recv() {
+            last = Some(progress);
+        }
+        let last = last.unwrap();
+        assert!(last.finished);
+        assert_eq!(last.total, None);
+        assert_eq!(last.downloaded, body.len() as u64);
+
+        // Cleanup
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    fn headers(pairs: &[(reqwest::header::HeaderName, &str)]) -> reqwest::header::HeaderMap {
+        let mut map = reqwest::header::HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(name.clone(), value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_extension_for_content_type() {
+        assert_eq!(Downloader::extension_for_content_type("application/epub+zip"), Some("epub"));
+        assert_eq!(Downloader::extension_for_content_type("Application/PDF; charset=binary"), Some("pdf"));
+        assert_eq!(Downloader::extension_for_content_type("application/octet-stream"), None);
+    }
+
+    #[test]
+    fn test_determine_filename_uses_content_type() {
+        use reqwest::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+
+        let map = headers(&[(CONTENT_TYPE, "application/epub+zip")]);
+        assert_eq!(Downloader::determine_filename("https://example.com/get/abc123", None, &map), "abc123.epub");
+        assert_eq!(Downloader::determine_filename("https://example.com/file.pdf", None, &map), "file.pdf");
+        assert_eq!(Downloader::determine_filename("https://example.com/get/abc", Some("Dr. Who - Smith"), &map), "Dr. Who - Smith.epub");
+        assert_eq!(Downloader::determine_filename("https://example.com/", Some("Book.pdf"), &map), "Book.pdf");
+
+        let map = headers(&[(CONTENT_TYPE, "application/pdf"), (CONTENT_DISPOSITION, "attachment; filename=\"real.epub\"")]);
+        assert_eq!(Downloader::determine_filename("https://example.com/get/abc123", None, &map), "real.epub");
+
+        let map = headers(&[(CONTENT_TYPE, "application/pdf")]);
+        let name = Downloader::determine_filename("https://example.com/", None, &map);
+        assert!(name.starts_with("downloaded_file_") && name.ends_with(".pdf"));
+
+        let name = Downloader::determine_filename("https://example.com/", None, &headers(&[]));
+        assert!(name.ends_with(".tmp"));
+    }
+
+    #[tokio::test]
+    async fn test_download_read_timeout() {
+        // Sends headers and part of the body, then stalls
+        let base = test_server::stall(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial").await;
+
+        let temp_dir = std::env::temp_dir().join(format!("annadl_timeout_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        let config = Config { download_read_timeout_secs: 1, ..Config::default() };
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap();
+        let err = downloader.download(&format!("{}/book.txt", base), None).await.unwrap_err();
+        assert!(err.to_string().contains("No data received"));
+
+        // Cleanup
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_aria2c_args() {
+        let aria2c = |config: Config| Downloader::new(PathBuf::from("/books"), &config).unwrap();
+        let downloader = aria2c(Config::default());
+        let args = downloader.aria2c_args("https://example.com/book.epub", "Book - Author.epub", false, &HeaderMap::new()).unwrap();
+        assert_eq!(args.first().map(String::as_str), Some("--dir=/books"));
+        assert!(args.contains(&"--out=Book - Author.epub".to_string()));
+        assert!(args.contains(&"--continue=true".to_string()));
+        assert!(!args.contains(&"--quiet=true".to_string()));
+        assert_eq!(args.last().map(String::as_str), Some("https://example.com/book.epub"));
+
+        assert!(args.contains(&"--split=4".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("--ca-certificate") || arg.starts_with("--check-certificate")));
+
+        let args = aria2c(Config { polite: true, ..Config::default() }).aria2c_args("https://example.com/book.epub", "b.epub", true, &HeaderMap::new()).unwrap();
+        assert!(args.contains(&"--quiet=true".to_string()));
+        assert!(args.contains(&"--max-connection-per-server=1".to_string()));
+        assert!(args.contains(&"--split=1".to_string()));
+
+        let proxied = aria2c(Config { proxy: Some("http://u:p@proxy:3128".to_string()), ..Config::default() });
+        let args = proxied.aria2c_args("https://example.com/b.epub", "b.epub", true, &HeaderMap::new()).unwrap();
+        assert!(args.contains(&"--all-proxy=http://u:p@proxy:3128".to_string()));
+        let mut socks = aria2c(Config::default());
+        socks.proxy = Some("socks5h://proxy:1080".to_string());
+        assert!(socks.aria2c_args("https://example.com/b.epub", "b.epub", true, &HeaderMap::new()).is_err());
+
+        let mut trusting = aria2c(Config { insecure: true, ..Config::default() });
+        trusting.ca_cert = Some(PathBuf::from("/etc/ssl/corp.pem"));
+        let args = trusting.aria2c_args("https://example.com/b.epub", "b.epub", true, &HeaderMap::new()).unwrap();
+        assert!(args.contains(&"--ca-certificate=/etc/ssl/corp.pem".to_string()));
+        assert!(args.contains(&"--check-certificate=false".to_string()));
+
+        let headers = http::browser_headers("Mozilla/5.0 Firefox/128.0");
+        let args = downloader.aria2c_args("https://example.com/b.epub", "b.epub", true, &headers).unwrap();
+        assert!(args.contains(&"--header=user-agent: Mozilla/5.0 Firefox/128.0".to_string()));
+        assert!(args.contains(&"--header=accept-language: en-US,en;q=0.5".to_string()));
+        assert_eq!(args.last().map(String::as_str), Some("https://example.com/b.epub"));
+    }
+
+    #[tokio::test]
+    async fn test_aria2c_missing_binary() {
+        let config = Config {
+            downloader_backend: DownloaderBackend::Aria2c,
+            aria2c_path: Some(PathBuf::from("/nonexistent/aria2c")),
+            ..Config::default()
+        };
+        let downloader = Downloader::new(std::env::temp_dir(), &config).unwrap();
+        let err = downloader.download_with_aria2c("https://example.com/a.pdf", "a.pdf", &HeaderMap::new()).await.unwrap_err();
+        assert!(err.to_string().contains("is aria2c installed"));
+    }
+
+    #[tokio::test]
+    async fn test_aria2c_resolves_link_with_head() {
+        let (base, server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/epub+zip\r\nContent-Length: 5000000\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let url = format!("{}/book.epub", base);
+        let config = Config {
+            downloader_backend: DownloaderBackend::Aria2c,
+            aria2c_path: Some(PathBuf::from("/nonexistent/aria2c")),
+            retry_max: 0,
+            ..Config::default()
+        };
+        let downloader = Downloader::new(std::env::temp_dir(), &config).unwrap();
+        let err = downloader.download(&url, Some("a.epub")).await.unwrap_err();
+        assert!(err.to_string().contains("is aria2c installed"));
+
+        // The file itself is left to aria2c
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("HEAD /book.epub"));
+    }
+
+    #[test]
+    fn test_check_redirect() {
+        let start = Url::parse("https://mirror.example/get/abc").unwrap();
+        let next = Url::parse("https://cdn.example/file.epub").unwrap();
+
+        assert_eq!(Downloader::check_redirect(&next, std::slice::from_ref(&start), 10), Ok(()));
+        assert_eq!(
+            Downloader::check_redirect(&start, &[start.clone(), next.clone()], 10),
+            Err(DownloadError::RedirectLoop(start.to_string()))
+        );
+        assert_eq!(
+            Downloader::check_redirect(&next, std::slice::from_ref(&start), 0),
+            Err(DownloadError::TooManyRedirects(0))
+        );
+
+        let limit = Url::parse("https://mirror.example/download_limit?md5=abc").unwrap();
+        assert!(matches!(
+            Downloader::check_redirect(&limit, std::slice::from_ref(&start), 10),
+            Err(DownloadError::LimitReached(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_limit_page() {
+        assert!(Downloader::is_limit_page("<h1>Daily Download Limit reached</h1>"));
+        assert!(!Downloader::is_limit_page("<h1>Slow download</h1>"));
+    }
+
+    #[tokio::test]
+    async fn test_download_reports_redirect_loop() {
+        // /a redirects to /b, which redirects back to /a
+        let (base, _server) = test_server::serve_with(|request| {
+            let target = if request.starts_with("GET /a") { "/b" } else { "/a" };
+            format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", target)
+        }).await;
+
+        let downloader = Downloader::new(std::env::temp_dir(), &Config::default()).unwrap();
+        let err = downloader.download(&format!("{}/a", base), None).await.unwrap_err();
+
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::RedirectLoop(_))));
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_skips_missing_files() {
+        // /gone is a 404, /busy a 429 and /book the file
+        let (base, _server) = test_server::serve_with(|request| {
+            if request.starts_with("GET /gone") {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            } else if request.starts_with("GET /busy") {
+                "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4"
+            }
+        }).await;
+
+        let temp_dir = std::env::temp_dir().join(format!("annadl_status_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
+        let url = |path: &str| format!("{}/{}", base, path);
+
+        let err = downloader.download(&url("gone"), Some("book.pdf")).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<DownloadError>(), Some(&DownloadError::NotFound(url("gone"))));
+        let err = downloader.download(&url("busy"), Some("book.pdf")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::Blocked { status: 429, .. })));
+
+        let (gone, busy, book) = (url("gone"), url("busy"), url("book"));
+        let path = downloader.download_verified(&[&gone, &busy, &book], Some("book.pdf"), None, 3).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_resumes_broken_off_transfer() {
+        // The first answer breaks off halfway; the retry asks for the rest
+        let (base, server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF",
+            "HTTP/1.1 206 Partial Content\r\nContent-Type: application/pdf\r\nContent-Range: bytes 4-7/8\r\nContent-Length: 4\r\nConnection: close\r\n\r\n-1.4",
+        ]).await;
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("annadl_resume_test_{}", nanos));
+        let config = Config { retry_base_ms: 10, ..Config::default() };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap().with_progress(0, tx);
+
+        let url = format!("{}/book.pdf", base);
+        let path = downloader.download_verified(&[&url], Some("book.pdf"), None, 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        let requests = server.await.unwrap();
+        assert!(!requests[0].to_lowercase().contains("range:"));
+        assert!(requests[1].to_lowercase().contains("range: bytes=4-"));
+        assert!(!downloader.is_download_in_progress("book.pdf"));
+        // The resumed transfer counts on from what was already there
+        let last = std::iter::from_fn(|| rx.try_recv().ok()).last().unwrap();
+        assert_eq!((last.downloaded, last.total), (8, Some(8)));
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_drops_part_when_falling_back() {
+        // The first mirror breaks off and ignores the range on the retry
+        let broken = "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF";
+        let (broken_base, _broken) = test_server::serve(vec![broken, broken]).await;
+        let (good_base, _good) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4",
+        ]).await;
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("annadl_fallback_part_test_{}", nanos));
+        let config = Config { retry_max: 1, retry_base_ms: 10, ..Config::default() };
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap();
+
+        let (broken, good) = (format!("{}/book.pdf", broken_base), format!("{}/book.pdf", good_base));
+        let path = downloader.download_verified(&[&broken, &good], Some("book.pdf"), None, 2).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        assert!(!downloader.is_download_in_progress("book.pdf"));
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_range_start() {
+        assert_eq!(Downloader::range_start(&headers(&[(reqwest::header::CONTENT_RANGE, "bytes 100-199/200")])), Some(100));
+        assert_eq!(Downloader::range_start(&headers(&[(reqwest::header::CONTENT_RANGE, "bytes */200")])), None);
+        assert_eq!(Downloader::range_start(&headers(&[])), None);
+    }
+
+    #[tokio::test]
+    async fn test_download_renames_part_file_when_complete() {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("annadl_part_test_{}", nanos));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
+        let ok = "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4";
+
+        // Another download of the same file is still writing it
+        File::create(temp_dir.join("book.pdf.part")).await.unwrap();
+        let (url, _server) = test_server::serve(vec![ok]).await;
+        let err = downloader.download_verified(&[&url, &url], Some("book.pdf"), None, 2).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<DownloadError>(), Some(&DownloadError::InProgress("book.pdf".to_string())));
+
+        // One nobody has written to for longer than the read timeout is
+        // left over and written over
+        let stale = std::time::SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options().write(true).open(temp_dir.join("book.pdf.part")).unwrap().set_modified(stale).unwrap();
+        let (url, _server) = test_server::serve(vec![ok]).await;
+        let path = downloader.download(&url, Some("book.pdf")).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        assert!(!downloader.is_download_in_progress("book.pdf"));
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_skips_broken_connections() {
+        // The first mirror answers with something that isn't HTTP at all
+        let (url, server) = test_server::serve(vec![
+            "garbage\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4",
+        ]).await;
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let temp_dir = std::env::temp_dir().join(format!("annadl_transport_test_{}", nanos));
+        let config = Config { retry_max: 0, ..Config::default() };
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap()
+            .with_history(temp_dir.join("annadl.db"));
+
+        let path = downloader.download_verified(&[&url, &url], Some("book.pdf"), None, 2).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        server.await.unwrap();
+        let records = History::open(&temp_dir.join("annadl.db")).unwrap().records().unwrap();
+        assert_eq!(records["127.0.0.1"], crate::reliability::HostRecord { successes: 1, failures: 1 });
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_retries_server_errors() {
+        let unavailable = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let temp_dir = std::env::temp_dir().join(format!("annadl_retry_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+
+        // The first request gets a 503, the retry the file
+        let (base, server) = test_server::serve(vec![
+            unavailable,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 8\r\nConnection: close\r\n\r\n%PDF-1.4",
+        ]).await;
+        let url = format!("{}/book", base);
+        let config = Config { retry_base_ms: 10, ..Config::default() };
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap();
+        let path = downloader.download_verified(&[&url], Some("book.pdf"), None, 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"%PDF-1.4");
+        assert_eq!(server.await.unwrap().len(), 2);
+
+        // With retries off the 503 is final
+        let (base, server) = test_server::serve(vec![unavailable]).await;
+        let url = format!("{}/book", base);
+        let config = Config { retry_max: 0, ..Config::default() };
+        let downloader = Downloader::new(temp_dir.clone(), &config).unwrap();
+        let err = downloader.download_verified(&[&url], Some("book.pdf"), None, 1).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<DownloadError>(), Some(DownloadError::Http { status: 503, .. })));
+        assert_eq!(server.await.unwrap().len(), 1);
+
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_write_error_detects_full_disk() {
+        let downloader = Downloader::new(PathBuf::from("/books"), &Config::default()).unwrap();
+
+        let full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        let err = downloader.write_error(full, "Failed to write chunk");
+        let download_err = err.downcast_ref::<DownloadError>().unwrap();
+        assert_eq!(download_err, &DownloadError::DiskFull(PathBuf::from("/books")));
+        assert!(!download_err.is_mirror_fault());
+
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let err = downloader.write_error(denied, "Failed to write chunk");
+        assert!(err.downcast_ref::<DownloadError>().is_none());
+        assert_eq!(err.to_string(), "Failed to write chunk");
+    }
+
+    #[test]
+    fn test_check_magic_bytes() {
+        assert!(Downloader::check_magic_bytes("pdf", b"%PDF-1.7 rest").is_ok());
+        assert!(Downloader::check_magic_bytes("EPUB", b"PK\x03\x04mimetype").is_ok());
+        assert!(Downloader::check_magic_bytes("txt", b"plain text").is_ok());
+        assert!(Downloader::check_magic_bytes("pdf", b"PK\x03\x04").is_err());
+        assert!(Downloader::check_magic_bytes("cbz", b"PK\x03\x04page001.jpg").is_ok());
+        assert!(Downloader::check_magic_bytes("cbr", b"Rar!\x1a\x07\x01\x00").is_ok());
+        assert!(Downloader::check_magic_bytes("cbr", b"PK\x03\x04").is_err());
+        assert!(Downloader::check_magic_bytes("cb7", b"7z\xbc\xaf\x27\x1c\x00\x04").is_ok());
+        assert!(Downloader::check_magic_bytes("cb7", b"Rar!\x1a\x07\x00").is_err());
+        assert!(Downloader::check_magic_bytes("m4b", b"\x00\x00\x00\x20ftypM4B ").is_ok());
+        assert!(Downloader::check_magic_bytes("mp3", b"ID3\x04\x00").is_ok());
+        assert!(Downloader::check_magic_bytes("mp3", b"\xff\xfb\x90\x64").is_ok());
+        assert!(Downloader::check_magic_bytes("mp3", b"PK\x03\x04").is_err());
+        assert!(Downloader::check_magic_bytes("flac", b"fLaC\x00\x00").is_ok());
+        assert!(Downloader::check_magic_bytes("epub", b"").is_err());
+    }
+
+    #[test]
+    fn test_check_magic_bytes_rejects_html() {
+        let page = b"  <!DOCTYPE html><html><body>Download limit reached</body></html>";
+        let result = Downloader::check_magic_bytes("epub", page);
+        assert_eq!(result, Err("Downloaded file is an HTML page, not a book".to_string()));
+        assert!(Downloader::check_magic_bytes("", page).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_quarantines_invalid_file() {
+        let temp_dir = std::env::temp_dir().join(format!("annadl_verify_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
+
+        let good = temp_dir.join("good.pdf");
+        tokio::fs::write(&good, b"%PDF-1.4").await.unwrap();
+        assert!(downloader.verify(&good, None).await.is_ok());
+        assert!(good.exists());
+
+        let bad = temp_dir.join("bad.pdf");
+        tokio::fs::write(&bad, b"<html>error</html>").await.unwrap();
+        assert!(downloader.verify(&bad, None).await.is_err());
+        assert!(!bad.exists());
+        assert!(temp_dir.join("quarantine/bad.pdf").exists());
+        let reason = tokio::fs::read_to_string(temp_dir.join("quarantine/bad.pdf.reason.txt")).await.unwrap();
+        assert!(reason.contains("HTML page"));
+
+        // A second bad file of the same name leaves the first in place
+        tokio::fs::write(&bad, b"").await.unwrap();
+        let err = downloader.verify(&bad, None).await.unwrap_err();
+        assert!(err.to_string().contains("bad (2).pdf"));
+        assert_eq!(tokio::fs::read(temp_dir.join("quarantine/bad.pdf")).await.unwrap(), b"<html>error</html>");
+        let reason = tokio::fs::read_to_string(temp_dir.join("quarantine/bad (2).pdf.reason.txt")).await.unwrap();
+        assert!(!reason.contains("HTML page"));
+
+        // Cleanup
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_checks_md5() {
+        let temp_dir = std::env::temp_dir().join(format!("annadl_md5_test_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
+
+        // md5("hello world")
+        let path = temp_dir.join("book.txt");
+        tokio::fs::write(&path, b"hello world").await.unwrap();
+        assert!(downloader.verify(&path, Some("5EB63BBBE01EEED093CB22BB8F5ACDC3")).await.is_ok());
+
+        let err = downloader.verify(&path, Some("00000000000000000000000000000000")).await.unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert!(temp_dir.join("quarantine/book.txt").exists());
+
+        // Cleanup
+        tokio::fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_cleanup_partial_downloads_empty_dir() {
         let temp_dir = std::env::temp_dir().join(format!("annadl_cleanup_empty_{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()));
         tokio::fs::create_dir_all(&temp_dir).await.unwrap();
 
-        let downloader = Downloader::new(temp_dir.clone()).unwrap();
+        let downloader = Downloader::new(temp_dir.clone(), &Config::default()).unwrap();
 
         // Should not error on empty directory
         let result = downloader.cleanup_partial_downloads().await;
@@ -0,0 +1,231 @@
+use crate::account::Credentials;
+use crate::config::{Config, LinkCheck};
+use crate::downloader::{self, Downloader};
+use crate::finish_download;
+use crate::openlibrary;
+use crate::reliability::Scorer;
+use crate::saved;
+use crate::scraper::{self, AnnaScraper, Book, SearchFilters};
+use crate::source::{self, SourceProvider};
+use crate::term;
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// What the user typed at a numbered prompt.
+#[derive(Debug, PartialEq)]
+enum Choice {
+    Pick(usize),
+    Back,
+    Quit,
+    Invalid,
+}
+
+fn parse_choice(input: &str, len: usize) -> Choice {
+    match input.trim().to_lowercase().as_str() {
+        "" | "b" | "back" => Choice::Back,
+        "q" | "quit" | "exit" => Choice::Quit,
+        n => match n.parse::<usize>() {
+            Ok(n) if (1..=len).contains(&n) => Choice::Pick(n - 1),
+            _ => Choice::Invalid,
+        },
+    }
+}
+
+/// Prints `label` and reads one line; None at end of input.
+fn prompt(label: &str) -> Result<Option<String>> {
+    print!("{}", label);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Asks for a number until it is in range; Back or Quit end the prompt.
+fn prompt_choice(label: &str, len: usize) -> Result<Choice> {
+    loop {
+        let Some(input) = prompt(label)? else {
+            return Ok(Choice::Quit);
+        };
+        match parse_choice(&input, len) {
+            Choice::Invalid => println!("Enter 1-{}, Enter to go back or q to quit", len),
+            choice => return Ok(choice),
+        }
+    }
+}
+
+/// Interactive mode using plain prompts and the inline progress bar, so
+/// everything stays in the terminal's scrollback.
+pub async fn run(config: &Config, credentials: Option<Credentials>, download_path: PathBuf, initial_query: Option<String>, filters: &SearchFilters) -> Result<()> {
+    let logged_in = credentials.is_some();
+    let source = source::provider(config, credentials.clone())?;
+    let page_credentials = credentials.clone();
+    let scraper = AnnaScraper::new(config)
+        .context("Failed to create scraper")?
+        .with_credentials(credentials);
+    let downloader = Downloader::new(download_path, config)
+        .context("Failed to create downloader")?
+        .with_history(saved::default_path());
+
+    println!("Type a title, author, ISBN, book link or MD5. Enter on an empty line quits.");
+    let mut next_query = initial_query;
+    loop {
+        let query = match next_query.take() {
+            Some(query) => query,
+            None => match prompt("\nSearch: ")? {
+                Some(query) if !query.is_empty() => query,
+                _ => return Ok(()),
+            },
+        };
+
+        let books = if let Some(md5) = scraper::md5_from_reference(&query, config) {
+            vec![scraper.open_md5(&md5).await]
+        } else {
+            match source.search(&query, filters, config.num_results(None)).await {
+                Ok(results) if results.books.is_empty() => {
+                    println!("No results found");
+                    continue;
+                }
+                Ok(results) => {
+                    if let Some(total) = results.total {
+                        println!("{} results", total);
+                    }
+                    results.books
+                }
+                Err(e) => {
+                    println!("Search failed: {:#}", e);
+                    continue;
+                }
+            }
+        };
+
+        print_books(&books);
+        let mut book = match prompt_choice("Book number: ", books.len())? {
+            Choice::Pick(i) => books[i].clone(),
+            Choice::Quit => return Ok(()),
+            _ => continue,
+        };
+
+        if config.enrich_metadata {
+            match openlibrary::enrich_book(config, &mut book).await {
+                Ok(changed) if !changed.is_empty() => println!("Open Library filled in {}", changed.join(", ")),
+                Ok(_) => {}
+                Err(e) => println!("Open Library lookup failed: {:#}", e),
+            }
+        }
+
+        // A failed book offers the other editions on its page instead
+        loop {
+            let pages = source::provider_for(config, page_credentials.clone(), &book.url)?;
+            let mut editions = Vec::new();
+            match download_book(config, &scraper, pages.as_ref(), &downloader, &mut book, &mut editions, logged_in).await {
+                Ok(()) => break,
+                Err(e) => println!("Download failed: {:#}", e),
+            }
+            if editions.is_empty() {
+                break;
+            }
+            println!("Other editions:");
+            print_books(&editions);
+            match prompt_choice("Edition number: ", editions.len())? {
+                Choice::Pick(i) => book = editions.swap_remove(i),
+                Choice::Quit => return Ok(()),
+                _ => break,
+            }
+        }
+    }
+}
+
+fn print_books(books: &[Book]) {
+    for (i, book) in books.iter().enumerate() {
+        term::outln!("{:>3}. {} — {}", i + 1, book.title, book.author.as_deref().unwrap_or("Unknown"));
+        if let Some(issue) = book.issue() {
+            println!("     Issue: {}", issue);
+        }
+        println!("     {} | {} | {} | {}", book.display_year(), book.display_language(), book.display_format(), book.display_size());
+    }
+}
+
+/// Downloads `book`, leaving the other editions listed on its page in
+/// `editions` for when it fails.
+async fn download_book(config: &Config, scraper: &AnnaScraper, pages: &dyn SourceProvider, downloader: &Downloader, book: &mut Book, editions: &mut Vec<Book>, logged_in: bool) -> Result<()> {
+    let filename = book.file_name();
+
+    // Members skip the link list while they have fast downloads left
+    if let Some(md5) = book.md5().filter(|_| logged_in) {
+        if let Ok(fast) = scraper.fast_download(md5).await {
+            println!("Member fast download{}", fast.downloads_left.map(|n| format!(" ({} left today)", n)).unwrap_or_default());
+            match downloader.download_verified(&[fast.url.as_str()], Some(&filename), Some(md5), 1).await {
+                Ok(path) => {
+                    finish_download(config, book, &path).await;
+                    return Ok(());
+                }
+                Err(e) => println!("Fast download failed ({:#}), trying mirrors", e),
+            }
+        }
+    }
+
+    let page = pages.details(&book.url).await
+        .context("Failed to fetch download links")?;
+    book.metadata.identifiers.merge(page.identifiers);
+    book.metadata.details.merge(page.details);
+    *editions = page.editions;
+    let (links, torrents): (Vec<_>, Vec<_>) = page.links.into_iter().partition(scraper::DownloadLink::is_http);
+    for link in &torrents {
+        println!("  {} ({}): {}", link.text, link.source, link.url);
+    }
+    if links.is_empty() {
+        anyhow::bail!("No download links found");
+    }
+    let (links, health): (Vec<_>, Vec<Option<downloader::LinkHealth>>) = if config.link_check == LinkCheck::Off {
+        let unchecked = vec![None; links.len()];
+        (links, unchecked)
+    } else {
+        println!("Checking {} links...", links.len());
+        let urls: Vec<String> = links.iter().map(|link| link.url.clone()).collect();
+        let health = downloader.check_all(&urls).await;
+        downloader::arrange_links(links.into_iter().zip(health).collect(), config.link_check)
+            .into_iter()
+            .map(|(link, health)| (link, Some(health)))
+            .unzip()
+    };
+    // Best scoring first, as in the TUI
+    let order = Scorer::load(config, &saved::default_path()).rank(&links, &health);
+    for (i, &j) in order.iter().enumerate() {
+        match &health[j] {
+            Some(health) => println!("{:>3}. {} ({}): {}", i + 1, links[j].text, links[j].source, health),
+            None => println!("{:>3}. {} ({})", i + 1, links[j].text, links[j].source),
+        }
+    }
+    let links: Vec<scraper::DownloadLink> = order.into_iter().map(|i| links[i].clone()).collect();
+    let Choice::Pick(index) = prompt_choice("Link number: ", links.len())? else {
+        return Ok(());
+    };
+
+    // Fall back to the remaining links if the chosen file fails validation
+    let urls: Vec<&str> = std::iter::once(links[index].url.as_str())
+        .chain(links.iter().map(|l| l.url.as_str()).filter(|url| *url != links[index].url))
+        .collect();
+    let path = downloader.download_verified(&urls, Some(&filename), book.md5(), config.download_attempts).await?;
+    finish_download(config, book, &path).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("2", 3), Choice::Pick(1));
+        assert_eq!(parse_choice(" 3 \n", 3), Choice::Pick(2));
+        assert_eq!(parse_choice("4", 3), Choice::Invalid);
+        assert_eq!(parse_choice("0", 3), Choice::Invalid);
+        assert_eq!(parse_choice("two", 3), Choice::Invalid);
+        assert_eq!(parse_choice("", 3), Choice::Back);
+        assert_eq!(parse_choice("B", 3), Choice::Back);
+        assert_eq!(parse_choice("q", 3), Choice::Quit);
+    }
+}
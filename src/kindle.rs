@@ -0,0 +1,145 @@
+//! Emailing downloads to a Kindle through Amazon's Send to Kindle service.
+
+use crate::account;
+use crate::config::Config;
+use anyhow::{Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::path::Path;
+
+const KEYRING_ENTRY: &str = "smtp";
+
+/// Formats Send to Kindle accepts by email, with their MIME types.
+const ACCEPTED_FORMATS: &[(&str, &str)] = &[
+    ("epub", "application/epub+zip"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("txt", "text/plain"),
+    ("rtf", "application/rtf"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+];
+
+/// Why a file can't be emailed, worded for the user.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum KindleError {
+    #[error("Send to Kindle is not set up: {0} is missing from the config")]
+    NotConfigured(&'static str),
+    #[error("No SMTP password stored; run `annadl smtp-login` first")]
+    NoPassword,
+    #[error("{} is {:.1}MB, over the {}MB email limit; copy it over USB with --send-to-device instead", .name, *.size as f64 / 1_000_000.0, .limit_mb)]
+    TooLarge { name: String, size: u64, limit_mb: u64 },
+    #[error("Send to Kindle doesn't accept .{0} files by email (EPUB, PDF, DOC(X), TXT, RTF and HTML only)")]
+    UnsupportedFormat(String),
+}
+
+/// The MIME type to attach `file` as, if it fits the size limit and Amazon
+/// accepts the format.
+fn check_attachment(file: &Path, size: u64, limit_mb: u64) -> Result<ContentType, KindleError> {
+    let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let mime = ACCEPTED_FORMATS.iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+        .ok_or_else(|| KindleError::UnsupportedFormat(extension.clone()))?;
+    if size > limit_mb * 1_000_000 {
+        return Err(KindleError::TooLarge { name, size, limit_mb });
+    }
+    ContentType::parse(mime).map_err(|_| KindleError::UnsupportedFormat(extension))
+}
+
+fn build_message(from: &str, to: &str, file: &Path, content_type: ContentType, body: Vec<u8>) -> Result<Message> {
+    let name = file.file_name().context("Not a file")?.to_string_lossy().into_owned();
+    let from: Mailbox = from.parse().with_context(|| format!("Invalid sender address {}", from))?;
+    let to: Mailbox = to.parse().with_context(|| format!("Invalid Kindle address {}", to))?;
+    Message::builder()
+        .from(from)
+        .to(to)
+        .subject(&name)
+        .multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(format!("Sent by anna-dl: {}", name)))
+                .singlepart(Attachment::new(name).body(body, content_type)),
+        )
+        .context("Failed to build email")
+}
+
+/// Emails `file` to the configured Kindle address.
+pub async fn send(config: &Config, file: &Path) -> Result<()> {
+    let to = config.kindle_email.as_deref().ok_or(KindleError::NotConfigured("kindle_email"))?;
+    let server = config.smtp_server.as_deref().ok_or(KindleError::NotConfigured("smtp_server"))?;
+    let username = config.smtp_username.as_deref().ok_or(KindleError::NotConfigured("smtp_username"))?;
+    // The sender must be on the Kindle account's approved list
+    let from = config.smtp_from.as_deref().unwrap_or(username);
+
+    let size = tokio::fs::metadata(file).await
+        .with_context(|| format!("Failed to read {}", file.display()))?
+        .len();
+    let content_type = check_attachment(file, size, config.kindle_max_mb)?;
+    let password = account::load_password(KEYRING_ENTRY).await?.ok_or(KindleError::NoPassword)?;
+    let body = tokio::fs::read(file).await
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let message = build_message(from, to, file, content_type, body)?;
+
+    // 465 is TLS from the start; other ports upgrade with STARTTLS
+    let builder = if config.smtp_port == 465 {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(server)
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(server)
+    }
+    .with_context(|| format!("Invalid SMTP server {}", server))?;
+    let mailer = builder
+        .port(config.smtp_port)
+        .credentials(Credentials::new(username.to_string(), password))
+        .build();
+    mailer.send(message).await
+        .with_context(|| format!("Failed to send email through {}", server))?;
+    Ok(())
+}
+
+/// Saves the SMTP password typed at `annadl smtp-login`.
+pub async fn store_password(password: String) -> Result<()> {
+    account::store_password(KEYRING_ENTRY, "the SMTP password", password).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_attachment() {
+        assert!(check_attachment(Path::new("Dune.epub"), 2_000_000, 50).is_ok());
+        assert!(check_attachment(Path::new("Dune.PDF"), 2_000_000, 50).is_ok());
+        assert_eq!(
+            check_attachment(Path::new("Dune.azw3"), 2_000_000, 50).unwrap_err(),
+            KindleError::UnsupportedFormat("azw3".to_string())
+        );
+
+        let err = check_attachment(Path::new("Atlas.pdf"), 60_000_000, 50).unwrap_err();
+        assert_eq!(err.to_string(), "Atlas.pdf is 60.0MB, over the 50MB email limit; copy it over USB with --send-to-device instead");
+        // A lower cap for providers like Gmail
+        assert!(check_attachment(Path::new("Atlas.pdf"), 30_000_000, 25).is_err());
+    }
+
+    #[test]
+    fn test_build_message() {
+        let content_type = check_attachment(Path::new("Dune.epub"), 4, 50).unwrap();
+        let message = build_message("me@example.com", "reader@kindle.com", Path::new("/tmp/Dune.epub"), content_type, b"PK\x03\x04".to_vec()).unwrap();
+        let raw = String::from_utf8_lossy(&message.formatted()).into_owned();
+        assert!(raw.contains("To: reader@kindle.com"));
+        assert!(raw.contains("Subject: Dune.epub"));
+        assert!(raw.contains("Content-Type: application/epub+zip"));
+        assert!(raw.contains("filename=\"Dune.epub\""));
+
+        assert!(build_message("not an address", "reader@kindle.com", Path::new("Dune.epub"), ContentType::TEXT_PLAIN, Vec::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_requires_config() {
+        let err = send(&Config::default(), Path::new("Dune.epub")).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<KindleError>(), Some(&KindleError::NotConfigured("kindle_email")));
+    }
+}
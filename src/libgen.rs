@@ -0,0 +1,346 @@
+use crate::config::Config;
+use crate::http::{self, UserAgents};
+use crate::retry::{self, RetryPolicy};
+use crate::scraper::{
+    format_filter_extensions, language_filter_codes, parse_identifiers, parse_size, Book, BookDetails,
+    BookPage, DomainFilter, DownloadLink, Format, LanguageCode, LinkKind, Metadata, ScrapeError, SearchFilters,
+    SearchResults,
+};
+use crate::source::SourceProvider;
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use scraper::{Html, Selector};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Rows requested per search; LibGen offers 25, 50 or 100.
+const RESULTS_PER_PAGE: usize = 25;
+
+/// Mirror page paths that lead to the file, best first: `get.php` serves it
+/// directly, the others are landing pages with a download button.
+const FILE_PATHS: &[&str] = &["get.php", "/main/", "ads.php", "file.php"];
+
+/// Searches Library Genesis directly, for when Anna's Archive can't be
+/// reached or finds nothing. Results use the same `Book` and
+/// `DownloadLink` types, with URLs on the LibGen mirror that answered.
+pub struct LibgenScraper {
+    client: reqwest::Client,
+    user_agents: UserAgents,
+    polite: bool,
+    retry: RetryPolicy,
+    /// Without trailing slashes.
+    mirrors: Vec<String>,
+    /// The mirror that answered last, tried first next time.
+    active_mirror: AtomicUsize,
+    domains: DomainFilter,
+}
+
+impl LibgenScraper {
+    pub fn new(config: &Config) -> Result<Self> {
+        let client = http::client_builder(config, config.scraper_timeouts())?
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            client,
+            user_agents: UserAgents::from_config(config),
+            polite: config.polite,
+            retry: config.retry_policy(),
+            mirrors: config.libgen_mirrors.iter()
+                .map(|m| m.trim().trim_end_matches('/').to_string())
+                .filter(|m| !m.is_empty())
+                .collect(),
+            active_mirror: AtomicUsize::new(0),
+            domains: DomainFilter::from_config(config),
+        })
+    }
+
+    /// Whether `url` is a page on one of the LibGen mirrors.
+    pub fn owns(&self, url: &str) -> bool {
+        self.mirrors.iter().any(|mirror| url.strip_prefix(mirror.as_str()).is_some_and(|rest| rest.starts_with('/')))
+    }
+
+    /// Books matching `query` on the first mirror that answers. LibGen can't
+    /// filter by format or language, so those filters apply afterwards.
+    pub async fn search(&self, query: &str, filters: &SearchFilters, max_results: usize) -> Result<SearchResults> {
+        let path = format!("/search.php?req={}&res={}&column=def&view=simple", urlencoding::encode(query), RESULTS_PER_PAGE);
+        let (mirror, html) = self.fetch_html(&path).await?;
+        let mut books: Vec<Book> = parse_search_results(&mirror, &html)
+            .into_iter()
+            .filter(|book| passes(filters, book))
+            .collect();
+        books.truncate(max_results);
+        Ok(SearchResults { books, total: None })
+    }
+
+    /// The mirror links on a LibGen book page, leaving out blocked domains.
+    pub async fn book_page(&self, book_url: &str) -> Result<BookPage> {
+        let path = self.mirrors.iter()
+            .find_map(|mirror| book_url.strip_prefix(mirror.as_str()))
+            .unwrap_or(book_url);
+        let (mirror, html) = self.fetch_html(path).await?;
+        let mut page = parse_book_page(&format!("{}{}", mirror, path), &html);
+        page.links.retain(|link| self.domains.allows(&link.url));
+        Ok(page)
+    }
+
+    /// Fetches `path` from the mirror that last answered, moving on to the
+    /// next ones while they can't be reached. Returns the mirror with the page.
+    async fn fetch_html(&self, path: &str) -> Result<(String, String)> {
+        let start = self.active_mirror.load(Ordering::Relaxed);
+        let mut last_error = None;
+        for i in 0..self.mirrors.len() {
+            let at = (start + i) % self.mirrors.len();
+            let url = format!("{}{}", self.mirrors[at], path);
+            match self.retry.run(|| self.fetch_html_once(&url)).await {
+                Ok(html) => {
+                    self.active_mirror.store(at, Ordering::Relaxed);
+                    return Ok((self.mirrors[at].clone(), html));
+                }
+                Err(e) if retry::classify(&e).is_some() => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.context("No LibGen mirrors configured")?.context("No LibGen mirror could be reached"))
+    }
+
+    async fn fetch_html_once(&self, url: &str) -> Result<String> {
+        let _turn = if self.polite { http::polite_turn(url).await } else { None };
+        let response = self.client.get(url)
+            .headers(self.user_agents.headers())
+            .send()
+            .await
+            .context("Failed to reach LibGen")?;
+        if !response.status().is_success() {
+            return Err(ScrapeError::from_status(response.status().as_u16(), url).into());
+        }
+        response.text().await.context("Failed to read LibGen page")
+    }
+}
+
+impl SourceProvider for LibgenScraper {
+    fn name(&self) -> &'static str {
+        "LibGen"
+    }
+
+    fn search<'a>(&'a self, query: &'a str, filters: &'a SearchFilters, max_results: usize) -> BoxFuture<'a, Result<SearchResults>> {
+        Box::pin(LibgenScraper::search(self, query, filters, max_results))
+    }
+
+    fn details<'a>(&'a self, book_url: &'a str) -> BoxFuture<'a, Result<BookPage>> {
+        Box::pin(self.book_page(book_url))
+    }
+
+    fn owns(&self, book_url: &str) -> bool {
+        LibgenScraper::owns(self, book_url)
+    }
+}
+
+/// The filters LibGen's search can't apply itself.
+fn passes(filters: &SearchFilters, book: &Book) -> bool {
+    let formats = format_filter_extensions(filters.format.as_deref().unwrap_or_default());
+    let languages = language_filter_codes(filters.language.as_deref().unwrap_or_default());
+    let format_ok = formats.is_empty() || book.format.as_ref().is_some_and(|f| formats.iter().any(|e| e == f.extension()));
+    let language_ok = languages.is_empty() || book.language.as_ref().is_some_and(|l| languages.contains(l));
+    format_ok && language_ok && filters.matches(book)
+}
+
+/// The rows of a LibGen results table: ID, authors, title, publisher,
+/// year, pages, language, size, extension and then mirror links.
+fn parse_search_results(mirror: &str, html: &str) -> Vec<Book> {
+    let document = Html::parse_document(html);
+    let (Ok(rows), Ok(cells), Ok(title_link), Ok(isbns)) = (
+        Selector::parse("table.c tr"),
+        Selector::parse("td"),
+        Selector::parse("a[href*='md5=']"),
+        Selector::parse("i"),
+    ) else {
+        return Vec::new();
+    };
+    let clean = |text: String| Some(text.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|t| !t.is_empty());
+
+    let mut books: Vec<Book> = Vec::new();
+    for row in document.select(&rows) {
+        let cells: Vec<_> = row.select(&cells).collect();
+        if cells.len() < 9 {
+            continue;
+        }
+        let text = |i: usize| clean(cells[i].text().collect());
+        // The header row has no book link
+        let Some(link) = cells[2].select(&title_link).next() else {
+            continue;
+        };
+        let Some(md5) = link.value().attr("href").and_then(md5_param) else {
+            continue;
+        };
+        // The ISBNs sit in the link too, below the title
+        let Some(title) = clean(link.children().filter_map(|node| node.value().as_text()).map(|t| t.to_string()).collect()) else {
+            continue;
+        };
+        if books.iter().any(|b| b.md5() == Some(md5.as_str())) {
+            continue;
+        }
+        let isbn_text: String = link.select(&isbns).flat_map(|i| i.text()).collect::<Vec<_>>().join(", ");
+
+        books.push(Book {
+            title,
+            author: text(1),
+            year: text(4).and_then(|y| y.parse().ok()),
+            language: text(6).and_then(|l| LanguageCode::from_name(&l)),
+            format: text(8).map(|ext| Format::from_extension(&ext)),
+            size_bytes: text(7).and_then(|s| parse_size(&s)),
+            url: format!("{}/book/index.php?md5={}", mirror, md5),
+            md5: Some(md5),
+            metadata: Metadata {
+                identifiers: parse_identifiers(&isbn_text),
+                details: BookDetails { publisher: text(3), pages: text(5).and_then(|p| p.parse().ok()), ..BookDetails::default() },
+                ..Metadata::default()
+            },
+        });
+    }
+    books
+}
+
+/// The links on a book page that lead to its file, `get.php` first.
+fn parse_book_page(url: &str, html: &str) -> BookPage {
+    let document = Html::parse_document(html);
+    let Ok(anchors) = Selector::parse("a[href]") else {
+        return BookPage::default();
+    };
+    let base = reqwest::Url::parse(url).ok();
+    let md5 = md5_param(url);
+
+    let mut links: Vec<(usize, DownloadLink)> = Vec::new();
+    for anchor in document.select(&anchors) {
+        let Some(href) = anchor.value().attr("href") else {
+            continue;
+        };
+        let Some(rank) = FILE_PATHS.iter().position(|p| href.contains(p)) else {
+            continue;
+        };
+        let names_file = md5.as_ref().is_none_or(|md5| href.to_ascii_lowercase().contains(md5));
+        let Some(absolute) = base.as_ref().and_then(|b| b.join(href).ok()) else {
+            continue;
+        };
+        if !names_file || !matches!(absolute.scheme(), "http" | "https") || links.iter().any(|(_, l)| l.url == absolute.as_str()) {
+            continue;
+        }
+        let host = absolute.host_str().unwrap_or_default().to_string();
+        let text = anchor.value().attr("title")
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map_or_else(|| format!("LibGen ({})", host), str::to_string);
+        links.push((rank, DownloadLink { text, url: absolute.to_string(), source: "LibGen".to_string(), kind: LinkKind::Http }));
+    }
+    links.sort_by_key(|(rank, _)| *rank);
+
+    let text = document.root_element().text().collect::<Vec<_>>().join(" ");
+    BookPage {
+        links: links.into_iter().map(|(_, link)| link).collect(),
+        identifiers: parse_identifiers(&text),
+        details: BookDetails::default(),
+        editions: Vec::new(),
+    }
+}
+
+/// The lowercase MD5 in a `md5=` query parameter.
+fn md5_param(href: &str) -> Option<String> {
+    // ASCII-only lowercasing keeps byte offsets valid for `href`
+    let start = href.to_ascii_lowercase().find("md5=")? + 4;
+    let md5: String = href[start..].chars().take_while(char::is_ascii_hexdigit).collect();
+    (md5.len() == 32).then(|| md5.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MD5: &str = "5c1b4ee5d18a5c6a9e047fa74b8f37d1";
+
+    fn results_page() -> String {
+        format!(r#"<html><body><table width=100% class=c>
+            <tr valign=top bgcolor=#C0C0C0><td><b>ID</b></td><td><b>Author(s)</b></td><td><b>Title</b></td><td><b>Publisher</b></td>
+                <td><b>Year</b></td><td><b>Pages</b></td><td><b>Language</b></td><td><b>Size</b></td><td><b>Extension</b></td><td colspan=2><b>Mirrors</b></td></tr>
+            <tr valign=top><td>123</td><td><a href='search.php?req=Frank Herbert&column=author'>Frank Herbert</a></td>
+                <td width=500><a href='book/index.php?md5={upper}' id=123>Dune<br><font color=green><i>9780441013593, 0441013597</i></font></a></td>
+                <td>Ace</td><td>2005</td><td>528</td><td>English</td><td nowrap>1 Mb</td><td nowrap>epub</td>
+                <td><a href='http://library.lol/main/{upper}' title='Gen.lib.rus.ec'>[1]</a></td><td><a href='http://libgen.li/ads.php?md5={upper}'>[2]</a></td></tr>
+            <tr valign=top><td>124</td><td>Frank Herbert</td>
+                <td width=500><a href='book/index.php?md5=1f0e3dad99908345f7439f8ffabdffc4' id=124>Dune Messiah</a></td>
+                <td></td><td></td><td>0</td><td>Russian</td><td nowrap>900 Kb</td><td nowrap>pdf</td><td></td><td></td></tr>
+        </table></body></html>"#, upper = MD5.to_uppercase())
+    }
+
+    #[test]
+    fn test_parse_search_results() {
+        let books = parse_search_results("https://libgen.is", &results_page());
+        assert_eq!(books.len(), 2);
+
+        let dune = &books[0];
+        assert_eq!(dune.title, "Dune");
+        assert_eq!(dune.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(dune.year, Some(2005));
+        assert_eq!(dune.language, LanguageCode::new("en"));
+        assert_eq!(dune.format, Some(Format::Epub));
+        assert_eq!(dune.size_bytes, parse_size("1MB"));
+        assert_eq!(dune.md5(), Some(MD5));
+        assert_eq!(dune.url, format!("https://libgen.is/book/index.php?md5={}", MD5));
+        assert_eq!(dune.metadata.identifiers.isbn(), Some("9780441013593"));
+        assert_eq!(dune.metadata.details.publisher.as_deref(), Some("Ace"));
+        assert_eq!(dune.metadata.details.pages, Some(528));
+
+        assert_eq!(books[1].year, None);
+        assert_eq!(books[1].language, LanguageCode::new("ru"));
+        assert_eq!(books[1].metadata.details.publisher, None);
+    }
+
+    #[test]
+    fn test_passes_filters() {
+        let books = parse_search_results("https://libgen.is", &results_page());
+        let filters = SearchFilters { format: Some("pdf".to_string()), ..SearchFilters::default() };
+        assert!(!passes(&filters, &books[0]));
+        assert!(passes(&filters, &books[1]));
+        let filters = SearchFilters { language: Some("en".to_string()), ..SearchFilters::default() };
+        assert!(passes(&filters, &books[0]));
+        assert!(!passes(&filters, &books[1]));
+    }
+
+    #[test]
+    fn test_parse_book_page() {
+        let html = format!(r#"<html><body>
+            <a href="/search.php?req=Frank+Herbert">Frank Herbert</a>
+            <a href="http://libgen.li/ads.php?md5={MD5}" title="Libgen.li">Libgen.li</a>
+            <a href="http://library.lol/main/{MD5}">Gen.lib.rus.ec</a>
+            <a href="/get.php?md5={MD5}&key=ABC">GET</a>
+            <a href="/get.php?md5=1f0e3dad99908345f7439f8ffabdffc4">Other file</a>
+            ISBN: 9780441013593
+        </body></html>"#);
+        let page = parse_book_page(&format!("https://libgen.is/book/index.php?md5={}", MD5), &html);
+        let urls: Vec<&str> = page.links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(urls, vec![
+            format!("https://libgen.is/get.php?md5={}&key=ABC", MD5).as_str(),
+            format!("http://library.lol/main/{}", MD5).as_str(),
+            format!("http://libgen.li/ads.php?md5={}", MD5).as_str(),
+        ]);
+        assert_eq!(page.links[0].text, "LibGen (libgen.is)");
+        assert_eq!(page.links[2].text, "Libgen.li");
+        assert!(page.links.iter().all(|l| l.source == "LibGen" && l.kind == LinkKind::Http));
+        assert_eq!(page.identifiers.isbn(), Some("9780441013593"));
+    }
+
+    #[test]
+    fn test_md5_param() {
+        assert_eq!(md5_param(&format!("/get.php?MD5={}", MD5.to_uppercase())).as_deref(), Some(MD5));
+        // Lowercasing "İ" grows it by a byte, which used to shift the offset
+        assert_eq!(md5_param(&format!("/İstanbul/get.php?md5={}", MD5)).as_deref(), Some(MD5));
+        assert_eq!(md5_param("/get.php?md5=abc"), None);
+        assert_eq!(md5_param("/search.php?req=dune"), None);
+    }
+
+    #[test]
+    fn test_owns() {
+        let config = Config { libgen_mirrors: vec!["https://libgen.is/".to_string()], ..Config::default() };
+        let libgen = LibgenScraper::new(&config).unwrap();
+        assert!(libgen.owns(&format!("https://libgen.is/book/index.php?md5={}", MD5)));
+        assert!(!libgen.owns("https://libgen.is.example/book"));
+        assert!(!libgen.owns(&format!("https://annas-archive.org/md5/{}", MD5)));
+    }
+}
@@ -0,0 +1,196 @@
+//! How sizes, counts and dates are written for people: digit separators
+//! and date order follow the user's locale, and sizes use binary or
+//! decimal units. JSON output keeps raw numbers and is not affected.
+
+use crate::config::Config;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// How byte sizes are scaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeUnits {
+    /// Powers of 1024, labelled KB, MB and GB as on Anna's Archive.
+    #[default]
+    Binary,
+    /// Powers of 1000, labelled kB, MB and GB.
+    Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateOrder {
+    /// 2026-10-17
+    Iso,
+    /// 17.10.2026, 17/10/2026 or 17-10-2026
+    DayMonthYear(char),
+    /// 10/17/2026
+    MonthDayYear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Locale {
+    thousands: char,
+    decimal: char,
+    dates: DateOrder,
+    units: SizeUnits,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self { thousands: ',', decimal: '.', dates: DateOrder::Iso, units: SizeUnits::Binary }
+    }
+}
+
+static CURRENT: OnceLock<Locale> = OnceLock::new();
+
+/// Sets the locale used by `current()`. Only the first call has an effect.
+pub fn init(config: &Config) {
+    let _ = CURRENT.set(Locale::from_config(config));
+}
+
+/// The locale set by `init`, or the environment's when `init` wasn't called.
+/// Tests always get the default, whatever the machine's locale.
+pub fn current() -> Locale {
+    *CURRENT.get_or_init(|| if cfg!(test) { Locale::default() } else { Locale::from_tag(&env_locale().unwrap_or_default()) })
+}
+
+/// The first of `LC_ALL`, `LC_NUMERIC` and `LANG` that is set.
+fn env_locale() -> Option<String> {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+}
+
+impl Locale {
+    /// The `locale` setting, falling back to the environment, with the
+    /// configured size units.
+    pub fn from_config(config: &Config) -> Self {
+        let tag = config.locale.clone().or_else(env_locale).unwrap_or_default();
+        Self { units: config.size_units, ..Self::from_tag(&tag) }
+    }
+
+    /// Reads a POSIX or BCP 47 tag such as `de_DE.UTF-8` or `en-GB`. Unknown
+    /// or empty tags get `1,234.5` and ISO dates.
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default().to_lowercase();
+        let mut parts = tag.split(['_', '-']);
+        let language = parts.next().unwrap_or_default();
+        let region = parts.next().unwrap_or_default();
+
+        let (thousands, decimal) = match (language, region) {
+            (_, "ch") | (_, "li") => ('\'', '.'),
+            ("de" | "da" | "nl" | "es" | "it" | "pt" | "id" | "tr" | "el" | "ro" | "hr" | "sl" | "sr", _) => ('.', ','),
+            ("fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "hu" | "bg" | "lt" | "lv" | "et", _) => ('\u{a0}', ','),
+            _ => (',', '.'),
+        };
+        let dates = match (language, region) {
+            ("en", "us" | "") => DateOrder::MonthDayYear,
+            ("en", _) | ("fr" | "es" | "it" | "pt" | "el", _) => DateOrder::DayMonthYear('/'),
+            ("nl", _) => DateOrder::DayMonthYear('-'),
+            ("de" | "da" | "nb" | "nn" | "no" | "fi" | "ru" | "uk" | "pl" | "cs" | "sk" | "tr" | "ro" | "hr" | "sl" | "sr" | "bg" | "et" | "lv", _) => DateOrder::DayMonthYear('.'),
+            _ => DateOrder::Iso,
+        };
+        Self { thousands, decimal, dates, units: SizeUnits::Binary }
+    }
+
+    /// A whole number with thousands separators, e.g. `12,345`.
+    pub fn count(&self, n: u64) -> String {
+        let digits = n.to_string();
+        let mut grouped = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(self.thousands);
+            }
+            grouped.push(c);
+        }
+        grouped
+    }
+
+    /// A byte size with one decimal, e.g. `1.5MB`; below 1KB in bytes.
+    pub fn size(&self, bytes: u64) -> String {
+        let (step, units): (u64, [&str; 4]) = match self.units {
+            SizeUnits::Binary => (1024, ["KB", "MB", "GB", "TB"]),
+            SizeUnits::Decimal => (1000, ["kB", "MB", "GB", "TB"]),
+        };
+        let mut scale = step.pow(units.len() as u32);
+        for unit in units.iter().rev() {
+            if bytes >= scale {
+                let value = format!("{:.1}", bytes as f64 / scale as f64);
+                return format!("{}{}", value.replace('.', &self.decimal.to_string()), unit);
+            }
+            scale /= step;
+        }
+        format!("{}B", bytes)
+    }
+
+    /// The local date of a Unix timestamp, e.g. `17.10.2026`.
+    pub fn date(&self, timestamp: i64) -> String {
+        let Some(time) = Local.timestamp_opt(timestamp, 0).single() else {
+            return "-".to_string();
+        };
+        let pattern = match self.dates {
+            DateOrder::Iso => "%Y-%m-%d".to_string(),
+            DateOrder::DayMonthYear(sep) => format!("%d{0}%m{0}%Y", sep),
+            DateOrder::MonthDayYear => "%m/%d/%Y".to_string(),
+        };
+        time.format(&pattern).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_separators() {
+        assert_eq!(Locale::default().count(999), "999");
+        assert_eq!(Locale::from_tag("en_US.UTF-8").count(1234567), "1,234,567");
+        assert_eq!(Locale::from_tag("de_DE.UTF-8").count(1234567), "1.234.567");
+        assert_eq!(Locale::from_tag("fr-FR").count(12345), "12\u{a0}345");
+        assert_eq!(Locale::from_tag("de_CH").count(12345), "12'345");
+        assert_eq!(Locale::from_tag("C").count(12345), "12,345");
+    }
+
+    #[test]
+    fn test_size_units() {
+        let binary = Locale::default();
+        assert_eq!(binary.size(512), "512B");
+        assert_eq!(binary.size(1536), "1.5KB");
+        assert_eq!(binary.size(2202009), "2.1MB");
+        assert_eq!(binary.size(3 << 30), "3.0GB");
+        assert_eq!(binary.size(1536 << 30), "1.5TB");
+
+        let decimal = Locale { units: SizeUnits::Decimal, ..Locale::default() };
+        assert_eq!(decimal.size(999), "999B");
+        assert_eq!(decimal.size(1500), "1.5kB");
+        assert_eq!(decimal.size(2202009), "2.2MB");
+
+        assert_eq!(Locale::from_tag("de_DE").size(1536), "1,5KB");
+    }
+
+    #[test]
+    fn test_date_order() {
+        // Noon UTC is the same date in every timezone from UTC-11 to UTC+11
+        let noon = 1792238400; // 2026-10-17 12:00 UTC
+        assert_eq!(Locale::from_tag("").date(noon), "2026-10-17");
+        assert_eq!(Locale::from_tag("en_US").date(noon), "10/17/2026");
+        assert_eq!(Locale::from_tag("en_GB").date(noon), "17/10/2026");
+        assert_eq!(Locale::from_tag("de_DE.UTF-8@euro").date(noon), "17.10.2026");
+        assert_eq!(Locale::from_tag("nl_NL").date(noon), "17-10-2026");
+        assert_eq!(Locale::from_tag("sv_SE").date(noon), "2026-10-17");
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config {
+            locale: Some("de_DE".to_string()),
+            size_units: SizeUnits::Decimal,
+            ..Config::default()
+        };
+        let locale = Locale::from_config(&config);
+        assert_eq!(locale.size(1500), "1,5kB");
+        assert_eq!(locale.count(1500), "1.500");
+    }
+}
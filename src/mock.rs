@@ -0,0 +1,239 @@
+//! A local stand-in for Anna's Archive and its download mirrors, serving
+//! the recorded pages in `fixtures/mock`, so search, book page and download
+//! can be exercised end to end without touching the real site. Built with
+//! `--features mock-server`; `--mock-server` points a normal run at it.
+
+use wiremock::matchers::{method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// The book every fixture describes; the MD5 of `DUNE_EPUB`.
+pub const DUNE_MD5: &str = "5c1b4ee5d18a5c6a9e047fa74b8f37d1";
+pub const DUNE_EPUB: &[u8] = include_bytes!("../fixtures/mock/dune.epub");
+
+/// The paper on the SciDB page; the MD5 of `PAPER_PDF`.
+pub const PAPER_DOI: &str = "10.1038/nature14539";
+pub const PAPER_MD5: &str = "88c998de0b9c26b59571527e8bb03b04";
+pub const PAPER_PDF: &[u8] = include_bytes!("../fixtures/mock/paper.pdf");
+
+/// The secret key the fast download API accepts, e.g. as `ANNADL_SECRET_KEY`.
+pub const MEMBER_KEY: &str = "mock-member-key";
+
+const SEARCH_HTML: &str = include_str!("../fixtures/mock/search.html");
+/// Mirror links are written as `{{base}}/...` and filled in with the
+/// server's address.
+const BOOK_HTML: &str = include_str!("../fixtures/mock/book.html");
+const RECORD_JSON: &str = include_str!("../fixtures/mock/record.json");
+const SCIDB_HTML: &str = include_str!("../fixtures/mock/scidb.html");
+
+pub struct MockArchive {
+    server: MockServer,
+}
+
+impl MockArchive {
+    /// Starts the server on a free local port with every fixture mounted.
+    /// It stops when dropped.
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let html = |body: String| ResponseTemplate::new(200)
+            .insert_header("content-type", "text/html; charset=utf-8")
+            .set_body_string(body);
+
+        Mock::given(method("GET")).and(path("/search"))
+            .respond_with(html(SEARCH_HTML.to_string()))
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path(format!("/md5/{}", DUNE_MD5)))
+            .respond_with(html(BOOK_HTML.replace("{{base}}", &server.uri())))
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path(format!("/db/aarecord_elasticsearch/md5:{}.json", DUNE_MD5)))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(RECORD_JSON, "application/json"))
+            .mount(&server).await;
+        // The first mirror has lost the file; the second serves it
+        Mock::given(method("GET")).and(path_regex("^/mirror/gone/"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path("/mirror/libgen/get.php")).and(query_param("md5", DUNE_MD5))
+            .respond_with(ResponseTemplate::new(200)
+                .insert_header("content-disposition", "attachment; filename=\"dune.epub\"")
+                .set_body_raw(DUNE_EPUB, "application/epub+zip"))
+            .mount(&server).await;
+
+        Mock::given(method("GET")).and(path("/dyn/api/fast_download.json"))
+            .and(query_param("md5", DUNE_MD5)).and(query_param("key", MEMBER_KEY))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "download_url": format!("{}/fast/{}.epub", server.uri(), DUNE_MD5),
+                "account_fast_download_info": {"downloads_left": 24, "downloads_per_day": 25},
+            })))
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path(format!("/fast/{}.epub", DUNE_MD5)))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(DUNE_EPUB, "application/epub+zip"))
+            .mount(&server).await;
+
+        Mock::given(method("GET")).and(path(format!("/scidb/{}", PAPER_DOI)))
+            .respond_with(html(SCIDB_HTML.replace("{{base}}", &server.uri()).replace("{{md5}}", PAPER_MD5)))
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path(format!("/mirror/scidb/{}.pdf", PAPER_DOI)))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(PAPER_PDF, "application/pdf"))
+            .mount(&server).await;
+
+        Self { server }
+    }
+
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::downloader::Downloader;
+    use crate::scraper::{AnnaScraper, Format, SearchFilters};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("annadl_mock_{}_{}", name, nanos))
+    }
+
+    /// The default config pointed at `mock`.
+    fn config(mock: &MockArchive) -> Config {
+        Config { archive_url: mock.uri(), libgen_fallback: false, ..Config::default() }
+    }
+
+    #[tokio::test]
+    async fn test_search_details_download() {
+        let mock = MockArchive::start().await;
+        let config = config(&mock);
+        let scraper = AnnaScraper::new(&config).unwrap();
+
+        let results = scraper.search("dune", &SearchFilters::default(), 5).await.unwrap();
+        assert_eq!(results.total.map(|t| t.count), Some(2));
+        assert_eq!(results.books.len(), 2);
+        let book = &results.books[0];
+        assert_eq!(book.title, "Dune");
+        assert_eq!(book.format, Some(Format::Epub));
+        assert_eq!(book.md5(), Some(DUNE_MD5));
+        assert_eq!(book.url, format!("{}/md5/{}", mock.uri(), DUNE_MD5));
+
+        let page = scraper.book_page(&book.url).await.unwrap();
+        assert_eq!(page.links.len(), 2);
+        assert_eq!(page.identifiers.isbn(), Some("9780441172719"));
+        assert_eq!(page.details.summary().as_deref(), Some("Chilton Books, 412 pages"));
+        assert_eq!(page.details.cover_url, Some(format!("{}/covers/dune.jpg", mock.uri())));
+        // The book itself and the list after the next heading are left out
+        let editions: Vec<_> = page.editions.iter().map(|b| (b.title.as_str(), b.format.clone())).collect();
+        assert_eq!(editions, [("Dune (40th Anniversary Edition)", Some(Format::Pdf))]);
+
+        // The removed file on the first mirror falls through to the second
+        let dir = temp_dir("download");
+        let downloader = Downloader::new(dir.clone(), &config).unwrap();
+        let urls: Vec<&str> = page.links.iter().map(|l| l.url.as_str()).collect();
+        let path = downloader.download_verified(&urls, Some("Dune - Frank Herbert.epub"), book.md5(), 3).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), DUNE_EPUB);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_book_by_md5() {
+        let mock = MockArchive::start().await;
+        let scraper = AnnaScraper::new(&config(&mock)).unwrap();
+
+        let book = scraper.book_by_md5(DUNE_MD5).await.unwrap();
+        assert_eq!(book.title, "Dune");
+        assert_eq!(book.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(book.size_bytes, Some(DUNE_EPUB.len() as u64));
+        assert_eq!(book.url, format!("{}/md5/{}", mock.uri(), DUNE_MD5));
+
+        // Unknown records are a clean failure, not a retry loop
+        let err = scraper.book_by_md5("00000000000000000000000000000000").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(crate::scraper::ScrapeError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_open_pasted_md5() {
+        let mock = MockArchive::start().await;
+        let scraper = AnnaScraper::new(&config(&mock)).unwrap();
+
+        // A link to the public site still opens on the configured one
+        let md5 = crate::scraper::md5_from_reference(&format!("https://annas-archive.org/md5/{}", DUNE_MD5), &config(&mock)).unwrap();
+        let book = scraper.open_md5(&md5).await;
+        assert_eq!(book.url, format!("{}/md5/{}", mock.uri(), DUNE_MD5));
+        let page = scraper.book_page(&book.url).await.unwrap();
+        assert_eq!(page.links.len(), 2);
+
+        // Without a record the placeholder points there too
+        let book = scraper.open_md5("00000000000000000000000000000000").await;
+        assert_eq!(book.url, format!("{}/md5/00000000000000000000000000000000", mock.uri()));
+    }
+
+    #[tokio::test]
+    async fn test_paper_download() {
+        let mock = MockArchive::start().await;
+        let config = config(&mock);
+        let scraper = AnnaScraper::new(&config).unwrap();
+
+        let paper = scraper.paper(&format!("https://doi.org/{}", PAPER_DOI)).await.unwrap();
+        assert_eq!(paper.title.as_deref(), Some("Deep learning"));
+        assert_eq!(paper.md5.as_deref(), Some(PAPER_MD5));
+
+        let dir = temp_dir("paper");
+        let downloader = Downloader::new(dir.clone(), &config).unwrap();
+        let path = downloader.download_verified(&[&paper.pdf_url], Some(&paper.filename()), paper.md5.as_deref(), 1).await.unwrap();
+        assert_eq!(path.file_name().unwrap(), "Deep learning.pdf");
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), PAPER_PDF);
+
+        // A DOI SciDB doesn't have
+        let err = scraper.paper("10.1000/missing").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(crate::scraper::ScrapeError::NotFound(_))));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_member_fast_download_with_secret_key() {
+        let mock = MockArchive::start().await;
+        let config = config(&mock);
+        let member = AnnaScraper::new(&config).unwrap()
+            .with_credentials(Some(crate::account::Credentials::from_secret_key(MEMBER_KEY)));
+
+        let fast = member.fast_download(DUNE_MD5).await.unwrap();
+        assert_eq!(fast.downloads_left, Some(24));
+        let dir = temp_dir("fast");
+        let downloader = Downloader::new(dir.clone(), &config).unwrap();
+        let path = downloader.download_verified(&[&fast.url], Some("Dune.epub"), Some(DUNE_MD5), 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), DUNE_EPUB);
+
+        // Without a session there is no account page to read
+        assert!(member.account_status().await.is_err());
+        let stranger = AnnaScraper::new(&config).unwrap()
+            .with_credentials(Some(crate::account::Credentials::from_secret_key("wrong")));
+        assert!(stranger.fast_download(DUNE_MD5).await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mirror_outage_is_retried() {
+        let mock = MockArchive::start().await;
+        // The mirror fails once before serving the file
+        Mock::given(method("GET")).and(path("/mirror/flaky/dune.epub"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock.server).await;
+        Mock::given(method("GET")).and(path("/mirror/flaky/dune.epub"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(DUNE_EPUB, "application/epub+zip"))
+            .mount(&mock.server).await;
+
+        let dir = temp_dir("retry");
+        let config = Config { retry_base_ms: 10, ..config(&mock) };
+        let downloader = Downloader::new(dir.clone(), &config).unwrap();
+        let url = format!("{}/mirror/flaky/dune.epub", mock.uri());
+        let path = downloader.download_verified(&[&url], Some("dune.epub"), Some(DUNE_MD5), 1).await.unwrap();
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), DUNE_EPUB);
+        assert_eq!(mock.server.received_requests().await.unwrap().len(), 2);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
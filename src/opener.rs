@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[cfg(target_os = "windows")]
+const OPENER: &str = "explorer";
+#[cfg(target_os = "macos")]
+const OPENER: &str = "open";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const OPENER: &str = "xdg-open";
+
+/// Opens a directory in the file manager, creating it first so a fresh
+/// download path still opens.
+pub fn open_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    open_path(dir)
+}
+
+/// Opens a file or directory with the platform's default application.
+pub fn open_path(path: &Path) -> Result<()> {
+    open(path.as_os_str())
+}
+
+/// Opens a URL with the application registered for its scheme, such as a
+/// torrent client for `magnet:` links.
+pub fn open_url(url: &str) -> Result<()> {
+    open(OsStr::new(url))
+}
+
+fn open(target: &OsStr) -> Result<()> {
+    // Not waited on: the file manager outlives us, and explorer's exit code is meaningless
+    Command::new(OPENER)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", OPENER))?;
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use crate::config::Config;
+use crate::http;
+use crate::scraper::Book;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const SEARCH_URL: &str = "https://openlibrary.org/search.json";
+
+/// Open Library asks API clients to identify themselves.
+const USER_AGENT: &str = concat!("anna-dl/", env!("CARGO_PKG_VERSION"), " (https://github.com/fpcMotif/anna-dl)");
+
+/// Subjects kept from a match; Open Library lists hundreds for popular works.
+const MAX_SUBJECTS: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    docs: Vec<Work>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Work {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    author_name: Vec<String>,
+    first_publish_year: Option<u16>,
+    #[serde(default)]
+    subject: Vec<String>,
+    #[serde(default)]
+    series: Vec<String>,
+}
+
+/// Looks books up on Open Library to fill in what the archive's listing
+/// leaves out.
+pub struct OpenLibrary {
+    client: reqwest::Client,
+    polite: bool,
+}
+
+impl OpenLibrary {
+    pub fn new(config: &Config) -> Result<Self> {
+        let client = http::client_builder(config, config.scraper_timeouts())?
+            .user_agent(USER_AGENT)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self { client, polite: config.polite })
+    }
+
+    /// Fills in `book`'s missing author, year, series and subjects,
+    /// replaces its title with the canonical one and normalizes the author
+    /// and year; see `apply`. Returns the names of the fields that changed;
+    /// empty when nothing matched.
+    pub async fn enrich(&self, book: &mut Book) -> Result<Vec<&'static str>> {
+        // An ISBN match is exact, whatever the listing calls the book
+        let exact = book.metadata.identifiers.isbn().is_some();
+        let title = (!exact).then_some(book.title.as_str());
+        let Some(work) = self.find(&search_url(SEARCH_URL, book), title).await? else {
+            return Ok(Vec::new());
+        };
+        Ok(apply(book, work, exact))
+    }
+
+    /// The first result, or the first whose title matches `title` when
+    /// given; Open Library's ranking puts loose matches after exact ones.
+    async fn find(&self, url: &str, title: Option<&str>) -> Result<Option<Work>> {
+        let _turn = if self.polite { http::polite_turn(url).await } else { None };
+        let response: SearchResponse = self.client.get(url)
+            .send()
+            .await
+            .context("Failed to reach Open Library")?
+            .error_for_status()
+            .context("Open Library search failed")?
+            .json()
+            .await
+            .context("Unexpected Open Library response")?;
+        Ok(response.docs.into_iter().find(|work| title.is_none_or(|title| titles_match(&work.title, title))))
+    }
+}
+
+/// One-off enrichment with a fresh client, for callers that enrich a
+/// single chosen book.
+pub async fn enrich_book(config: &Config, book: &mut Book) -> Result<Vec<&'static str>> {
+    OpenLibrary::new(config)?.enrich(book).await
+}
+
+/// Copies the fields `enrich` reported as `changed` from `enriched` into
+/// `book`, which may have gained other details in the meantime.
+pub fn copy_changed(book: &mut Book, enriched: &Book, changed: &[&str]) {
+    for field in changed {
+        match *field {
+            "title" => book.title = enriched.title.clone(),
+            "author" => book.author = enriched.author.clone(),
+            "year" => book.year = enriched.year,
+            "series" => book.metadata.series = enriched.metadata.series.clone(),
+            "subjects" => book.metadata.subjects = enriched.metadata.subjects.clone(),
+            _ => {}
+        }
+    }
+}
+
+fn search_url(base: &str, book: &Book) -> String {
+    const FIELDS: &str = "title,author_name,first_publish_year,subject,series";
+    if let Some(isbn) = book.metadata.identifiers.isbn() {
+        return format!("{}?limit=1&fields={}&isbn={}", base, FIELDS, isbn);
+    }
+    let mut url = format!("{}?limit=5&fields={}&title={}",
+        base,
+        FIELDS,
+        urlencoding::encode(&normalize(&book.title)));
+    if let Some(ref author) = book.author {
+        url.push_str(&format!("&author={}", urlencoding::encode(author)));
+    }
+    url
+}
+
+/// Lowercase words without punctuation or a trailing "(Series, Book 1)".
+fn normalize(title: &str) -> String {
+    let title = title.split(['(', '[', ':']).next().unwrap_or(title);
+    title.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether two titles name the same book: equal once normalized, ignoring
+/// subtitles and series suffixes.
+fn titles_match(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    !a.is_empty() && a == b
+}
+
+/// A name's words, lowercased and sorted, so "Herbert, Frank" and
+/// "frank herbert" compare equal.
+fn name_words(name: &str) -> Vec<String> {
+    let mut words: Vec<String> = name.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    words
+}
+
+/// Whether the scraped year can be a publication year at all. Listings
+/// sometimes show an upload date or a number from the file name instead.
+fn plausible_year(year: u16) -> bool {
+    use chrono::Datelike;
+    (1450..=chrono::Utc::now().year() + 1).contains(&i32::from(year))
+}
+
+/// Updates `book` from an Open Library match. Missing fields are filled
+/// in. An author written differently from Open Library's name, such as
+/// "Herbert, Frank", takes that spelling. When the match is `exact` (by
+/// ISBN), an author sharing no name with any of the work's authors is
+/// replaced too. A year outside the plausible range is replaced.
+fn apply(book: &mut Book, work: Work, exact: bool) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let title = work.title.trim();
+    if !title.is_empty() && title != book.title {
+        book.title = title.to_string();
+        changed.push("title");
+    }
+    let author = match book.author.as_deref() {
+        None => work.author_name.first().cloned(),
+        Some(scraped) => {
+            let words = name_words(scraped);
+            let same = work.author_name.iter().find(|name| name_words(name) == words);
+            let unrelated = work.author_name.iter().all(|name| name_words(name).iter().all(|w| !words.contains(w)));
+            match same {
+                Some(name) if name != scraped => Some(name.clone()),
+                None if exact && unrelated => work.author_name.first().cloned(),
+                _ => None,
+            }
+        }
+    };
+    if let Some(author) = author {
+        book.author = Some(author);
+        changed.push("author");
+    }
+    if !book.year.is_some_and(plausible_year) && work.first_publish_year.is_some() && book.year != work.first_publish_year {
+        book.year = work.first_publish_year;
+        changed.push("year");
+    }
+    if book.metadata.series.is_none() {
+        if let Some(series) = work.series.into_iter().next() {
+            book.metadata.series = Some(series);
+            changed.push("series");
+        }
+    }
+    if book.metadata.subjects.is_empty() && !work.subject.is_empty() {
+        book.metadata.subjects = work.subject.into_iter().take(MAX_SUBJECTS).collect();
+        changed.push("subjects");
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn book(title: &str) -> Book {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = title.to_string();
+        book
+    }
+
+    #[test]
+    fn test_titles_match() {
+        assert!(titles_match("Dune", "dune (Dune Chronicles, Book 1)"));
+        assert!(titles_match("The Hobbit: or There and Back Again", "The Hobbit"));
+        assert!(!titles_match("Dune Messiah", "Dune"));
+        assert!(!titles_match("", "(1)"));
+    }
+
+    #[test]
+    fn test_search_url() {
+        let mut dune = book("Dune (Dune Chronicles, Book 1)");
+        dune.author = Some("Frank Herbert".to_string());
+        assert_eq!(
+            search_url("https://ol.test/search.json", &dune),
+            "https://ol.test/search.json?limit=5&fields=title,author_name,first_publish_year,subject,series&title=dune&author=Frank%20Herbert"
+        );
+
+        dune.metadata.identifiers.isbn13.push("9780441013593".to_string());
+        assert!(search_url("https://ol.test/search.json", &dune).ends_with("&isbn=9780441013593"));
+    }
+
+    #[test]
+    fn test_apply_only_fills_missing() {
+        let mut dune = book("dune");
+        dune.year = Some(1990);
+        let work = Work {
+            title: "Dune".to_string(),
+            author_name: vec!["Frank Herbert".to_string()],
+            first_publish_year: Some(1965),
+            subject: (0..20).map(|i| format!("Subject {}", i)).collect(),
+            series: vec!["Dune Chronicles".to_string()],
+        };
+        assert_eq!(apply(&mut dune, work.clone(), false), vec!["title", "author", "series", "subjects"]);
+        assert_eq!(dune.title, "Dune");
+        assert_eq!(dune.year, Some(1990));
+        assert_eq!(dune.metadata.subjects.len(), MAX_SUBJECTS);
+
+        // A second pass has nothing left to fill
+        assert!(apply(&mut dune, work, false).is_empty());
+    }
+
+    #[test]
+    fn test_apply_normalizes_author_and_year() {
+        let work = Work {
+            title: "Dune".to_string(),
+            author_name: vec!["Frank Herbert".to_string()],
+            first_publish_year: Some(1965),
+            ..Work::default()
+        };
+
+        let mut dune = book("Dune");
+        dune.author = Some("HERBERT, Frank".to_string());
+        dune.year = Some(2981);
+        assert_eq!(apply(&mut dune, work.clone(), false), vec!["author", "year"]);
+        assert_eq!(dune.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(dune.year, Some(1965));
+
+        // A garbled author only gives way to an exact match
+        dune.author = Some("PDF".to_string());
+        assert!(apply(&mut dune, work.clone(), false).is_empty());
+        assert_eq!(apply(&mut dune, work.clone(), true), vec!["author"]);
+        assert_eq!(dune.author.as_deref(), Some("Frank Herbert"));
+
+        // Co-authors and a plausible edition year are kept
+        dune.author = Some("Frank Herbert; Brian Herbert".to_string());
+        dune.year = Some(2005);
+        assert!(apply(&mut dune, work, true).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_find_skips_other_titles() {
+        let body = r#"{"docs": [{"title": "Dune Messiah"}, {"title": "Dune", "author_name": ["Frank Herbert"]}]}"#;
+        let (base, _server) = test_server::serve(vec![test_server::response("200 OK", "application/json", body)]).await;
+
+        let library = OpenLibrary::new(&Config::default()).unwrap();
+        let work = library.find(&format!("{}/search.json", base), Some("Dune")).await.unwrap().unwrap();
+        assert_eq!(work.author_name, vec!["Frank Herbert"]);
+    }
+}
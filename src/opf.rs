@@ -0,0 +1,201 @@
+//! Calibre-compatible OPF metadata written next to downloads, so adding the
+//! folder to Calibre keeps the title, authors and identifiers.
+
+use crate::scraper::Book;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Escapes text for use in XML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters aren't allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders an OPF 2.0 package document for `book`.
+pub fn render(book: &Book) -> String {
+    let mut meta = Vec::new();
+    let element = |name: &str, attrs: &str, value: &str| format!("    <{name}{attrs}>{}</{name}>", escape(value));
+
+    if let Some(md5) = book.md5() {
+        meta.push(element("dc:identifier", " id=\"md5_id\" opf:scheme=\"MD5\"", md5));
+    }
+    meta.push(element("dc:title", "", &book.title));
+    if let Some(ref author) = book.author {
+        // The listing joins several authors into one field
+        for name in author.split([';', '&']).map(str::trim).filter(|n| !n.is_empty()) {
+            meta.push(element("dc:creator", &format!(" opf:role=\"aut\" opf:file-as=\"{}\"", escape(&file_as(name))), name));
+        }
+    }
+    if let Some(year) = book.year {
+        meta.push(element("dc:date", "", &year.to_string()));
+    }
+    if let Some(ref publisher) = book.metadata.details.publisher {
+        meta.push(element("dc:publisher", "", publisher));
+    }
+    if let Some(ref description) = book.metadata.details.description {
+        meta.push(element("dc:description", "", description));
+    }
+    if let Some(ref language) = book.language {
+        meta.push(element("dc:language", "", language.as_str()));
+    }
+    let ids = &book.metadata.identifiers;
+    for (scheme, values) in [("ISBN", &ids.isbn13), ("ISBN", &ids.isbn10), ("AMAZON", &ids.asin), ("OCLC", &ids.oclc), ("DOI", &ids.doi)] {
+        for value in values {
+            meta.push(element("dc:identifier", &format!(" opf:scheme=\"{}\"", scheme), value));
+        }
+    }
+    for subject in &book.metadata.subjects {
+        meta.push(element("dc:subject", "", subject));
+    }
+    if !book.url.is_empty() {
+        meta.push(element("dc:source", "", &book.url));
+    }
+    if let Some(ref series) = book.metadata.series {
+        meta.push(format!("    <meta name=\"calibre:series\" content=\"{}\"/>", escape(series)));
+    }
+
+    let unique = if book.md5().is_some() { " unique-identifier=\"md5_id\"" } else { "" };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\"{}>\n  \
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n\
+         {}\n  \
+         </metadata>\n\
+         </package>\n",
+        unique,
+        meta.join("\n")
+    )
+}
+
+/// "Frank Herbert" as Calibre sorts it: "Herbert, Frank". Names already in
+/// that form are kept.
+fn file_as(name: &str) -> String {
+    if name.contains(',') {
+        return name.to_string();
+    }
+    match name.rsplit_once(' ') {
+        Some((given, family)) => format!("{}, {}", family, given),
+        None => name.to_string(),
+    }
+}
+
+/// Where the OPF for `file` goes: same folder and name, `.opf` extension.
+/// Calibre pairs files sharing a name when adding a folder.
+pub fn sidecar_path(file: &Path) -> PathBuf {
+    file.with_extension("opf")
+}
+
+/// The MD5 identifier in an OPF written by `render`.
+fn md5_of(opf: &str) -> Option<&str> {
+    let start = opf.find("opf:scheme=\"MD5\">")? + "opf:scheme=\"MD5\">".len();
+    let len = opf[start..].find('<')?;
+    Some(&opf[start..start + len])
+}
+
+/// Writes the OPF for a downloaded `file`; returns its path. Other formats
+/// of the title share the sidecar, so one describing a different MD5 is
+/// left alone.
+pub fn write_sidecar(file: &Path, book: &Book) -> Result<PathBuf> {
+    let path = sidecar_path(file);
+    if let Ok(existing) = std::fs::read_to_string(&path) {
+        if let Some(md5) = md5_of(&existing) {
+            if book.md5().is_none_or(|own| !own.eq_ignore_ascii_case(md5)) {
+                anyhow::bail!("{} already describes another file ({}); not overwriting it", path.display(), md5);
+            }
+        }
+    }
+    std::fs::write(&path, render(book))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::LanguageCode;
+
+    fn dune() -> Book {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "Dune <Deluxe & Annotated>".to_string();
+        book.author = Some("Frank Herbert".to_string());
+        book.year = Some(1965);
+        book.language = LanguageCode::new("en");
+        book.metadata.series = Some("Dune Chronicles".to_string());
+        book.metadata.subjects = vec!["Science fiction".to_string()];
+        book.metadata.identifiers.isbn13 = vec!["9780441013593".to_string()];
+        book.metadata.details.publisher = Some("Ace".to_string());
+        book
+    }
+
+    #[test]
+    fn test_render() {
+        let opf = render(&dune());
+        assert!(opf.starts_with("<?xml"));
+        assert!(opf.contains("unique-identifier=\"md5_id\""));
+        assert!(opf.contains("<dc:title>Dune &lt;Deluxe &amp; Annotated&gt;</dc:title>"));
+        assert!(opf.contains("<dc:creator opf:role=\"aut\" opf:file-as=\"Herbert, Frank\">Frank Herbert</dc:creator>"));
+        assert!(opf.contains("<dc:date>1965</dc:date>"));
+        assert!(opf.contains("<dc:publisher>Ace</dc:publisher>"));
+        assert!(!opf.contains("<dc:description>"));
+        assert!(opf.contains("<dc:language>en</dc:language>"));
+        assert!(opf.contains("<dc:identifier opf:scheme=\"ISBN\">9780441013593</dc:identifier>"));
+        assert!(opf.contains("<dc:subject>Science fiction</dc:subject>"));
+        assert!(opf.contains("<meta name=\"calibre:series\" content=\"Dune Chronicles\"/>"));
+    }
+
+    #[test]
+    fn test_render_minimal() {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.md5 = None;
+        book.url = String::new();
+        let opf = render(&book);
+        assert!(!opf.contains("unique-identifier"));
+        assert!(!opf.contains("dc:creator"));
+        assert!(!opf.contains("dc:source"));
+    }
+
+    #[test]
+    fn test_file_as() {
+        assert_eq!(file_as("Frank Herbert"), "Herbert, Frank");
+        assert_eq!(file_as("J. R. R. Tolkien"), "Tolkien, J. R. R.");
+        assert_eq!(file_as("Herbert, Frank"), "Herbert, Frank");
+        assert_eq!(file_as("Homer"), "Homer");
+    }
+
+    #[test]
+    fn test_write_sidecar() {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_opf_{}", nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("Dune - Frank Herbert.epub");
+
+        let path = write_sidecar(&file, &dune()).unwrap();
+        assert_eq!(path, dir.join("Dune - Frank Herbert.opf"));
+        assert!(std::fs::read_to_string(&path).unwrap().contains("<dc:title>"));
+        // Rewriting the same book's sidecar is fine
+        assert!(write_sidecar(&file, &dune()).is_ok());
+
+        // Another edition under the same name keeps the first sidecar
+        let mut pdf = dune();
+        pdf.md5 = Some("0123456789abcdef0123456789abcdef".to_string());
+        pdf.title = "Dune (scan)".to_string();
+        let err = write_sidecar(&dir.join("Dune - Frank Herbert.pdf"), &pdf).unwrap_err();
+        assert!(err.to_string().contains("not overwriting"));
+        let kept = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(md5_of(&kept), Some("d41d8cd98f00b204e9800998ecf8427e"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
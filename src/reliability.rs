@@ -0,0 +1,197 @@
+//! Scores download links so the likeliest to work is tried first. A score
+//! blends the kind of source, how downloads from the link's host went
+//! before, and how quickly the host answered its link check.
+
+use crate::config::Config;
+use crate::downloader::LinkHealth;
+use crate::scraper::DownloadLink;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How much each part of a score counts. Only the ratios matter; a zero
+/// leaves that part out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreWeights {
+    /// The kind of link, e.g. a LibGen file over a slow-download queue.
+    pub source: f64,
+    /// Share of earlier downloads from the host that succeeded.
+    pub history: f64,
+    /// Response time in the link check.
+    pub speed: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self { source: 1.0, history: 1.0, speed: 0.5 }
+    }
+}
+
+/// Successful and failed downloads from one host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HostRecord {
+    pub successes: u32,
+    pub failures: u32,
+}
+
+impl HostRecord {
+    /// The success rate, pulled towards one half while there are few
+    /// downloads, so a single failure doesn't bury a host.
+    pub fn rate(&self) -> f64 {
+        (self.successes as f64 + 1.0) / ((self.successes + self.failures) as f64 + 2.0)
+    }
+}
+
+/// Download outcomes by host, kept in the same database as saved searches.
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS link_outcomes (
+                host TEXT PRIMARY KEY,
+                successes INTEGER NOT NULL DEFAULT 0,
+                failures INTEGER NOT NULL DEFAULT 0
+            )",
+        )
+        .context("Failed to set up the link history table")?;
+        Ok(Self { conn })
+    }
+
+    /// Counts a download from `url`'s host as a success or a failure.
+    pub fn record(&self, url: &str, success: bool) -> Result<()> {
+        let Some(host) = host(url) else {
+            return Ok(());
+        };
+        let (successes, failures) = if success { (1, 0) } else { (0, 1) };
+        self.conn.execute(
+            "INSERT INTO link_outcomes (host, successes, failures) VALUES (?1, ?2, ?3)
+             ON CONFLICT(host) DO UPDATE SET successes = successes + excluded.successes, failures = failures + excluded.failures",
+            params![host, successes, failures],
+        )
+        .context("Failed to record the download")?;
+        Ok(())
+    }
+
+    pub fn records(&self) -> Result<HashMap<String, HostRecord>> {
+        let mut statement = self.conn.prepare("SELECT host, successes, failures FROM link_outcomes")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, HostRecord { successes: row.get(1)?, failures: row.get(2)? })))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url).ok()?.host_str().map(str::to_lowercase)
+}
+
+/// Scores links with the configured weights and the recorded history.
+#[derive(Debug, Clone, Default)]
+pub struct Scorer {
+    weights: ScoreWeights,
+    history: HashMap<String, HostRecord>,
+}
+
+impl Scorer {
+    pub fn new(weights: ScoreWeights, history: HashMap<String, HostRecord>) -> Self {
+        Self { weights, history }
+    }
+
+    /// A scorer with the history in `db`; a database that can't be read
+    /// counts as no history.
+    pub fn load(config: &Config, db: &Path) -> Self {
+        let history = History::open(db).and_then(|history| history.records()).unwrap_or_default();
+        Self::new(config.link_score_weights, history)
+    }
+
+    /// From 0 to 1. A link whose check found it dead scores 0; an
+    /// unchecked link or unknown host counts as average on that part.
+    pub fn score(&self, link: &DownloadLink, health: Option<&LinkHealth>) -> f64 {
+        if health.is_some_and(LinkHealth::is_dead) {
+            return 0.0;
+        }
+        let history = host(&link.url)
+            .and_then(|host| self.history.get(&host))
+            .map_or(0.5, HostRecord::rate);
+        let speed = health.map_or(0.5, |health| 1.0 / (1.0 + health.latency.as_secs_f64()));
+        let ScoreWeights { source, history: history_weight, speed: speed_weight } = self.weights;
+        let total = source.max(0.0) + history_weight.max(0.0) + speed_weight.max(0.0);
+        if total == 0.0 {
+            return 0.0;
+        }
+        (source.max(0.0) * link.source_score() + history_weight.max(0.0) * history + speed_weight.max(0.0) * speed) / total
+    }
+
+    /// Indices of `links` from the best score to the worst; equal scores
+    /// keep the page's order. `health` is by index and may be shorter.
+    pub fn rank(&self, links: &[DownloadLink], health: &[Option<LinkHealth>]) -> Vec<usize> {
+        let scores: Vec<f64> = links.iter()
+            .enumerate()
+            .map(|(i, link)| self.score(link, health.get(i).and_then(Option::as_ref)))
+            .collect();
+        let mut order: Vec<usize> = (0..links.len()).collect();
+        order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::LinkKind;
+    use std::time::Duration;
+
+    fn link(source: &str, url: &str) -> DownloadLink {
+        DownloadLink { text: source.to_string(), url: url.to_string(), source: source.to_string(), kind: LinkKind::Http }
+    }
+
+    #[test]
+    fn test_history() {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_history_{}", nanos));
+        let history = History::open(&dir.join("annadl.db")).unwrap();
+        history.record("https://Libgen.li/get.php?md5=a", true).unwrap();
+        history.record("https://libgen.li/get.php?md5=b", true).unwrap();
+        history.record("https://libgen.li/get.php?md5=c", false).unwrap();
+        history.record("not a url", false).unwrap();
+
+        let records = history.records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records["libgen.li"], HostRecord { successes: 2, failures: 1 });
+        assert_eq!(HostRecord::default().rate(), 0.5);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rank() {
+        let links = vec![
+            link("Mirror", "https://flaky.example/file"),
+            link("IPFS", "https://ipfs.io/ipfs/Qm"),
+            link("LibGen", "https://libgen.li/get.php?md5=a"),
+        ];
+        let history = HashMap::from([("flaky.example".to_string(), HostRecord { successes: 0, failures: 8 })]);
+        let scorer = Scorer::new(ScoreWeights::default(), history);
+        assert_eq!(scorer.rank(&links, &[]), [2, 1, 0]);
+
+        // A fast answer lifts IPFS over a slow LibGen mirror
+        let checked = |secs| Some(LinkHealth { status: Some(200), latency: Duration::from_secs(secs), ..LinkHealth::default() });
+        let weights = ScoreWeights { source: 0.0, history: 0.0, speed: 1.0 };
+        let scorer = Scorer::new(weights, HashMap::new());
+        assert_eq!(scorer.rank(&links, &[checked(1), checked(0), checked(20)]), [1, 0, 2]);
+
+        // Dead links go last whatever their source
+        let dead = Some(LinkHealth { status: Some(404), ..LinkHealth::default() });
+        assert_eq!(Scorer::default().rank(&links[1..], &[None, dead]), [0, 1]);
+        assert_eq!(Scorer::new(ScoreWeights { source: 0.0, history: 0.0, speed: 0.0 }, HashMap::new()).score(&links[2], None), 0.0);
+    }
+}
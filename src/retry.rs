@@ -0,0 +1,283 @@
+//! The retry policy shared by the scraper and downloader: which failures
+//! are worth another attempt, how many attempts and how long to wait.
+
+use crate::config::Config;
+use crate::downloader::DownloadError;
+use crate::scraper::ScrapeError;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Kinds of failure a request can be retried after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryClass {
+    /// The connection couldn't be made or broke off.
+    Connect,
+    /// The request or a read timed out.
+    Timeout,
+    /// HTTP 5xx.
+    ServerError,
+    /// HTTP 429 or a Cloudflare browser check.
+    RateLimited,
+}
+
+impl RetryClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Timeout => "timeout",
+            Self::ServerError => "server_error",
+            Self::RateLimited => "rate_limited",
+        }
+    }
+}
+
+impl FromStr for RetryClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "connect" => Ok(Self::Connect),
+            "timeout" => Ok(Self::Timeout),
+            "server_error" | "5xx" => Ok(Self::ServerError),
+            "rate_limited" | "429" => Ok(Self::RateLimited),
+            other => Err(format!("'{}' is not one of connect, timeout, server_error, rate_limited", other)),
+        }
+    }
+}
+
+/// A read that got no data within the read timeout.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("No data received for {0}s")]
+pub struct Stalled(pub u64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one.
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled for each one after.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Fraction of each wait added at random, from 0 to 1.
+    pub jitter: f64,
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_retries: config.retry_max,
+            base_delay: Duration::from_millis(config.retry_base_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            jitter: if config.retry_jitter.is_finite() { config.retry_jitter.clamp(0.0, 1.0) } else { 0.0 },
+            retry_on: config.retry_on.clone(),
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+
+    pub fn should_retry(&self, error: &anyhow::Error) -> bool {
+        classify(error).is_some_and(|class| self.retry_on.contains(&class))
+    }
+
+    /// Wait before retry number `retry` after `error`: the backoff delay,
+    /// or the `Retry-After` time of a rate limit when longer, plus up to
+    /// `jitter` of the delay at random so parallel requests don't all come
+    /// back at once. None when the site asked for more than `max_delay`.
+    pub fn wait(&self, retry: u32, error: &anyhow::Error) -> Option<Duration> {
+        let delay = self.delay(retry);
+        let asked = retry_after(error).unwrap_or_default();
+        if asked > self.max_delay {
+            return None;
+        }
+        let jitter = delay.mul_f64(self.jitter * rand::random::<f64>());
+        Some(delay.max(asked) + jitter)
+    }
+
+    /// Runs `attempt` until it succeeds, fails in a way the policy doesn't
+    /// retry, or runs out of retries; returns the last result.
+    pub async fn run<T, F, Fut>(&self, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Err(e) if retry < self.max_retries && self.should_retry(&e) => {
+                    let Some(wait) = self.wait(retry, &e) else {
+                        return Err(e);
+                    };
+                    tokio::time::sleep(wait).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// The wait a rate-limited response asked for.
+fn retry_after(error: &anyhow::Error) -> Option<Duration> {
+    error.chain().find_map(|cause| match cause.downcast_ref::<ScrapeError>() {
+        Some(ScrapeError::RateLimited { retry_after: Some(secs) }) => Some(Duration::from_secs(*secs)),
+        _ => None,
+    })
+}
+
+/// Which retry class a failure belongs to, if any.
+pub fn classify(error: &anyhow::Error) -> Option<RetryClass> {
+    let by_status = |status: u16| match status {
+        429 => Some(RetryClass::RateLimited),
+        500..=599 => Some(RetryClass::ServerError),
+        _ => None,
+    };
+    error.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<ScrapeError>() {
+            return match *e {
+                ScrapeError::Blocked { status } | ScrapeError::Http { status, .. } => by_status(status),
+                ScrapeError::RateLimited { .. } | ScrapeError::Challenge { .. } => Some(RetryClass::RateLimited),
+                _ => None,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<DownloadError>() {
+            return match *e {
+                DownloadError::Blocked { status, .. } | DownloadError::Http { status, .. } => by_status(status),
+                _ => None,
+            };
+        }
+        if cause.is::<Stalled>() {
+            return Some(RetryClass::Timeout);
+        }
+        let e = cause.downcast_ref::<reqwest::Error>()?;
+        if e.is_timeout() {
+            Some(RetryClass::Timeout)
+        } else if e.is_connect() || e.is_request() || e.is_body() {
+            Some(RetryClass::Connect)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+            jitter: 0.0,
+            retry_on: vec![RetryClass::Connect, RetryClass::Timeout, RetryClass::ServerError],
+        }
+    }
+
+    #[test]
+    fn test_delay_doubles_up_to_the_ceiling() {
+        let policy = policy(5);
+        let delays: Vec<u64> = (0..5).map(|retry| policy.delay(retry).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_jitter_from_config() {
+        let jitter = |retry_jitter| RetryPolicy::from_config(&Config { retry_jitter, ..Config::default() }).jitter;
+        assert_eq!(jitter(0.25), 0.25);
+        assert_eq!(jitter(3.0), 1.0);
+        assert_eq!(jitter(-1.0), 0.0);
+        assert_eq!(jitter(f64::NAN), 0.0);
+    }
+
+    #[test]
+    fn test_classify() {
+        let server = anyhow::Error::new(ScrapeError::Http { status: 503, url: "u".to_string() });
+        assert_eq!(classify(&server), Some(RetryClass::ServerError));
+        let limited = anyhow::Error::new(DownloadError::Blocked { status: 429, url: "u".to_string() });
+        assert_eq!(classify(&limited), Some(RetryClass::RateLimited));
+        let forbidden = anyhow::Error::new(ScrapeError::Blocked { status: 403 });
+        assert_eq!(classify(&forbidden), None);
+        let challenge = anyhow::Error::new(ScrapeError::Challenge { status: 503 });
+        assert_eq!(classify(&challenge), Some(RetryClass::RateLimited));
+        let stalled = anyhow::Error::new(Stalled(30)).context("Search failed");
+        assert_eq!(classify(&stalled), Some(RetryClass::Timeout));
+        assert_eq!(classify(&anyhow::Error::new(DownloadError::NotFound("u".to_string()))), None);
+        assert_eq!(classify(&anyhow::anyhow!("something else")), None);
+
+        assert!(!policy(2).should_retry(&limited));
+        assert!(policy(2).should_retry(&server));
+    }
+
+    #[test]
+    fn test_retry_class_from_str() {
+        assert_eq!("server-error".parse(), Ok(RetryClass::ServerError));
+        assert_eq!("429".parse(), Ok(RetryClass::RateLimited));
+        assert!("sometimes".parse::<RetryClass>().is_err());
+    }
+
+    #[test]
+    fn test_wait_respects_retry_after_with_jitter() {
+        let mut policy = policy(3);
+        policy.retry_on.push(RetryClass::RateLimited);
+        let server = anyhow::Error::new(ScrapeError::Http { status: 503, url: "u".to_string() });
+        assert_eq!(policy.wait(1, &server), Some(Duration::from_millis(1000)));
+
+        policy.jitter = 0.5;
+        let wait = policy.wait(1, &server).unwrap();
+        assert!((Duration::from_millis(1000)..=Duration::from_millis(1500)).contains(&wait), "{:?}", wait);
+
+        let limited = |retry_after| anyhow::Error::new(ScrapeError::RateLimited { retry_after });
+        for _ in 0..20 {
+            let wait = policy.wait(1, &limited(None)).unwrap();
+            assert!((Duration::from_millis(1000)..=Duration::from_millis(1500)).contains(&wait), "{:?}", wait);
+            let wait = policy.wait(0, &limited(Some(2))).unwrap();
+            assert!((Duration::from_millis(2000)..=Duration::from_millis(2250)).contains(&wait), "{:?}", wait);
+        }
+        // Longer than the policy is willing to wait
+        assert_eq!(policy.wait(0, &limited(Some(60))), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_retries_transient_failures() {
+        let calls = AtomicU32::new(0);
+        let started = tokio::time::Instant::now();
+        let result = policy(3).run(|| async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(anyhow::Error::new(Stalled(30))),
+                _ => Ok("page"),
+            }
+        }).await;
+        assert_eq!(result.unwrap(), "page");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(started.elapsed(), Duration::from_millis(1500));
+
+        // Out of retries
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<()> = policy(1).run(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow::Error::new(Stalled(30)))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Not retryable
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<()> = policy(3).run(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow::Error::new(ScrapeError::NotFound("u".to_string())))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Saved searches: a query and its filters stored under a name, so recurring
+//! searches can be rerun from `annadl saved run` or the TUI, or watched for
+//! new books with `annadl saved watch`.
+
+use crate::scraper::{SearchFilters, SearchIndex, SortOrder};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    pub filters: SearchFilters,
+    /// Unix time the search was first saved; None until it is.
+    pub saved_at: Option<i64>,
+}
+
+/// The SQLite database holding saved searches.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens the database in the user's data directory, creating it on
+    /// first use.
+    pub fn open_default() -> Result<Self> {
+        Self::open(&default_path())
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        // Filters are kept as JSON so new filter fields need no migration
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS saved_searches (
+                name TEXT PRIMARY KEY,
+                query TEXT NOT NULL,
+                filters TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            CREATE TABLE IF NOT EXISTS seen_md5 (
+                search TEXT NOT NULL,
+                md5 TEXT NOT NULL,
+                seen_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (search, md5)
+            );
+            CREATE TABLE IF NOT EXISTS watched_searches (
+                name TEXT PRIMARY KEY,
+                last_run INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+        )
+        .context("Failed to set up the saved searches table")?;
+        Ok(Self { conn })
+    }
+
+    /// Saves `search`, replacing any search with the same name.
+    pub fn save(&self, search: &SavedSearch) -> Result<()> {
+        let filters = serde_json::to_string(&search.filters)?;
+        self.conn.execute(
+            "INSERT INTO saved_searches (name, query, filters) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET query = excluded.query, filters = excluded.filters",
+            params![search.name, search.query, filters],
+        )
+        .context("Failed to save the search")?;
+        Ok(())
+    }
+
+    /// Every saved search, by name.
+    pub fn list(&self) -> Result<Vec<SavedSearch>> {
+        let mut statement = self.conn.prepare("SELECT name, query, filters, created_at FROM saved_searches ORDER BY name")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?, row.get(3)?)))?;
+        rows.map(|row| {
+            let (name, query, filters, saved_at) = row?;
+            Ok(SavedSearch { name, query, filters: parse_filters(&filters), saved_at: Some(saved_at) })
+        })
+        .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<SavedSearch>> {
+        let row: Option<(String, String, String, i64)> = self.conn
+            .query_row(
+                "SELECT name, query, filters, created_at FROM saved_searches WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        Ok(row.map(|(name, query, filters, saved_at)| SavedSearch { name, query, filters: parse_filters(&filters), saved_at: Some(saved_at) }))
+    }
+
+    /// Deletes the named search and what its watch has seen; false when
+    /// there was none.
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        self.conn.execute("DELETE FROM seen_md5 WHERE search = ?1", params![name])?;
+        self.conn.execute("DELETE FROM watched_searches WHERE name = ?1", params![name])?;
+        let deleted = self.conn.execute("DELETE FROM saved_searches WHERE name = ?1", params![name])?;
+        Ok(deleted > 0)
+    }
+
+    /// Whether the named search has been watched before, so its results
+    /// have something to be new against, even if it found nothing then.
+    pub fn watched(&self, name: &str) -> Result<bool> {
+        let last_run: Option<i64> = self.conn
+            .query_row("SELECT last_run FROM watched_searches WHERE name = ?1", params![name], |row| row.get(0))
+            .optional()?;
+        Ok(last_run.is_some())
+    }
+
+    /// Records a watch run of the named search that found `md5s`, returning
+    /// the ones it hadn't seen before.
+    pub fn record_run(&self, name: &str, md5s: &[&str]) -> Result<Vec<String>> {
+        self.conn.execute(
+            "INSERT INTO watched_searches (name) VALUES (?1)
+             ON CONFLICT(name) DO UPDATE SET last_run = strftime('%s', 'now')",
+            params![name],
+        )
+        .context("Failed to record the watch run")?;
+        let mut statement = self.conn.prepare("INSERT OR IGNORE INTO seen_md5 (search, md5) VALUES (?1, ?2)")?;
+        let mut new = Vec::new();
+        for md5 in md5s {
+            if statement.execute(params![name, md5]).context("Failed to record seen results")? > 0 {
+                new.push(md5.to_string());
+            }
+        }
+        Ok(new)
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("anna-dl")
+        .join("annadl.db")
+}
+
+/// Filters written by an older or newer version fall back to no filters
+/// rather than hiding the search.
+fn parse_filters(json: &str) -> SearchFilters {
+    serde_json::from_str(json).unwrap_or_default()
+}
+
+/// One-line summary of the filters, e.g. `epub, de, < 5MB`.
+pub fn describe(filters: &SearchFilters) -> String {
+    let mut parts = Vec::new();
+    if let Some(ref format) = filters.format {
+        parts.push(format.clone());
+    }
+    if let Some(ref language) = filters.language {
+        parts.push(language.clone());
+    }
+    if let Some(size) = filters.max_size_mb {
+        parts.push(format!("< {}MB", size));
+    }
+    if !filters.content.is_empty() {
+        parts.push(filters.content.iter().map(|c| c.name()).collect::<Vec<_>>().join("/"));
+    }
+    if filters.index != SearchIndex::Books {
+        parts.push(format!("{} index", filters.index.as_str()));
+    }
+    if filters.sort != SortOrder::Relevance {
+        parts.push(format!("{} first", filters.sort.as_str()));
+    }
+    if filters.magazines {
+        parts.push("magazines".to_string());
+    }
+    if let Some(year) = filters.issue_year {
+        parts.push(format!("issue year {}", year));
+    }
+    if let Some(month) = filters.issue_month {
+        parts.push(format!("issue month {}", month));
+    }
+    if parts.is_empty() {
+        "no filters".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{ContentType, SortOrder};
+
+    fn temp_db() -> PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("annadl_saved_{}", nanos)).join("annadl.db")
+    }
+
+    fn discworld() -> SavedSearch {
+        SavedSearch {
+            name: "discworld-de".to_string(),
+            query: "Scheibenwelt Pratchett".to_string(),
+            filters: SearchFilters {
+                format: Some("epub".to_string()),
+                language: Some("de".to_string()),
+                ..Default::default()
+            },
+            saved_at: None,
+        }
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let path = temp_db();
+        let store = Store::open(&path).unwrap();
+        assert!(store.list().unwrap().is_empty());
+
+        store.save(&discworld()).unwrap();
+        store.save(&SavedSearch {
+            name: "wired".to_string(),
+            query: "wired".to_string(),
+            filters: SearchFilters { magazines: true, issue_year: Some(2019), ..Default::default() },
+            saved_at: None,
+        }).unwrap();
+        let mut loaded = store.get("discworld-de").unwrap().unwrap();
+        assert!(loaded.saved_at.take().is_some());
+        assert_eq!(loaded, discworld());
+        assert_eq!(store.get("missing").unwrap(), None);
+
+        // Saving under an existing name replaces it
+        let mut updated = discworld();
+        updated.filters.format = Some("pdf".to_string());
+        store.save(&updated).unwrap();
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["discworld-de", "wired"]);
+        assert_eq!(store.get("discworld-de").unwrap().unwrap().filters.format.as_deref(), Some("pdf"));
+
+        // Reopening keeps what was saved
+        drop(store);
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.list().unwrap().len(), 2);
+        assert!(store.remove("wired").unwrap());
+        assert!(!store.remove("wired").unwrap());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_seen_md5() {
+        let path = temp_db();
+        let store = Store::open(&path).unwrap();
+        store.save(&discworld()).unwrap();
+        assert!(!store.watched("discworld-de").unwrap());
+
+        let md5s = ["5c1b4ee5d18a5c6a9e047fa74b8f37d1", "d41d8cd98f00b204e9800998ecf8427e"];
+        assert_eq!(store.record_run("discworld-de", &md5s).unwrap().len(), 2);
+        assert!(store.watched("discworld-de").unwrap());
+        assert_eq!(store.record_run("discworld-de", &md5s[1..]).unwrap(), Vec::<String>::new());
+        let newer = "0123456789abcdef0123456789abcdef";
+        assert_eq!(store.record_run("discworld-de", &[md5s[0], newer]).unwrap(), vec![newer]);
+        // Each search keeps its own set, and a run that found nothing
+        // still counts as watched
+        assert!(!store.watched("wired").unwrap());
+        assert!(store.record_run("wired", &[]).unwrap().is_empty());
+        assert!(store.watched("wired").unwrap());
+
+        // A removed search starts over when saved again
+        store.remove("discworld-de").unwrap();
+        assert!(!store.watched("discworld-de").unwrap());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_filters_tolerates_unknown_json() {
+        let filters = parse_filters(r#"{"format": "epub", "sort": "newest", "collection": "fiction"}"#);
+        assert_eq!(filters.format.as_deref(), Some("epub"));
+        assert_eq!(filters.sort, SortOrder::Newest);
+        assert_eq!(parse_filters("not json"), SearchFilters::default());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(&SearchFilters::default()), "no filters");
+        assert_eq!(describe(&discworld().filters), "epub, de");
+        let filters = SearchFilters { max_size_mb: Some(5.0), magazines: true, ..Default::default() };
+        assert_eq!(describe(&filters), "< 5MB, magazines");
+        let filters = SearchFilters { content: vec![ContentType::Fiction, ContentType::Comic], ..Default::default() };
+        assert_eq!(describe(&filters), "fiction/comic");
+        let filters = SearchFilters { sort: SortOrder::Newest, index: SearchIndex::Journals, ..Default::default() };
+        assert_eq!(describe(&filters), "journals index, newest first");
+    }
+}
@@ -0,0 +1,283 @@
+//! The JSON shapes printed by `--json`, kept separate from the internal
+//! `Book` and `DownloadLink` so those can change without breaking scripts.
+//!
+//! Every top-level document carries `schema_version`. Adding a field keeps
+//! the version; renaming, removing or retyping one bumps it.
+
+use crate::scraper::{Book, BookDetails, DownloadLink, Format, Identifiers, Issue, LanguageCode, LinkKind, Metadata, ResultCount};
+use serde::{Deserialize, Serialize};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A book. Unknown values are `null`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookJson {
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<u16>,
+    /// ISO 639 code, lowercase, e.g. `"en"`.
+    pub language: Option<String>,
+    /// File extension, lowercase, e.g. `"epub"`.
+    pub format: Option<String>,
+    pub size_bytes: Option<u64>,
+    /// Lowercase hex MD5 of the file.
+    pub md5: Option<String>,
+    /// The book's page on Anna's Archive.
+    pub url: String,
+    /// Volume, number, year and month of a magazine issue, read from the
+    /// title.
+    #[serde(default)]
+    pub issue: Option<Issue>,
+    /// Filled in by Open Library enrichment, when enabled.
+    #[serde(default)]
+    pub series: Option<String>,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    /// ISBN-13, ISBN-10, ASIN, OCLC and DOI values from the book page.
+    #[serde(default)]
+    pub identifiers: Identifiers,
+    /// From the book page, so only known once it has been fetched.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub publisher: Option<String>,
+    #[serde(default)]
+    pub edition: Option<String>,
+    #[serde(default)]
+    pub pages: Option<u32>,
+    #[serde(default)]
+    pub cover_url: Option<String>,
+}
+
+impl From<&Book> for BookJson {
+    fn from(book: &Book) -> Self {
+        Self {
+            title: book.title.clone(),
+            author: book.author.clone(),
+            year: book.year,
+            language: book.language.as_ref().map(|l| l.as_str().to_string()),
+            format: book.format.as_ref().map(|f| f.extension().to_string()),
+            size_bytes: book.size_bytes,
+            md5: book.md5.clone(),
+            url: book.url.clone(),
+            issue: book.issue(),
+            series: book.metadata.series.clone(),
+            subjects: book.metadata.subjects.clone(),
+            identifiers: book.metadata.identifiers.clone(),
+            description: book.metadata.details.description.clone(),
+            publisher: book.metadata.details.publisher.clone(),
+            edition: book.metadata.details.edition.clone(),
+            pages: book.metadata.details.pages,
+            cover_url: book.metadata.details.cover_url.clone(),
+        }
+    }
+}
+
+impl From<BookJson> for Book {
+    fn from(json: BookJson) -> Self {
+        Self {
+            title: json.title,
+            author: json.author,
+            year: json.year,
+            language: json.language.as_deref().and_then(LanguageCode::new),
+            format: json.format.map(Format::from),
+            size_bytes: json.size_bytes,
+            md5: json.md5,
+            url: json.url,
+            metadata: Metadata {
+                series: json.series,
+                subjects: json.subjects,
+                identifiers: json.identifiers,
+                details: BookDetails {
+                    description: json.description,
+                    publisher: json.publisher,
+                    edition: json.edition,
+                    pages: json.pages,
+                    cover_url: json.cover_url,
+                },
+                periodical: json.issue.is_some(),
+            },
+        }
+    }
+}
+
+/// A place the file can be downloaded from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadLinkJson {
+    pub url: String,
+    /// Link text as shown on the book page.
+    pub label: String,
+    /// Mirror family, e.g. `"LibGen"`.
+    pub source: String,
+    /// `"http"` for a mirror, `"torrent"` or `"magnet"` for a torrent client.
+    #[serde(default)]
+    pub kind: LinkKind,
+}
+
+impl From<&DownloadLink> for DownloadLinkJson {
+    fn from(link: &DownloadLink) -> Self {
+        Self {
+            url: link.url.clone(),
+            label: link.text.clone(),
+            source: link.source.clone(),
+            kind: link.kind,
+        }
+    }
+}
+
+/// Output of a search.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchJson {
+    pub schema_version: u32,
+    pub query: String,
+    /// The site's overall match count, when shown.
+    pub total: Option<u64>,
+    /// Whether `total` is a lower bound or estimate.
+    pub total_approximate: bool,
+    pub books: Vec<BookJson>,
+}
+
+impl SearchJson {
+    pub fn new(query: &str, books: &[Book], total: Option<ResultCount>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            query: query.to_string(),
+            total: total.map(|t| t.count),
+            total_approximate: total.is_some_and(|t| t.approximate),
+            books: books.iter().map(BookJson::from).collect(),
+        }
+    }
+}
+
+/// Output for a single book opened by link or MD5.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookDetailsJson {
+    pub schema_version: u32,
+    pub book: BookJson,
+    pub links: Vec<DownloadLinkJson>,
+    /// Other files of the same work listed on the book page.
+    #[serde(default)]
+    pub editions: Vec<BookJson>,
+}
+
+impl BookDetailsJson {
+    pub fn new(book: &Book, links: &[DownloadLink], editions: &[Book]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            book: book.into(),
+            links: links.iter().map(DownloadLinkJson::from).collect(),
+            editions: editions.iter().map(BookJson::from).collect(),
+        }
+    }
+}
+
+/// Output of `annadl lookup`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LookupJson {
+    pub schema_version: u32,
+    pub books: Vec<BookJson>,
+}
+
+/// Body of the webhook `annadl saved watch` posts when a saved search
+/// finds books it hadn't seen before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchJson {
+    pub schema_version: u32,
+    /// The saved search's name.
+    pub search: String,
+    pub query: String,
+    /// Only the new books.
+    pub books: Vec<BookJson>,
+}
+
+impl WatchJson {
+    pub fn new(search: &str, query: &str, books: &[Book]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            search: search.to_string(),
+            query: query.to_string(),
+            books: books.iter().map(BookJson::from).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_book() -> Book {
+        Book {
+            title: "Dune".to_string(),
+            author: Some("Frank Herbert".to_string()),
+            year: Some(1965),
+            language: LanguageCode::new("en"),
+            format: Some(Format::Epub),
+            size_bytes: Some(1572864),
+            md5: Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+            url: "https://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_search_json_is_stable() {
+        let total = ResultCount { count: 1200, approximate: true };
+        let json = serde_json::to_value(SearchJson::new("dune", &[sample_book()], Some(total))).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "schema_version": 1,
+            "query": "dune",
+            "total": 1200,
+            "total_approximate": true,
+            "books": [{
+                "title": "Dune",
+                "author": "Frank Herbert",
+                "year": 1965,
+                "language": "en",
+                "format": "epub",
+                "size_bytes": 1572864,
+                "md5": "d41d8cd98f00b204e9800998ecf8427e",
+                "url": "https://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e",
+                "issue": null,
+                "series": null,
+                "subjects": [],
+                "identifiers": {"isbn13": [], "isbn10": [], "asin": [], "oclc": [], "doi": []},
+                "description": null,
+                "publisher": null,
+                "edition": null,
+                "pages": null,
+                "cover_url": null
+            }]
+        }));
+    }
+
+    #[test]
+    fn test_book_details_json() {
+        let link = DownloadLink {
+            text: "Libgen.li".to_string(),
+            url: "https://libgen.li/file".to_string(),
+            source: "LibGen".to_string(),
+            kind: LinkKind::Http,
+        };
+        let mut edition = sample_book();
+        edition.format = Some(Format::Pdf);
+        let json = serde_json::to_value(BookDetailsJson::new(&sample_book(), &[link], &[edition])).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["links"], serde_json::json!([
+            {"url": "https://libgen.li/file", "label": "Libgen.li", "source": "LibGen", "kind": "http"}
+        ]));
+        assert_eq!(json["editions"][0]["format"], "pdf");
+    }
+
+    #[test]
+    fn test_book_json_round_trip() {
+        let mut book = sample_book();
+        book.metadata.details.publisher = Some("Ace".to_string());
+        book.metadata.details.pages = Some(604);
+        let json: BookJson = serde_json::from_str(&serde_json::to_string(&BookJson::from(&book)).unwrap()).unwrap();
+        let restored = Book::from(json);
+        assert_eq!(restored.format, book.format);
+        assert_eq!(restored.language, book.language);
+        assert_eq!(restored.md5, book.md5);
+        assert_eq!(restored.metadata.details, book.metadata.details);
+    }
+}
@@ -1,108 +1,1842 @@
+use crate::account::{self, AccountStatus, Credentials};
+use crate::config::Config;
+use crate::http::{self, UserAgents};
+use crate::libgen::LibgenScraper;
+use crate::locale;
+use crate::retry::{self, RetryPolicy, Stalled};
+use crate::source::SourceProvider;
 use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures::StreamExt;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
 
-#[derive(Debug, Clone, Default)]
+/// A regex compiled the first time this call site runs rather than on every
+/// call; None if the pattern doesn't compile.
+macro_rules! regex {
+    ($pattern:expr) => {{
+        static RE: std::sync::LazyLock<Option<regex::Regex>> =
+            std::sync::LazyLock::new(|| regex::Regex::new($pattern).ok());
+        RE.as_ref()
+    }};
+}
+
+/// A CSS selector parsed once per call site, like `regex!`.
+macro_rules! selector {
+    ($css:expr) => {{
+        static SELECTOR: std::sync::LazyLock<Option<Selector>> =
+            std::sync::LazyLock::new(|| Selector::parse($css).ok());
+        SELECTOR.as_ref()
+    }};
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SearchFilters {
+    /// Extensions or groups such as "epub,mobi" or "comics".
     pub format: Option<String>,
+    /// Language codes such as "en" or "en,de".
     pub language: Option<String>,
     pub max_size_mb: Option<f64>,
+    /// Kinds of record to search; empty searches all of them.
+    pub content: Vec<ContentType>,
+    pub sort: SortOrder,
+    pub index: SearchIndex,
+    /// Restricts the search to magazines and periodicals.
+    pub magazines: bool,
+    pub issue_year: Option<u16>,
+    pub issue_month: Option<u8>,
+}
+
+impl SearchFilters {
+    /// Whether `book` passes the filters the site can't apply: size and
+    /// issue date. Books of unknown size are kept.
+    pub fn matches(&self, book: &Book) -> bool {
+        let fits = self.max_size_mb.is_none_or(|max_mb| {
+            book.size_bytes.is_none_or(|bytes| bytes as f64 <= max_mb * 1024.0 * 1024.0)
+        });
+        fits && self.matches_issue(book)
+    }
+
+    /// Whether the search is limited to magazines or comics.
+    pub fn periodicals(&self) -> bool {
+        self.magazines
+            || self.index.content().is_some()
+            || self.content.iter().any(|kind| matches!(kind, ContentType::Magazine | ContentType::Comic))
+    }
+
+    /// Whether `book` matches the issue year and month filters. Books whose
+    /// issue can't be read never match a set filter.
+    fn matches_issue(&self, book: &Book) -> bool {
+        if self.issue_year.is_none() && self.issue_month.is_none() {
+            return true;
+        }
+        let issue = book.issue();
+        let year = issue.as_ref().and_then(|i| i.year).or(book.year);
+        let month = issue.and_then(|i| i.month);
+        self.issue_year.is_none_or(|y| year == Some(y))
+            && self.issue_month.is_none_or(|m| month == Some(m))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Book {
     pub title: String,
     pub author: Option<String>,
-    pub year: Option<String>,
-    pub language: Option<String>,
-    pub format: Option<String>,
-    pub size: Option<String>,
+    pub year: Option<u16>,
+    pub language: Option<LanguageCode>,
+    pub format: Option<Format>,
+    pub size_bytes: Option<u64>,
+    /// Identifies the file; None for results that don't link to an MD5 page.
+    pub md5: Option<String>,
     pub url: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+/// Details the search listing doesn't carry, filled in from other sources
+/// such as Open Library.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub series: Option<String>,
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub identifiers: Identifiers,
+    #[serde(default)]
+    pub details: BookDetails,
+    /// Set on magazine and comic results, the only ones whose titles are
+    /// read for issue details.
+    #[serde(default)]
+    pub periodical: bool,
+}
+
+/// What a book's page says about the book beyond the listing. Its ISBNs
+/// are kept with the other identifiers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookDetails {
+    pub description: Option<String>,
+    pub publisher: Option<String>,
+    pub edition: Option<String>,
+    pub pages: Option<u32>,
+    pub cover_url: Option<String>,
+}
+
+impl BookDetails {
+    /// Fills in the values missing here from `other`.
+    pub fn merge(&mut self, other: BookDetails) {
+        let BookDetails { description, publisher, edition, pages, cover_url } = other;
+        self.description = self.description.take().or(description);
+        self.publisher = self.publisher.take().or(publisher);
+        self.edition = self.edition.take().or(edition);
+        self.pages = self.pages.or(pages);
+        self.cover_url = self.cover_url.take().or(cover_url);
+    }
+
+    /// Publisher, edition and page count, e.g. "Ace, 40th anniversary
+    /// edition, 604 pages"; None when none are known.
+    pub fn summary(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            self.publisher.clone(),
+            self.edition.clone(),
+            self.pages.map(|n| format!("{} pages", n)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+/// Catalogue identifiers shown on a book page. ISBNs are digits only, with
+/// valid check digits.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Identifiers {
+    pub isbn13: Vec<String>,
+    pub isbn10: Vec<String>,
+    pub asin: Vec<String>,
+    pub oclc: Vec<String>,
+    pub doi: Vec<String>,
+}
+
+impl Identifiers {
+    pub fn is_empty(&self) -> bool {
+        self.kinds().iter().all(|(_, values)| values.is_empty())
+    }
+
+    /// The ISBN most likely to find the book elsewhere, preferring ISBN-13.
+    pub fn isbn(&self) -> Option<&str> {
+        self.isbn13.first().or(self.isbn10.first()).map(String::as_str)
+    }
+
+    /// Adds the values of `other` not already present.
+    pub fn merge(&mut self, other: Identifiers) {
+        let Identifiers { isbn13, isbn10, asin, oclc, doi } = other;
+        for (mine, theirs) in [
+            (&mut self.isbn13, isbn13),
+            (&mut self.isbn10, isbn10),
+            (&mut self.asin, asin),
+            (&mut self.oclc, oclc),
+            (&mut self.doi, doi),
+        ] {
+            for value in theirs {
+                if !mine.contains(&value) {
+                    mine.push(value);
+                }
+            }
+        }
+    }
+
+    fn kinds(&self) -> [(&'static str, &Vec<String>); 5] {
+        [
+            ("ISBN", &self.isbn13),
+            ("ISBN", &self.isbn10),
+            ("ASIN", &self.asin),
+            ("OCLC", &self.oclc),
+            ("DOI", &self.doi),
+        ]
+    }
+}
+
+impl std::fmt::Display for Identifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self.kinds().iter()
+            .flat_map(|(label, values)| values.iter().map(move |v| format!("{} {}", label, v)))
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+fn isbn10_valid(digits: &str) -> bool {
+    let chars: Vec<char> = digits.chars().collect();
+    if chars.len() != 10 || !chars[..9].iter().all(char::is_ascii_digit) {
+        return false;
+    }
+    let check = match chars[9] {
+        'X' | 'x' => 10,
+        c => match c.to_digit(10) {
+            Some(d) => d,
+            None => return false,
+        },
+    };
+    let sum: u32 = chars[..9].iter()
+        .enumerate()
+        .map(|(i, c)| (10 - i as u32) * c.to_digit(10).unwrap_or(0))
+        .sum();
+    (sum + check).is_multiple_of(11)
+}
+
+fn isbn13_valid(digits: &str) -> bool {
+    if digits.len() != 13 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = digits.chars()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).unwrap_or(0) * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Sorts a raw identifier into its kind, normalizing and validating it.
+/// `kind` uses the archive's names: "isbn13", "isbn10", "asin", "oclc" or
+/// "doi".
+fn push_identifier(identifiers: &mut Identifiers, kind: &str, value: &str) {
+    let value = value.trim();
+    let (list, value) = match kind {
+        "isbn13" | "isbn10" => {
+            let digits: String = value.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase();
+            if isbn13_valid(&digits) {
+                (&mut identifiers.isbn13, digits)
+            } else if isbn10_valid(&digits) {
+                (&mut identifiers.isbn10, digits)
+            } else {
+                return;
+            }
+        }
+        "asin" => (&mut identifiers.asin, value.to_uppercase()),
+        "oclc" => (&mut identifiers.oclc, value.to_string()),
+        "doi" => (&mut identifiers.doi, value.trim_end_matches(['.', ',', ';', ')']).to_string()),
+        _ => return,
+    };
+    if !value.is_empty() && !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Finds labelled ISBN, ASIN, OCLC and DOI values in a book page's text.
+pub fn parse_identifiers(text: &str) -> Identifiers {
+    let patterns = [
+        ("isbn13", regex!(r"(?i)\bISBN(?:-?1[03])?\s*:?\s*([\dX][\dX-]{8,15}[\dX])\b")),
+        ("isbn13", regex!(r"\b(97[89](?:-?\d){10})\b")),
+        ("asin", regex!(r"(?i)\bASIN\s*:?\s*([A-Z0-9]{10})\b")),
+        ("oclc", regex!(r"(?i)\b(?:OCLC|WorldCat)(?:\s+(?:number|no\.?|#))?\s*:?\s*(\d{4,12})\b")),
+        ("doi", regex!(r"\b(10\.\d{4,9}/[^\s\x22<>]+)")),
+    ];
+    let mut identifiers = Identifiers::default();
+    for (kind, re) in patterns {
+        let Some(re) = re else {
+            continue;
+        };
+        for caps in re.captures_iter(text) {
+            push_identifier(&mut identifiers, kind, &caps[1]);
+        }
+    }
+    identifiers
+}
+
+/// The ISBN-13 for a valid ISBN-10.
+fn isbn10_to_13(isbn10: &str) -> String {
+    let digits = format!("978{}", &isbn10[..9]);
+    let sum: u32 = digits.chars()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).unwrap_or(0) * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    format!("{}{}", digits, (10 - sum % 10) % 10)
+}
+
+/// The ISBN-10 for an ISBN-13 in the 978 range; 979 ISBNs have none.
+fn isbn13_to_10(isbn13: &str) -> Option<String> {
+    let body = isbn13.strip_prefix("978")?.get(..9)?;
+    let sum: u32 = body.chars()
+        .enumerate()
+        .map(|(i, c)| (10 - i as u32) * c.to_digit(10).unwrap_or(0))
+        .sum();
+    let check = match (11 - sum % 11) % 11 {
+        10 => 'X',
+        d => char::from_digit(d, 10)?,
+    };
+    Some(format!("{}{}", body, check))
+}
+
+/// A search query that is an ISBN or DOI rather than words, such as
+/// "978-0-441-17271-9", "ISBN 0441172717" or "doi:10.1038/nature14539".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryIdentifier {
+    /// ISBN-10s are converted to ISBN-13.
+    Isbn(String),
+    /// Lowercased; DOIs are case-insensitive.
+    Doi(String),
+}
+
+impl QueryIdentifier {
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim().to_lowercase();
+        let doi = ["https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "doi.org/", "doi:"]
+            .iter()
+            .find_map(|prefix| query.strip_prefix(prefix))
+            .unwrap_or(&query)
+            .trim();
+        if regex!(r"^10\.\d{4,9}/\S+$")?.is_match(doi) {
+            return Some(Self::Doi(doi.to_string()));
+        }
+
+        let isbn_re = regex!(r"^(?:isbn(?:-?1[03])?\s*:?\s*)?([\dx][\dx -]{8,15}[\dx])$")?;
+        let digits: String = isbn_re.captures(&query)?[1]
+            .chars()
+            .filter(|c| !matches!(c, '-' | ' '))
+            .collect::<String>()
+            .to_uppercase();
+        if isbn13_valid(&digits) {
+            Some(Self::Isbn(digits))
+        } else if isbn10_valid(&digits) {
+            Some(Self::Isbn(isbn10_to_13(&digits)))
+        } else {
+            None
+        }
+    }
+
+    /// The search term that matches the identifier exactly rather than as
+    /// loose words and numbers.
+    pub fn search_term(&self) -> String {
+        match self {
+            Self::Isbn(isbn) => format!("isbn13:{}", isbn),
+            Self::Doi(doi) => format!("doi:{}", doi),
+        }
+    }
+
+    /// Whether `book` is known to carry this identifier.
+    pub fn matches(&self, book: &Book) -> bool {
+        let identifiers = &book.metadata.identifiers;
+        match self {
+            Self::Isbn(isbn) => identifiers.isbn13.contains(isbn)
+                || isbn13_to_10(isbn).is_some_and(|isbn10| identifiers.isbn10.contains(&isbn10)),
+            Self::Doi(doi) => identifiers.doi.iter().any(|d| d.eq_ignore_ascii_case(doi)),
+        }
+    }
+}
+
+impl Book {
+    /// A placeholder for a book known only by its MD5, with its page on the
+    /// site at `base_url`.
+    pub fn from_md5(base_url: &str, md5: &str) -> Self {
+        Self {
+            title: md5.to_string(),
+            author: None,
+            year: None,
+            language: None,
+            format: None,
+            size_bytes: None,
+            md5: Some(md5.to_string()),
+            url: format!("{}/md5/{}", base_url, md5),
+            metadata: Metadata::default(),
+        }
+    }
+
+    pub fn md5(&self) -> Option<&str> {
+        self.md5.as_deref()
+    }
+
+    /// What tells two results apart: the MD5 when known, since the same
+    /// file is listed under another host after a mirror switch, else the URL.
+    pub fn id(&self) -> &str {
+        self.md5().unwrap_or(&self.url)
+    }
+
+    pub fn display_year(&self) -> String {
+        self.year.map_or_else(|| "Unknown".to_string(), |y| y.to_string())
+    }
+
+    pub fn display_language(&self) -> String {
+        self.language.as_ref().map_or_else(|| "Unknown".to_string(), |l| l.to_string())
+    }
+
+    pub fn display_format(&self) -> String {
+        self.format.as_ref().map_or_else(|| "Unknown".to_string(), |f| f.to_string())
+    }
+
+    pub fn display_size(&self) -> String {
+        self.size_bytes.map_or_else(|| "Unknown".to_string(), |bytes| locale::current().size(bytes))
+    }
+
+    /// The name a download is saved under, "Title - Author.ext". Without a
+    /// known format the downloader infers the extension.
+    pub fn file_name(&self) -> String {
+        let mut name = format!("{} - {}", self.title.chars().take(50).collect::<String>(), self.author.as_deref().unwrap_or("Unknown"));
+        if let Some(ref format) = self.format {
+            name.push('.');
+            name.push_str(format.extension());
+        }
+        name
+    }
+
+    /// Issue details read from a periodical's title, e.g. "Wired No. 12
+    /// (December 2019)". The listed year stands in when the title has none.
+    /// None for books that aren't magazines or comics.
+    pub fn issue(&self) -> Option<Issue> {
+        if !self.metadata.periodical {
+            return None;
+        }
+        let mut issue = parse_issue(&self.title)?;
+        issue.year = issue.year.or(self.year);
+        Some(issue)
+    }
+}
+
+/// The volume, number and date of a magazine issue.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Issue {
+    pub volume: Option<u32>,
+    pub number: Option<u32>,
+    pub year: Option<u16>,
+    pub month: Option<u8>,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(volume) = self.volume {
+            parts.push(format!("Vol. {}", volume));
+        }
+        if let Some(number) = self.number {
+            parts.push(format!("No. {}", number));
+        }
+        let month = self.month.and_then(|m| (m as usize).checked_sub(1).and_then(|i| MONTHS.get(i)));
+        match (month, self.year) {
+            (Some(month), Some(year)) => parts.push(format!("{} {}", month, year)),
+            (Some(month), None) => parts.push(month.to_string()),
+            (None, Some(year)) => parts.push(year.to_string()),
+            (None, None) => {}
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Reads a month given as 1-12, a name or a three-letter abbreviation.
+pub fn parse_month(input: &str) -> Option<u8> {
+    let input = input.trim().trim_end_matches('.').to_lowercase();
+    if let Ok(n) = input.parse::<u8>() {
+        return (1..=12).contains(&n).then_some(n);
+    }
+    if input.len() < 3 {
+        return None;
+    }
+    MONTHS.iter()
+        .position(|m| m.to_lowercase().starts_with(&input) || (input == "sept" && *m == "September"))
+        .map(|i| i as u8 + 1)
+}
+
+/// Finds the volume, issue number and date in a periodical title. None when
+/// the title has none of them, i.e. it isn't recognisably an issue.
+pub fn parse_issue(title: &str) -> Option<Issue> {
+    let number_re = regex!(r"(?i)(?:\bno\.?|\bnr\.?|\bissue|#)\s*(\d{1,4})\b")?;
+    let volume_re = regex!(r"(?i)\bvol(?:ume|\.)?\s*(\d{1,4})\b")?;
+    let iso_re = regex!(r"\b((?:19|20)\d{2})-(\d{2})\b")?;
+    let named_re = regex!(r"(?i)\b([a-z]{3,9})\.?,?\s+((?:19|20)\d{2})\b")?;
+
+    let mut issue = Issue {
+        volume: volume_re.captures(title).and_then(|c| c[1].parse().ok()),
+        number: number_re.captures(title).and_then(|c| c[1].parse().ok()),
+        ..Issue::default()
+    };
+    if let Some(caps) = iso_re.captures(title).filter(|c| parse_month(&c[2]).is_some()) {
+        issue.year = caps[1].parse().ok();
+        issue.month = parse_month(&caps[2]);
+    } else if let Some(caps) = named_re.captures_iter(title).find(|c| parse_month(&c[1]).is_some()) {
+        issue.year = caps[2].parse().ok();
+        issue.month = parse_month(&caps[1]);
+    }
+
+    if issue.volume.is_none() && issue.number.is_none() && issue.month.is_none() {
+        return None;
+    }
+    Some(issue)
+}
+
+/// The MD5 from an `/md5/<hash>` book URL.
+fn md5_from_url(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("/md5/")?;
+    let hash = rest.split(['/', '?', '#']).next()?;
+    is_md5(hash).then(|| hash.to_lowercase())
+}
+
+/// The markup following a book page's other-editions heading, up to the
+/// next heading.
+fn editions_section(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let candidates = selector!("h2, h3, h4, div, span, p")?;
+    let headings = selector!("h1, h2, h3, h4")?;
+    let label = regex!(r"(?i)^(?:other editions|same title|other files|other versions)\s*(?:\(\d+\)|\d+)?\s*:?$")?;
+    let is_heading = |element: &scraper::ElementRef| {
+        let text = element.text().collect::<Vec<_>>().join(" ");
+        label.is_match(text.trim())
+    };
+    // The innermost match, so a wrapper around the heading isn't taken for it
+    let heading = document.select(candidates).find(|element| {
+        is_heading(element) && !element.children().filter_map(scraper::ElementRef::wrap).any(|child| is_heading(&child))
+    })?;
+    let section: String = heading.next_siblings()
+        .filter_map(scraper::ElementRef::wrap)
+        .take_while(|sibling| !headings.matches(sibling))
+        .map(|sibling| sibling.html())
+        .collect();
+    (!section.is_empty()).then_some(section)
+}
+
+/// A file format, from its extension.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Format {
+    Epub,
+    Pdf,
+    Mobi,
+    Azw3,
+    Fb2,
+    Djvu,
+    Txt,
+    Doc,
+    Docx,
+    Rtf,
+    Cbz,
+    Cbr,
+    Cb7,
+    M4b,
+    Mp3,
+    Flac,
+    /// Any other extension, lowercased.
+    Other(String),
+}
+
+impl Format {
+    pub fn from_extension(ext: &str) -> Self {
+        let ext = ext.trim().trim_start_matches('.').to_lowercase();
+        match ext.as_str() {
+            "epub" => Self::Epub,
+            "pdf" => Self::Pdf,
+            "mobi" => Self::Mobi,
+            "azw3" => Self::Azw3,
+            "fb2" => Self::Fb2,
+            "djvu" => Self::Djvu,
+            "txt" => Self::Txt,
+            "doc" => Self::Doc,
+            "docx" => Self::Docx,
+            "rtf" => Self::Rtf,
+            "cbz" => Self::Cbz,
+            "cbr" => Self::Cbr,
+            "cb7" => Self::Cb7,
+            "m4b" => Self::M4b,
+            "mp3" => Self::Mp3,
+            "flac" => Self::Flac,
+            _ => Self::Other(ext),
+        }
+    }
+
+    /// The lowercase file extension, without a dot.
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Epub => "epub",
+            Self::Pdf => "pdf",
+            Self::Mobi => "mobi",
+            Self::Azw3 => "azw3",
+            Self::Fb2 => "fb2",
+            Self::Djvu => "djvu",
+            Self::Txt => "txt",
+            Self::Doc => "doc",
+            Self::Docx => "docx",
+            Self::Rtf => "rtf",
+            Self::Cbz => "cbz",
+            Self::Cbr => "cbr",
+            Self::Cb7 => "cb7",
+            Self::M4b => "m4b",
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Other(ext) => ext,
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension().to_uppercase())
+    }
+}
+
+impl From<String> for Format {
+    fn from(ext: String) -> Self {
+        Self::from_extension(&ext)
+    }
+}
+
+impl From<Format> for String {
+    fn from(format: Format) -> Self {
+        format.extension().to_string()
+    }
+}
+
+/// An ISO 639 language code such as `en` or `zh`, lowercased.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LanguageCode(String);
+
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"), ("de", "German"), ("en", "English"), ("es", "Spanish"),
+    ("fr", "French"), ("hi", "Hindi"), ("id", "Indonesian"), ("it", "Italian"),
+    ("ja", "Japanese"), ("ko", "Korean"), ("nl", "Dutch"), ("pl", "Polish"),
+    ("pt", "Portuguese"), ("ru", "Russian"), ("sv", "Swedish"), ("tr", "Turkish"),
+    ("uk", "Ukrainian"), ("zh", "Chinese"),
+];
+
+impl LanguageCode {
+    /// Accepts codes like `en`, `ENG` or `zh-Hant`.
+    pub fn new(code: &str) -> Option<Self> {
+        let code = code.trim().to_lowercase();
+        let primary = code.split('-').next()?;
+        ((2..=3).contains(&primary.len()) && code.chars().all(|c| c.is_ascii_alphabetic() || c == '-'))
+            .then_some(Self(code))
+    }
+
+    /// The code for an English language name such as "English", or for a
+    /// code given in its place.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        LANGUAGE_NAMES.iter()
+            .find(|(_, known)| known.eq_ignore_ascii_case(name))
+            .map(|(code, _)| Self(code.to_string()))
+            .or_else(|| (name.len() <= 3).then(|| Self::new(name)).flatten())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// English name of the language, for the common ones.
+    pub fn name(&self) -> Option<&'static str> {
+        let primary = self.0.split('-').next()?;
+        LANGUAGE_NAMES.iter().find(|(code, _)| *code == primary).map(|(_, name)| *name)
+    }
+}
+
+impl std::fmt::Display for LanguageCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} [{}]", name, self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// A kind of record, as the site's `content` search filter groups them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentType {
+    #[serde(rename = "book_nonfiction")]
+    Nonfiction,
+    #[serde(rename = "book_fiction")]
+    Fiction,
+    /// Books the site hasn't classed as fiction or nonfiction.
+    #[serde(rename = "book_unknown")]
+    UnknownBook,
+    #[serde(rename = "book_comic")]
+    Comic,
+    #[serde(rename = "magazine")]
+    Magazine,
+    #[serde(rename = "journal_article")]
+    Article,
+    #[serde(rename = "standards_document")]
+    Standard,
+    #[serde(rename = "musical_score")]
+    Score,
+    #[serde(rename = "other")]
+    Other,
+}
+
+impl ContentType {
+    /// The value of the site's `content` query parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Nonfiction => "book_nonfiction",
+            Self::Fiction => "book_fiction",
+            Self::UnknownBook => "book_unknown",
+            Self::Comic => "book_comic",
+            Self::Magazine => "magazine",
+            Self::Article => "journal_article",
+            Self::Standard => "standards_document",
+            Self::Score => "musical_score",
+            Self::Other => "other",
+        }
+    }
+
+    /// The short name `--content` takes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nonfiction => "nonfiction",
+            Self::Fiction => "fiction",
+            Self::UnknownBook => "unknown",
+            Self::Comic => "comic",
+            Self::Magazine => "magazine",
+            Self::Article => "article",
+            Self::Standard => "standard",
+            Self::Score => "score",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = String;
+
+    /// Accepts the short names, their plurals and the site's own values.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase().replace('-', "_");
+        let all = [
+            Self::Nonfiction, Self::Fiction, Self::UnknownBook, Self::Comic, Self::Magazine,
+            Self::Article, Self::Standard, Self::Score, Self::Other,
+        ];
+        let singular = s.strip_suffix('s').unwrap_or(&s);
+        all.into_iter()
+            .find(|c| c.as_str() == s || c.name() == s || c.name() == singular || (*c == Self::Nonfiction && s == "non_fiction"))
+            .ok_or_else(|| format!(
+                "'{}' is not one of {}",
+                s,
+                all.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ")
+            ))
+    }
+}
+
+/// The part of the archive a search covers. Journals is the site's separate
+/// index of papers; magazines and comics are the main index limited to that
+/// kind of record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchIndex {
+    #[default]
+    Books,
+    Journals,
+    Magazines,
+    Comics,
+}
+
+impl SearchIndex {
+    pub const ALL: [SearchIndex; 4] = [Self::Books, Self::Journals, Self::Magazines, Self::Comics];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Books => "books",
+            Self::Journals => "journals",
+            Self::Magazines => "magazines",
+            Self::Comics => "comics",
+        }
+    }
+
+    /// The index after this one, wrapping around; for the TUI toggle.
+    pub fn next(&self) -> Self {
+        let at = Self::ALL.iter().position(|index| index == self).unwrap_or_default();
+        Self::ALL[(at + 1) % Self::ALL.len()]
+    }
+
+    /// The site's `index` parameter; None for the main index.
+    fn param(&self) -> Option<&'static str> {
+        match self {
+            Self::Journals => Some("journals"),
+            _ => None,
+        }
+    }
+
+    /// The content type the main index is limited to.
+    fn content(&self) -> Option<ContentType> {
+        match self {
+            Self::Magazines => Some(ContentType::Magazine),
+            Self::Comics => Some(ContentType::Comic),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SearchIndex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "books" | "book" => Ok(Self::Books),
+            "journals" | "journal" | "papers" | "articles" => Ok(Self::Journals),
+            "magazines" | "magazine" => Ok(Self::Magazines),
+            "comics" | "comic" => Ok(Self::Comics),
+            other => Err(format!("'{}' is not one of books, journals, magazines, comics", other)),
+        }
+    }
+}
+
+/// The order the site lists search results in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Relevance,
+    /// Most recently published first.
+    Newest,
+    Oldest,
+    /// Largest file first.
+    Largest,
+    Smallest,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Relevance => "relevance",
+            Self::Newest => "newest",
+            Self::Oldest => "oldest",
+            Self::Largest => "largest",
+            Self::Smallest => "smallest",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "relevance" | "relevant" => Ok(Self::Relevance),
+            "newest" | "new" => Ok(Self::Newest),
+            "oldest" | "old" => Ok(Self::Oldest),
+            "largest" | "large" => Ok(Self::Largest),
+            "smallest" | "small" => Ok(Self::Smallest),
+            other => Err(format!("'{}' is not one of relevance, newest, oldest, largest, smallest", other)),
+        }
+    }
+}
+
+/// Language codes in a filter such as "en", "en,de" or "EN DE". Words that
+/// aren't codes are skipped.
+pub fn language_filter_codes(filter: &str) -> Vec<LanguageCode> {
+    let mut codes: Vec<LanguageCode> = Vec::new();
+    for code in filter.split([',', ' ']).filter_map(LanguageCode::new) {
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    codes
+}
+
+/// Extensions for a format filter such as "pdf", "cbz,cbr", "comics" or
+/// "audiobooks".
+pub fn format_filter_extensions(filter: &str) -> Vec<String> {
+    let mut extensions: Vec<String> = Vec::new();
+    for term in filter.split([',', ' ']).map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()) {
+        let expanded: Vec<String> = match term.as_str() {
+            "comic" | "comics" => [Format::Cbz, Format::Cbr, Format::Cb7].iter().map(|f| f.extension().to_string()).collect(),
+            "audiobook" | "audiobooks" => [Format::M4b, Format::Mp3, Format::Flac].iter().map(|f| f.extension().to_string()).collect(),
+            _ => vec![Format::from_extension(&term).extension().to_string()],
+        };
+        for ext in expanded {
+            if !extensions.contains(&ext) {
+                extensions.push(ext);
+            }
+        }
+    }
+    extensions
+}
+
+/// Parses sizes like "1.5MB", "500 KB" or "1GB" into bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let digits: String = size.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let value = digits.parse::<f64>().ok()?;
+    let unit = size[digits.len()..].trim().to_uppercase();
+    let multiplier = match unit.as_str() {
+        "TB" => 1u64 << 40,
+        "GB" => 1 << 30,
+        "MB" | "" => 1 << 20,
+        "KB" => 1 << 10,
+        "B" => 1,
+        _ => return None,
+    };
+    Some((value * multiplier as f64).round() as u64)
+}
+
+/// One page of search results plus the site's overall match count.
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    pub books: Vec<Book>,
+    pub total: Option<ResultCount>,
+}
+
+/// Where a search sends each page's new matches as soon as it is parsed.
+pub type PageSink = tokio::sync::mpsc::UnboundedSender<SearchResults>;
+
+/// Pages smaller than this are error or placeholder pages, not result lists.
+const LAYOUT_CHECK_MIN_BYTES: usize = 10 * 1024;
+
+/// Failures where the site answered, but not with a page that could be
+/// used. Returned inside `anyhow::Error`; callers branch with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ScrapeError {
+    #[error("Site layout likely changed: the search page loaded but no books could be read from it. Check for an anna-dl update or report the issue{}", saved_hint(.saved))]
+    LayoutChanged { saved: Option<PathBuf> },
+    /// Bot protection or an access block (HTTP 403).
+    #[error("Anna's Archive refused the request (HTTP {status}). Wait a while, or use --polite to space out requests")]
+    Blocked { status: u16 },
+    /// HTTP 429, with the wait the site asked for in seconds.
+    #[error("Anna's Archive is limiting requests (HTTP 429){}. Use --polite to space them out", retry_after_hint(.retry_after))]
+    RateLimited { retry_after: Option<u64> },
+    /// A Cloudflare browser check, which only a real browser can pass.
+    #[error("Anna's Archive answered with a Cloudflare browser check (HTTP {status}). Wait a while, use --polite, or add another domain to archive_mirrors")]
+    Challenge { status: u16 },
+    #[error("Not found on Anna's Archive: {0}")]
+    NotFound(String),
+    #[error("HTTP error: {status} ({url})")]
+    Http { status: u16, url: String },
+}
+
+impl ScrapeError {
+    /// The error for a non-success response to `url`.
+    pub fn from_status(status: u16, url: &str) -> Self {
+        match status {
+            403 => Self::Blocked { status },
+            429 => Self::RateLimited { retry_after: None },
+            404 | 410 => Self::NotFound(url.to_string()),
+            _ => Self::Http { status, url: url.to_string() },
+        }
+    }
+}
+
+fn retry_after_hint(retry_after: &Option<u64>) -> String {
+    retry_after
+        .map(|secs| format!("; it asks to wait {}s", secs))
+        .unwrap_or_default()
+}
+
+/// Whether a response is a Cloudflare challenge rather than the page: the
+/// `cf-mitigated` header, or the interstitial's markers in the HTML.
+fn is_challenge(headers: &reqwest::header::HeaderMap, body: &str) -> bool {
+    if headers.get("cf-mitigated").is_some_and(|value| value == "challenge") {
+        return true;
+    }
+    const MARKERS: [&str; 4] = ["<title>Just a moment...</title>", "/cdn-cgi/challenge-platform/", "window._cf_chl_opt", "cf-browser-verification"];
+    MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+/// Seconds from a `Retry-After` header; HTTP dates aren't used by the site.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn saved_hint(saved: &Option<PathBuf>) -> String {
+    saved.as_ref()
+        .map(|path| format!(" (page saved to {})", path.display()))
+        .unwrap_or_default()
+}
+
+/// The "N results" figure from a search page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultCount {
+    pub count: u64,
+    pub approximate: bool,
+}
+
+impl std::fmt::Display for ResultCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let grouped = locale::current().count(self.count);
+        if self.approximate {
+            write!(f, "~{}", grouped)
+        } else {
+            write!(f, "{}", grouped)
+        }
+    }
+}
+
+pub fn is_md5(s: &str) -> bool {
+    s.len() == 32 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The MD5 in a pasted book link or bare hash, if the input is one. Links
+/// count on any of the configured mirrors and the site's own domains.
+pub fn md5_from_reference(input: &str, config: &Config) -> Option<String> {
+    let input = input.trim();
+    if is_md5(input) {
+        return Some(input.to_lowercase());
+    }
+
+    let url = reqwest::Url::parse(input).ok()?;
+    let origin = url.origin();
+    let known = mirror_list(config).iter().map(String::as_str)
+        .chain(ARCHIVE_DOMAINS)
+        .filter_map(|mirror| reqwest::Url::parse(mirror).ok())
+        .any(|mirror| mirror.origin() == origin);
+    if !known {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    segments.find(|s| *s == "md5")?;
+    let hash = segments.next()?;
+    is_md5(hash).then(|| hash.to_lowercase())
+}
+
+const FAST_DOWNLOAD_API: &str = "/dyn/api/fast_download.json";
+const RECORD_API: &str = "/db/aarecord_elasticsearch";
+const SCIDB_PATH: &str = "/scidb/";
+
+/// The site's own domains. When `archive_url` is one of them the others are
+/// tried after it, so a domain blocked in one country doesn't stop searches.
+pub const ARCHIVE_DOMAINS: [&str; 3] = [
+    "https://annas-archive.org",
+    "https://annas-archive.se",
+    "https://annas-archive.li",
+];
+
+/// The onion service in Tor mode, then `archive_url`, then `archive_mirrors`,
+/// then the site's other domains when `archive_url` is one of them; without
+/// trailing slashes or repeats. Links built on `archive_url` are rewritten to
+/// the first of these that answers.
+pub fn mirror_list(config: &Config) -> Vec<String> {
+    let primary = config.archive_url();
+    let built_in: &[&str] = if ARCHIVE_DOMAINS.contains(&primary) { &ARCHIVE_DOMAINS } else { &[] };
+    let mut mirrors: Vec<String> = Vec::new();
+    let candidates = config.onion_url().into_iter()
+        .chain(std::iter::once(primary))
+        .chain(config.archive_mirrors.iter().map(|m| m.trim().trim_end_matches('/')))
+        .chain(built_in.iter().copied());
+    for mirror in candidates.filter(|m| !m.is_empty()) {
+        if !mirrors.iter().any(|m| m == mirror) {
+            mirrors.push(mirror.to_string());
+        }
+    }
+    mirrors
+}
+
+/// Which of `mirrors` answered last. Process-wide, since a scraper is
+/// created per command and the working mirror should outlive it.
+fn active_mirror(mirrors: &[String]) -> Arc<AtomicUsize> {
+    static ACTIVE: OnceLock<Mutex<HashMap<Vec<String>, Arc<AtomicUsize>>>> = OnceLock::new();
+    let mut active = ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    active.entry(mirrors.to_vec()).or_default().clone()
+}
+
+/// Whether a request that failed on one mirror is worth trying on the next:
+/// the site was unreachable, refused us or had a server error, rather than
+/// lacking the page.
+fn fails_over(error: &anyhow::Error) -> bool {
+    retry::classify(error).is_some()
+        || error.chain().any(|cause| matches!(
+            cause.downcast_ref::<ScrapeError>(),
+            Some(ScrapeError::Blocked { .. } | ScrapeError::Challenge { .. } | ScrapeError::Http { status: 451, .. })
+        ))
+}
+/// The site has no bulk metadata endpoint, so record lookups run in parallel.
+const LOOKUP_CONCURRENCY: usize = 4;
+/// Result pages fetched at most for one search, so a size or issue filter
+/// that matches almost nothing doesn't walk the whole listing.
+const MAX_SEARCH_PAGES: u32 = 10;
+/// Editions read from a book page at most.
+const MAX_EDITIONS: usize = 20;
+
+/// A member download link handed out by the fast_download API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FastDownload {
+    pub url: String,
+    pub downloads_left: Option<u32>,
+    pub downloads_per_day: Option<u32>,
+}
+
+impl FastDownload {
+    pub fn link(&self) -> DownloadLink {
+        DownloadLink {
+            text: "Fast download (member)".to_string(),
+            url: self.url.clone(),
+            source: "Anna's Archive".to_string(),
+            kind: LinkKind::Http,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FastDownloadResponse {
+    download_url: Option<String>,
+    error: Option<String>,
+    account_fast_download_info: Option<FastDownloadInfo>,
+}
+
+#[derive(Deserialize)]
+struct FastDownloadInfo {
+    downloads_left: Option<u32>,
+    downloads_per_day: Option<u32>,
+}
+
+/// What a book page offers beyond the search listing.
+#[derive(Debug, Clone, Default)]
+pub struct BookPage {
+    pub links: Vec<DownloadLink>,
+    pub identifiers: Identifiers,
+    pub details: BookDetails,
+    /// Other files of the same work listed under the page's "other
+    /// editions" or "same title" heading, without the book itself.
+    pub editions: Vec<Book>,
+}
+
+/// A paper on SciDB, Anna's Archive's viewer for papers by DOI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paper {
+    pub doi: String,
+    pub title: Option<String>,
+    /// The PDF shown in the viewer.
+    pub pdf_url: String,
+    /// The file's record, when the page links to one.
+    pub md5: Option<String>,
+    /// The SciDB page.
+    pub url: String,
+}
+
+impl Paper {
+    /// The paper as a book record, for metadata sidecars and uploads.
+    pub fn to_book(&self) -> Book {
+        let mut identifiers = Identifiers::default();
+        identifiers.doi.push(self.doi.clone());
+        Book {
+            title: self.title.clone().unwrap_or_else(|| self.doi.clone()),
+            author: None,
+            year: None,
+            language: None,
+            format: Some(Format::Pdf),
+            size_bytes: None,
+            md5: self.md5.clone(),
+            url: self.url.clone(),
+            metadata: Metadata { identifiers, ..Metadata::default() },
+        }
+    }
+
+    /// A file name from the title, or the DOI with its slashes replaced.
+    pub fn filename(&self) -> String {
+        match self.title {
+            Some(ref title) => title.chars().take(80).collect::<String>().replace(['/', '\\'], "-"),
+            None => self.doi.replace(['/', '\\'], "_"),
+        }
+    }
+}
+
+/// The parts of an `/db/aarecord_elasticsearch/md5:<hash>.json` record we use.
+#[derive(Deserialize)]
+struct AaRecord {
+    file_unified_data: FileUnifiedData,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FileUnifiedData {
+    title_best: String,
+    author_best: String,
+    year_best: String,
+    language_codes: Vec<String>,
+    extension_best: String,
+    filesize_best: u64,
+    identifiers_unified: std::collections::HashMap<String, Vec<String>>,
+    publisher_best: String,
+    edition_varia_best: String,
+    stripped_description_best: String,
+    cover_url_best: String,
+}
+
+/// The facts in a search result's comma-separated summary line, read field
+/// by field so a format never ends up as the author.
+#[derive(Debug, Default, PartialEq)]
+struct ResultInfo {
+    language: Option<LanguageCode>,
+    format: Option<Format>,
+    size_bytes: Option<u64>,
+    year: Option<u16>,
+}
+
+impl ResultInfo {
+    fn parse(line: &str) -> Self {
+        let mut info = Self::default();
+        let (Some(language), Some(size), Some(year)) = (
+            regex!(r"\[([a-zA-Z-]{2,8})\]$"),
+            regex!(r"(?i)^\d+(?:\.\d+)?\s*[KMGT]?B$"),
+            regex!(r"^(?:19|20)\d{2}$"),
+        ) else {
+            return info;
+        };
+
+        for field in line.split(',').map(str::trim) {
+            if let Some(caps) = language.captures(field) {
+                info.language = info.language.or_else(|| LanguageCode::new(&caps[1]));
+            } else if let Some(ext) = field.strip_prefix('.').filter(|e| !e.is_empty() && e.len() <= 5 && e.chars().all(|c| c.is_ascii_alphanumeric())) {
+                info.format = info.format.or_else(|| Some(Format::from_extension(ext)));
+            } else if size.is_match(field) {
+                info.size_bytes = info.size_bytes.or_else(|| parse_size(field));
+            } else if year.is_match(field) {
+                info.year = info.year.or_else(|| field.parse().ok());
+            }
+        }
+        info
+    }
+}
+
+/// Config-driven rules for which mirror domains links may point to.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DomainFilter {
+    blocked: Vec<String>,
+    allowed: Vec<String>,
+}
+
+impl DomainFilter {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let normalize = |domains: &[String]| {
+            domains.iter()
+                .map(|d| d.trim().trim_start_matches('.').to_lowercase())
+                .filter(|d| !d.is_empty())
+                .collect()
+        };
+        Self {
+            blocked: normalize(&config.blocked_domains),
+            allowed: normalize(&config.allowed_domains),
+        }
+    }
+
+    pub(crate) fn allows(&self, url: &str) -> bool {
+        let host = reqwest::Url::parse(url).ok()
+            .and_then(|u| u.host_str().map(str::to_lowercase));
+        let matches = |domains: &[String]| match host {
+            Some(ref host) => domains.iter().any(|d| host == d || host.ends_with(&format!(".{}", d))),
+            None => false,
+        };
+        !matches(&self.blocked) && (self.allowed.is_empty() || matches(&self.allowed))
+    }
 }
 
 pub struct AnnaScraper {
     client: reqwest::Client,
+    read_timeout: Duration,
+    credentials: Option<Credentials>,
+    domains: DomainFilter,
+    /// Without trailing slashes.
+    ipfs_gateways: Vec<String>,
+    user_agents: UserAgents,
+    polite: bool,
+    save_unparsed_pages: bool,
+    retry: RetryPolicy,
+    /// `archive_url`, without a trailing slash. Book URLs are built on it
+    /// and moved to the working mirror when fetched.
+    base_url: String,
+    /// `base_url` first; see `mirror_list`.
+    mirrors: Vec<String>,
+    /// The mirror that answered last, tried first next time. Shared with
+    /// every scraper for the same mirrors; see `active_mirror`.
+    active_mirror: Arc<AtomicUsize>,
+    /// Searched when the site can't be reached or finds nothing.
+    libgen: Option<LibgenScraper>,
 }
 
 impl AnnaScraper {
-    pub fn new() -> Result<Self> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent(Self::random_user_agent())
+    pub fn new(config: &Config) -> Result<Self> {
+        let timeouts = config.scraper_timeouts();
+        let client = http::client_builder(config, timeouts)?
             .build()
             .context("Failed to create HTTP client")?;
+        let mirrors = mirror_list(config);
         
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            read_timeout: timeouts.read,
+            credentials: None,
+            domains: DomainFilter::from_config(config),
+            ipfs_gateways: config.ipfs_gateways.iter()
+                .map(|g| g.trim().trim_end_matches('/').to_string())
+                .filter(|g| !g.is_empty())
+                .collect(),
+            user_agents: UserAgents::from_config(config),
+            polite: config.polite,
+            save_unparsed_pages: config.save_unparsed_pages,
+            retry: config.retry_policy(),
+            base_url: config.archive_url().to_string(),
+            active_mirror: active_mirror(&mirrors),
+            mirrors,
+            libgen: config.libgen_fallback.then(|| LibgenScraper::new(config)).transpose()?,
+        })
     }
     
-    pub async fn search(&self, query: &str, filters: &SearchFilters, max_results: usize) -> Result<Vec<Book>> {
-        let mut search_url = format!("https://annas-archive.org/search?q={}",
-            urlencoding::encode(query));
+    /// Sends the account session with every request, when logged in.
+    pub fn with_credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+    
+    /// The search page URL, with one `ext`, `lang` and `content` parameter
+    /// per value; the site matches any of the values given for each.
+    /// Relevance is the site's default order and isn't sent.
+    fn search_url(&self, query: &str, filters: &SearchFilters) -> String {
+        let query = QueryIdentifier::parse(query).map_or_else(|| query.to_string(), |id| id.search_term());
+        let mut search_url = format!("{}/search?q={}",
+            self.base_url, urlencoding::encode(&query));
         
-        if let Some(ref fmt) = filters.format {
-             search_url.push_str(&format!("&ext={}", urlencoding::encode(fmt)));
+        for ext in format_filter_extensions(filters.format.as_deref().unwrap_or_default()) {
+            search_url.push_str(&format!("&ext={}", urlencoding::encode(&ext)));
+        }
+
+        for lang in language_filter_codes(filters.language.as_deref().unwrap_or_default()) {
+            search_url.push_str(&format!("&lang={}", urlencoding::encode(lang.as_str())));
         }
 
-        if let Some(ref lang) = filters.language {
-             search_url.push_str(&format!("&lang={}", urlencoding::encode(lang)));
+        let mut content = filters.content.clone();
+        let implied = [filters.magazines.then_some(ContentType::Magazine), filters.index.content()];
+        for kind in implied.into_iter().flatten() {
+            if !content.contains(&kind) {
+                content.push(kind);
+            }
+        }
+        for kind in content {
+            search_url.push_str(&format!("&content={}", kind.as_str()));
+        }
+
+        if let Some(index) = filters.index.param() {
+            search_url.push_str(&format!("&index={}", index));
         }
 
-        let html = self.fetch_html(&search_url).await?;
-        let mut books = self.parse_search_results(&html, max_results * 2).await?;
+        if filters.sort != SortOrder::Relevance {
+            search_url.push_str(&format!("&sort={}", filters.sort.as_str()));
+        }
+        search_url
+    }
+
+    /// Fetches result pages until `max_results` books pass the filters, a
+    /// page has no new results, or the site's result count is reached.
+    /// For an ISBN or DOI query, books listing that identifier come first.
+    /// When the site can't be reached, its layout can't be read or it finds
+    /// nothing, LibGen is searched instead, if the fallback is on. The
+    /// site's own error is kept if LibGen fails too.
+    pub async fn search(&self, query: &str, filters: &SearchFilters, max_results: usize) -> Result<SearchResults> {
+        self.search_paged(query, filters, max_results, None).await
+    }
 
-        // Post-filtering for size
-        if let Some(max_mb) = filters.max_size_mb {
-            books.retain(|b| {
-                if let Some(ref s) = b.size {
-                     Self::parse_size_mb(s).map(|v| v <= max_mb).unwrap_or(true)
-                } else {
-                    true
+    /// `search`, also sending each page's matches to `pages` as soon as it
+    /// is parsed. The returned results are the final list, identifier
+    /// matches first.
+    pub async fn search_paged(&self, query: &str, filters: &SearchFilters, max_results: usize, pages: Option<&PageSink>) -> Result<SearchResults> {
+        let result = self.search_archive(query, filters, max_results, pages).await;
+        let Some(libgen) = &self.libgen else {
+            return result;
+        };
+        let falls_back = match &result {
+            Ok(results) => results.books.is_empty(),
+            Err(e) => fails_over(e) || matches!(e.downcast_ref::<ScrapeError>(), Some(ScrapeError::LayoutChanged { .. })),
+        };
+        if !falls_back {
+            return result;
+        }
+        let query = QueryIdentifier::parse(query).map_or_else(|| query.to_string(), |id| id.search_term());
+        match libgen.search(&query, filters, max_results).await {
+            Ok(found) if !found.books.is_empty() => {
+                if let Some(pages) = pages {
+                    let _ = pages.send(found.clone());
                 }
-            });
+                Ok(found)
+            }
+            _ => result,
         }
+    }
 
-        if books.len() > max_results {
-            books.truncate(max_results);
+    async fn search_archive(&self, query: &str, filters: &SearchFilters, max_results: usize, pages: Option<&PageSink>) -> Result<SearchResults> {
+        let search_url = self.search_url(query, filters);
+        let mut books: Vec<Book> = Vec::new();
+        let mut seen: Vec<String> = Vec::new();
+        let mut total = None;
+        let periodical = filters.periodicals();
+
+        for page in 1..=MAX_SEARCH_PAGES {
+            let url = if page == 1 { search_url.clone() } else { format!("{}&page={}", search_url, page) };
+            let fetched = match self.fetch_html(&url).await {
+                Ok(html) => self.parse_search_results(&html, max_results * 2).await.map(|found| (html, found)),
+                Err(e) => Err(e),
+            };
+            // A later page failing keeps the books already found, and shown
+            let (html, found) = match fetched {
+                Ok(fetched) => fetched,
+                Err(e) if page == 1 => return Err(e),
+                Err(_) => break,
+            };
+            if page == 1 {
+                total = Self::parse_result_count(&html);
+                if found.is_empty() {
+                    self.check_layout(&html, total)?;
+                }
+            }
+
+            // Past the last page the site may repeat it rather than come back empty
+            let mut new: Vec<Book> = found.into_iter().filter(|b| !seen.iter().any(|id| id == b.id())).collect();
+            if new.is_empty() {
+                break;
+            }
+            for book in &mut new {
+                book.metadata.periodical = periodical;
+            }
+            seen.extend(new.iter().map(|b| b.id().to_string()));
+            let matched: Vec<Book> = new.into_iter().filter(|b| filters.matches(b)).collect();
+            if let Some(pages) = pages {
+                let room = max_results.saturating_sub(books.len());
+                if room > 0 && !matched.is_empty() {
+                    let _ = pages.send(SearchResults { books: matched.iter().take(room).cloned().collect(), total });
+                }
+            }
+            books.extend(matched);
+
+            if books.len() >= max_results || total.is_some_and(|t| seen.len() as u64 >= t.count) {
+                break;
+            }
         }
 
-        Ok(books)
+        if let Some(id) = QueryIdentifier::parse(query) {
+            books.sort_by_key(|book| !id.matches(book));
+        }
+        books.truncate(max_results);
+        Ok(SearchResults { books, total })
     }
 
-    fn parse_size_mb(size_str: &str) -> Option<f64> {
-        let size_str = size_str.trim();
-        let digits: String = size_str.chars().take_while(|c| c.is_digit(10) || *c == '.').collect();
-        let val = digits.parse::<f64>().ok()?;
+    /// A full-size page with no books that doesn't say "no results" means the
+    /// selectors no longer match the site's markup.
+    fn check_layout(&self, html: &str, total: Option<ResultCount>) -> Result<()> {
+        if html.len() < LAYOUT_CHECK_MIN_BYTES || total.is_some_and(|t| t.count == 0) {
+            return Ok(());
+        }
+        let document = Html::parse_document(html);
+        let text = document.root_element().text().collect::<String>().to_lowercase();
+        if text.contains("no files found") || text.contains("no results") {
+            return Ok(());
+        }
 
-        let upper_size = size_str.to_uppercase();
-        if upper_size.contains("KB") {
-            Some(val / 1024.0)
-        } else if upper_size.contains("MB") {
-            Some(val)
-        } else if upper_size.contains("GB") {
-            Some(val * 1024.0)
+        let saved = if self.save_unparsed_pages {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            let path = std::env::temp_dir().join(format!("annadl_search_{}.html", nanos));
+            std::fs::write(&path, html).ok().map(|_| path)
         } else {
-            Some(val)
+            None
+        };
+        Err(ScrapeError::LayoutChanged { saved }.into())
+    }
+
+    /// Finds "1,340 results (approx)" / "10000+ results" on a search page.
+    fn parse_result_count(html: &str) -> Option<ResultCount> {
+        let document = Html::parse_document(html);
+        let text = document.root_element().text().collect::<Vec<_>>().join(" ");
+        let re = regex!(r"(?i)(~\s*)?(\d[\d,.]*)(\+)?\s+(?:total\s+)?results?\b(\s*\(approx)?")?;
+        let caps = re.captures(&text)?;
+        let count = caps[2].replace([',', '.'], "").parse().ok()?;
+        let approximate = caps.get(1).is_some() || caps.get(3).is_some() || caps.get(4).is_some();
+        Some(ResultCount { count, approximate })
+    }
+
+    /// Membership tier and fast-download quota of the logged-in account.
+    pub async fn account_status(&self) -> Result<AccountStatus> {
+        match self.credentials {
+            None => anyhow::bail!("Not logged in"),
+            Some(ref credentials) if credentials.session.is_empty() => {
+                anyhow::bail!("secret key from {}; run annadl login for account details", account::SECRET_KEY_ENV)
+            }
+            Some(_) => {}
+        }
+        let html = self.fetch_html(&format!("{}{}", self.base_url, account::ACCOUNT_PATH)).await?;
+        Ok(account::parse_account_status(&html))
+    }
+    
+    /// Asks the fast_download API for a member link, skipping the book page.
+    /// Fails when not logged in or when the daily quota is used up.
+    pub async fn fast_download(&self, md5: &str) -> Result<FastDownload> {
+        let Some(ref credentials) = self.credentials else {
+            anyhow::bail!("Not logged in");
+        };
+        let api = format!("{}{}", self.base_url, FAST_DOWNLOAD_API);
+        let _turn = self.turn(&api).await;
+        // Errors come back as JSON with a non-success status, so parse regardless
+        let mut request = self.client
+            .get(&api)
+            .query(&[("md5", md5), ("key", credentials.secret_key.as_str())])
+            .headers(self.user_agents.headers());
+        if let Some(cookie) = credentials.cookie_header() {
+            request = request.header(reqwest::header::COOKIE, cookie);
         }
+        let body = request
+            .send()
+            .await
+            .context("Failed to reach the fast download API")?
+            .text()
+            .await
+            .context("Failed to read fast download response")?;
+        Self::parse_fast_download(&body)
     }
     
-    pub async fn get_book_details(&self, book_url: &str) -> Result<Vec<DownloadLink>> {
+    fn parse_fast_download(json: &str) -> Result<FastDownload> {
+        let response: FastDownloadResponse = serde_json::from_str(json)
+            .context("Unexpected fast download response")?;
+        let (downloads_left, downloads_per_day) = response.account_fast_download_info
+            .map(|info| (info.downloads_left, info.downloads_per_day))
+            .unwrap_or_default();
+        match response.download_url {
+            Some(url) => Ok(FastDownload { url, downloads_left, downloads_per_day }),
+            None => anyhow::bail!(
+                "Fast download unavailable: {}",
+                response.error.as_deref().unwrap_or("no link returned")
+            ),
+        }
+    }
+    
+    /// Resolves MD5s to metadata without scraping each book page. Results
+    /// keep the input order; duplicates are looked up once.
+    pub async fn lookup_md5s(&self, md5s: &[&str]) -> Vec<(String, Result<Book>)> {
+        let mut unique: Vec<String> = Vec::new();
+        for md5 in md5s {
+            let md5 = md5.trim().to_lowercase();
+            if !unique.contains(&md5) {
+                unique.push(md5);
+            }
+        }
+        
+        futures::stream::iter(unique)
+            .map(|md5| async move {
+                let result = self.book_by_md5(&md5).await;
+                (md5, result)
+            })
+            .buffered(LOOKUP_CONCURRENCY)
+            .collect()
+            .await
+    }
+    
+    /// Metadata for a single MD5.
+    pub async fn book_by_md5(&self, md5: &str) -> Result<Book> {
+        if !is_md5(md5) {
+            anyhow::bail!("Not an MD5 hash");
+        }
+        let json = self.fetch_html(&format!("{}{}/md5:{}.json", self.base_url, RECORD_API, md5)).await?;
+        self.parse_record(md5, &json)
+    }
+
+    /// A pasted MD5's book: its record, or a placeholder on the configured
+    /// site when the record can't be fetched.
+    pub async fn open_md5(&self, md5: &str) -> Book {
+        self.book_by_md5(md5).await.unwrap_or_else(|_| Book::from_md5(&self.base_url, md5))
+    }
+    
+    fn parse_record(&self, md5: &str, json: &str) -> Result<Book> {
+        let record: AaRecord = serde_json::from_str(json)
+            .context("Unexpected record format")?;
+        let data = record.file_unified_data;
+        let non_empty = |s: String| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+        let mut identifiers = Identifiers::default();
+        for (kind, values) in &data.identifiers_unified {
+            for value in values {
+                push_identifier(&mut identifiers, kind, value);
+            }
+        }
+        
+        Ok(Book {
+            title: non_empty(data.title_best).unwrap_or_else(|| md5.to_string()),
+            author: non_empty(data.author_best),
+            year: data.year_best.trim().parse().ok(),
+            language: data.language_codes.iter().find_map(|code| LanguageCode::new(code)),
+            format: non_empty(data.extension_best).map(|ext| Format::from_extension(&ext)),
+            size_bytes: (data.filesize_best > 0).then_some(data.filesize_best),
+            md5: Some(md5.to_lowercase()),
+            url: format!("{}/md5/{}", self.base_url, md5.to_lowercase()),
+            metadata: Metadata {
+                identifiers,
+                details: BookDetails {
+                    description: non_empty(data.stripped_description_best),
+                    publisher: non_empty(data.publisher_best),
+                    edition: non_empty(data.edition_varia_best),
+                    pages: None,
+                    cover_url: non_empty(data.cover_url_best),
+                },
+                ..Metadata::default()
+            },
+        })
+    }
+    
+    /// The SciDB page for `doi`, which also takes `doi:` and doi.org forms.
+    pub async fn paper(&self, doi: &str) -> Result<Paper> {
+        let Some(QueryIdentifier::Doi(doi)) = QueryIdentifier::parse(doi) else {
+            anyhow::bail!("'{}' is not a DOI like 10.1038/nature14539", doi.trim());
+        };
+        let url = format!("{}{}{}", self.base_url, SCIDB_PATH, doi);
+        let html = self.fetch_html(&url).await?;
+        Self::parse_paper(&doi, &url, &html)
+    }
+
+    /// Reads the viewer's PDF, the title and the record link from a SciDB
+    /// page. A page without a PDF means SciDB doesn't have the paper.
+    fn parse_paper(doi: &str, url: &str, html: &str) -> Result<Paper> {
+        let document = Html::parse_document(html);
+        let select = |selector: Option<&Selector>| document.select(selector?).next();
+        let page = reqwest::Url::parse(url).context("Invalid SciDB URL")?;
+
+        let pdf = [
+            (selector!("embed[src]"), "src"),
+            (selector!("iframe[src]"), "src"),
+            (selector!("object[data]"), "data"),
+            (selector!("a[download][href]"), "href"),
+            (selector!("a[href$='.pdf']"), "href"),
+        ]
+        .iter()
+        .find_map(|(selector, attr)| select(*selector)?.value().attr(attr))
+        .and_then(|src| page.join(src).ok())
+        .ok_or_else(|| ScrapeError::NotFound(format!("no PDF on SciDB for {}", doi)))?;
+
+        let title = select(selector!("h1"))
+            .map(|h1| h1.text().collect::<String>())
+            .or_else(|| select(selector!("meta[name='citation_title']"))?.value().attr("content").map(str::to_string))
+            .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|title| !title.is_empty() && !title.eq_ignore_ascii_case(doi));
+        let md5 = select(selector!("a[href*='/md5/']"))
+            .and_then(|a| a.value().attr("href"))
+            .and_then(md5_from_url);
+
+        Ok(Paper { doi: doi.to_string(), title, pdf_url: pdf.to_string(), md5, url: url.to_string() })
+    }
+
+    /// The download links, identifiers and details on a book's page.
+    pub async fn book_page(&self, book_url: &str) -> Result<BookPage> {
+        if let Some(libgen) = self.libgen.as_ref().filter(|libgen| libgen.owns(book_url)) {
+            return libgen.book_page(book_url).await;
+        }
         let html = self.fetch_html(book_url).await?;
-        self.parse_download_links(&html).await
+        let mut links = self.parse_download_links(&html).await?;
+        // Gateways last: the page's own links are usually faster
+        for link in ipfs_links(&parse_ipfs_cids(&html), &self.ipfs_gateways) {
+            if !links.iter().any(|l| l.url == link.url) {
+                links.push(link);
+            }
+        }
+        // Magnets have no host to filter on
+        links.retain(|link| link.kind == LinkKind::Magnet || self.domains.allows(&link.url));
+        // Mirrors first, so a link's number is the same with or without torrents
+        links.sort_by_key(|link| link.kind != LinkKind::Http);
+        let text = Html::parse_document(&html).root_element().text().collect::<Vec<_>>().join(" ");
+        Ok(BookPage {
+            links,
+            identifiers: parse_identifiers(&text),
+            details: Self::parse_book_details(book_url, &html),
+            editions: self.parse_editions(book_url, &html).await,
+        })
+    }
+
+    /// The results listed after an "Other editions", "Same title" or
+    /// "Other files" heading, up to the next heading. The section uses the
+    /// search listing's markup, so it is read the same way.
+    async fn parse_editions(&self, book_url: &str, html: &str) -> Vec<Book> {
+        let Some(section) = editions_section(html) else {
+            return Vec::new();
+        };
+        let own = md5_from_url(book_url);
+        let mut editions = self.parse_search_results(&section, MAX_EDITIONS + 1).await.unwrap_or_default();
+        editions.retain(|book| book.md5.is_none() || book.md5 != own);
+        editions.truncate(MAX_EDITIONS);
+        editions
+    }
+
+    /// The description, cover and labelled publisher, edition and page count
+    /// on a book page. Labels may end in a colon and share an element with
+    /// their value, or be followed by it in the next one, as in a `<dl>`.
+    fn parse_book_details(url: &str, html: &str) -> BookDetails {
+        let document = Html::parse_document(html);
+        let select = |selector: Option<&Selector>| document.select(selector?).next();
+        let content = |selector: Option<&Selector>| select(selector)?.value().attr("content").map(str::to_string);
+        let clean = |text: String| Some(text.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|t| !t.is_empty());
+
+        let description = select(selector!(".js-md5-top-box-description"))
+            .and_then(|div| clean(div.text().collect()))
+            .or_else(|| content(selector!("meta[property='og:description']")).and_then(clean))
+            .or_else(|| content(selector!("meta[name='description']")).and_then(clean));
+        let cover_url = content(selector!("meta[property='og:image']"))
+            .or_else(|| select(selector!("img[src*='cover']"))?.value().attr("src").map(str::to_string))
+            .and_then(|src| reqwest::Url::parse(url).ok()?.join(&src).ok())
+            .map(String::from);
+
+        let mut details = BookDetails { description, cover_url, ..BookDetails::default() };
+        let Some(label) = regex!(r"(?i)^(publisher|edition|pages|page count|number of pages)\s*:?\s*(.*)$") else {
+            return details;
+        };
+        let texts: Vec<String> = document.root_element().text().filter_map(|t| clean(t.to_string())).collect();
+        for (i, text) in texts.iter().enumerate() {
+            let Some(caps) = label.captures(text) else {
+                continue;
+            };
+            let value = match caps[2].trim() {
+                "" => texts.get(i + 1).map(String::as_str).unwrap_or_default(),
+                value => value,
+            };
+            if value.is_empty() {
+                continue;
+            }
+            match caps[1].to_lowercase().as_str() {
+                "publisher" => details.publisher = details.publisher.take().or_else(|| Some(value.to_string())),
+                "edition" => details.edition = details.edition.take().or_else(|| Some(value.to_string())),
+                _ => details.pages = details.pages.or_else(|| value.split_whitespace().next()?.parse().ok()),
+            }
+        }
+        details
     }
     
+    /// In polite mode, waits for this host's turn; hold the result until the
+    /// response is fully read.
+    async fn turn(&self, url: &str) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        if self.polite {
+            http::polite_turn(url).await
+        } else {
+            None
+        }
+    }
+    
+    /// Fetches `url`, moving pages of the site to the mirror that last
+    /// answered and on to the next ones when it's unreachable or refuses.
     async fn fetch_html(&self, url: &str) -> Result<String> {
-        let response = self.client
-            .get(url)
+        let Some(path) = self.mirror_path(url) else {
+            return self.retry.run(|| self.fetch_html_once(url)).await;
+        };
+        let start = self.active_mirror.load(Ordering::Relaxed);
+        let mut last_error = None;
+        for i in 0..self.mirrors.len() {
+            let at = (start + i) % self.mirrors.len();
+            let url = format!("{}{}", self.mirrors[at], path);
+            match self.retry.run(|| self.fetch_html_once(&url)).await {
+                Ok(html) => {
+                    self.active_mirror.store(at, Ordering::Relaxed);
+                    return Ok(html);
+                }
+                Err(e) if fails_over(&e) => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        let error = last_error.context("No Anna's Archive mirrors configured")?;
+        if self.mirrors.len() > 1 {
+            return Err(error.context(format!("None of the {} Anna's Archive mirrors could be reached", self.mirrors.len())));
+        }
+        Err(error)
+    }
+
+    /// The path and query of `url` when it is on one of the mirrors.
+    fn mirror_path<'a>(&self, url: &'a str) -> Option<&'a str> {
+        self.mirrors.iter()
+            .filter_map(|mirror| url.strip_prefix(mirror.as_str()))
+            .find(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+    }
+    
+    async fn fetch_html_once(&self, url: &str) -> Result<String> {
+        let _turn = self.turn(url).await;
+        let mut request = self.client.get(url).headers(self.user_agents.headers());
+        if let Some(cookie) = self.credentials.as_ref().and_then(Credentials::cookie_header) {
+            request = request.header(reqwest::header::COOKIE, cookie);
+        }
+        let response = request
             .send()
             .await
             .context("Failed to fetch URL")?;
         
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
         if !response.status().is_success() {
-            anyhow::bail!("HTTP error: {}", response.status());
+            if matches!(status, 403 | 429 | 503) {
+                let body = tokio::time::timeout(self.read_timeout, response.text())
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+                    .unwrap_or_default();
+                if is_challenge(&headers, &body) {
+                    return Err(ScrapeError::Challenge { status }.into());
+                }
+            }
+            return Err(match ScrapeError::from_status(status, url) {
+                ScrapeError::RateLimited { .. } => ScrapeError::RateLimited { retry_after: retry_after(&headers) },
+                e => e,
+            }.into());
         }
         
-        response.text().await.context("Failed to read response body")
+        // Read chunk by chunk so a stalled server trips the read timeout
+        let mut body = Vec::new();
+        let mut stream = response.bytes_stream();
+        loop {
+            let next = tokio::time::timeout(self.read_timeout, stream.next())
+                .await
+                .map_err(|_| Stalled(self.read_timeout.as_secs()))?;
+            let Some(chunk) = next else {
+                break;
+            };
+            body.extend_from_slice(&chunk.context("Failed to read response body")?);
+        }
+        
+        let html = String::from_utf8_lossy(&body).into_owned();
+        // Otherwise the check page is parsed as an empty result list
+        if is_challenge(&headers, &html) {
+            return Err(ScrapeError::Challenge { status }.into());
+        }
+        Ok(html)
     }
     
     async fn parse_search_results(&self, html: &str, max_results: usize) -> Result<Vec<Book>> {
@@ -110,26 +1844,27 @@ impl AnnaScraper {
         
         // Multiple fallback selectors for book links
         let selectors = [
-            "a.js-vim-focus.custom-a",
-            "a[href*='md5']",
-            ".book-title a",
-            "a[href*='book']",
+            selector!("a.js-vim-focus.custom-a"),
+            selector!("a[href*='md5']"),
+            selector!(".book-title a"),
+            selector!("a[href*='book']"),
         ];
         
-        let mut books = Vec::new();
+        let mut books: Vec<Book> = Vec::new();
         
-        for selector_str in &selectors {
-            if let Ok(selector) = Selector::parse(selector_str) {
-                let elements: Vec<_> = document.select(&selector).take(max_results * 2).collect();
-                
-                if !elements.is_empty() {
-                    for element in elements.iter().take(max_results) {
-                        if let Some(book) = self.extract_book_info(element, &document) {
+        for selector in selectors.into_iter().flatten() {
+            let elements: Vec<_> = document.select(selector).take(max_results * 2).collect();
+            
+            if !elements.is_empty() {
+                for element in elements.iter().take(max_results) {
+                    // A result's cover and title both link to it
+                    if let Some(book) = self.extract_book_info(element, &document) {
+                        if !books.iter().any(|b| b.id() == book.id()) {
                             books.push(book);
                         }
                     }
-                    break;
                 }
+                break;
             }
         }
         
@@ -142,49 +1877,66 @@ impl AnnaScraper {
         
         // Look for external download section
         let section_selectors = [
-            "#external-downloads",
-            ".external-downloads",
-            "[data-section='downloads']",
+            selector!("#external-downloads"),
+            selector!(".external-downloads"),
+            selector!("[data-section='downloads']"),
         ];
         
-        for selector_str in &section_selectors {
-            if let Ok(selector) = Selector::parse(selector_str) {
-                if let Some(section) = document.select(&selector).next() {
-                    links.extend(self.extract_links_from_section(&section));
-                }
+        for selector in section_selectors.into_iter().flatten() {
+            if let Some(section) = document.select(selector).next() {
+                links.extend(self.extract_links_from_section(&section));
             }
         }
         
         // Fallback: search all download links on page
         if links.is_empty() {
             let link_selectors = [
-                "a[href*='libgen']",
-                "a[href*='download']",
-                "a[href*='mirror']",
-                "a[href*='get.php']",
-                ".download-link",
+                selector!("a[href*='libgen']"),
+                selector!("a[href*='download']"),
+                selector!("a[href*='mirror']"),
+                selector!("a[href*='get.php']"),
+                selector!(".download-link"),
             ];
             
             let mut seen_urls = std::collections::HashSet::new();
 
-            for selector_str in &link_selectors {
-                if let Ok(selector) = Selector::parse(selector_str) {
-                    for element in document.select(&selector) {
-                        if let Some(link) = self.extract_download_link(element) {
-                            if seen_urls.insert(link.url.clone()) {
-                                links.push(link);
-                            }
+            for selector in link_selectors.into_iter().flatten() {
+                for element in document.select(selector) {
+                    if let Some(link) = self.extract_download_link(element) {
+                        if seen_urls.insert(link.url.clone()) {
+                            links.push(link);
                         }
                     }
                 }
             }
         }
         
+        // Torrents found by the selectors above are added again, complete
+        links.retain(DownloadLink::is_http);
+        for link in self.extract_torrent_links(&document) {
+            if !links.iter().any(|l| l.url == link.url) {
+                links.push(link);
+            }
+        }
+        
         Ok(links)
     }
     
+    /// Reads one result, preferring the record JSON embedded next to it,
+    /// then the fields the site renders as separate elements, and only then
+    /// guessing from the result's text.
     fn extract_book_info(&self, element: &scraper::ElementRef, _document: &Html) -> Option<Book> {
         let href = element.value().attr("href")?.to_string();
+        let url = format!("{}{}", self.base_url, href);
+        let container = self.find_book_container(*element);
+
+        if let Some(book) = self.embedded_record(container.unwrap_or(*element), &url) {
+            return Some(book);
+        }
+        if let Some(book) = Self::structured_result(element, &url) {
+            return Some(book);
+        }
+
         let title = element.text().collect::<String>().trim().to_string();
         
         if title.is_empty() {
@@ -192,8 +1944,7 @@ impl AnnaScraper {
         }
         
         // Find parent container for metadata
-        let container = self.find_book_container(*element)?;
-        let container_text = container.text().collect::<String>();
+        let container_text = container?.text().collect::<String>();
         
         Some(Book {
             title: title.clone(),
@@ -201,11 +1952,63 @@ impl AnnaScraper {
             year: self.extract_year(&container_text),
             language: self.extract_language(&container_text),
             format: self.extract_format(&container_text),
-            size: self.extract_size(&container_text),
-            url: format!("https://annas-archive.org{}", href),
+            size_bytes: self.extract_size(&container_text),
+            md5: md5_from_url(&url),
+            url,
+            metadata: Metadata { identifiers: parse_identifiers(&container_text), ..Metadata::default() },
         })
     }
     
+    /// A result whose container carries its record as
+    /// `<script type="application/json">`, in the md5 lookup's format.
+    fn embedded_record(&self, container: scraper::ElementRef, url: &str) -> Option<Book> {
+        let selector = selector!("script[type='application/json']")?;
+        let md5 = md5_from_url(url)?;
+        let json = container.select(selector).next()?.text().collect::<String>();
+        let mut book = self.parse_record(&md5, &json).ok()?;
+        book.url = url.to_string();
+        Some(book)
+    }
+
+    /// A result in the site's layout: the title in an `h3`, a grey line of
+    /// comma-separated facts ("English [en], .pdf, 5.2MB, ..."), the
+    /// publisher and year on one line and the author in italics.
+    fn structured_result(element: &scraper::ElementRef, url: &str) -> Option<Book> {
+        let select = |selector: Option<&Selector>| {
+            let text = element.select(selector?).next()?.text().collect::<String>();
+            Some(text.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|t| !t.is_empty())
+        };
+        let title = select(selector!("h3"))?;
+        let info = ResultInfo::parse(&select(selector!("[class*='text-gray-500']")).unwrap_or_default());
+        let imprint = select(selector!("div.truncate:not(.italic)"));
+        let imprint_year: Option<u16> = imprint.as_deref().and_then(|line| {
+            regex!(r"\b(?:19|20)\d{2}\b")?.find_iter(line).last()?.as_str().parse().ok()
+        });
+        // "Publisher, 2019" or just the publisher
+        let publisher = imprint.as_deref().and_then(|line| {
+            let name = match imprint_year {
+                Some(year) => line.trim_end().strip_suffix(&year.to_string()).unwrap_or(line),
+                None => line,
+            };
+            Some(name.trim().trim_end_matches(',').trim().to_string()).filter(|n| !n.is_empty())
+        });
+
+        Some(Book {
+            title,
+            author: select(selector!(".italic")),
+            year: imprint_year.or(info.year),
+            language: info.language,
+            format: info.format,
+            size_bytes: info.size_bytes,
+            md5: md5_from_url(url),
+            url: url.to_string(),
+            metadata: Metadata {
+                details: BookDetails { publisher, ..BookDetails::default() },
+                ..Metadata::default()
+            },
+        })
+    }
+
     fn find_book_container<'a>(&self, element: scraper::ElementRef<'a>) -> Option<scraper::ElementRef<'a>> {
         let mut current = element;
         
@@ -233,33 +2036,33 @@ impl AnnaScraper {
             let line = line.trim();
             if line.is_empty() || line == exclude { continue; }
             // Author usually appears as a name without brackets or special chars
-            if line.len() < 50 && !line.starts_with('[') && !line.contains("http") {
-                if line.chars().all(|c| c.is_alphabetic() || c.is_whitespace() || c == ',' || c == '.') {
-                    return Some(line.to_string());
-                }
+            if line.len() < 50
+                && !line.starts_with('[')
+                && !line.contains("http")
+                && matches!(Format::from_extension(line), Format::Other(_))
+                && line.chars().all(|c| c.is_alphabetic() || c.is_whitespace() || c == ',' || c == '.')
+            {
+                return Some(line.to_string());
             }
         }
         None
     }
     
-    fn extract_year(&self, text: &str) -> Option<String> {
-        let re = regex::Regex::new(r"\b(19|20)\d{2}\b").ok()?;
-        re.find(text).map(|m| m.as_str().to_string())
+    fn extract_year(&self, text: &str) -> Option<u16> {
+        regex!(r"\b(19|20)\d{2}\b")?.find(text).and_then(|m| m.as_str().parse().ok())
     }
     
-    fn extract_language(&self, text: &str) -> Option<String> {
-        let re = regex::Regex::new(r"(\w+)\s+\[([a-z]{2})\]").ok()?;
-        re.captures(text).and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
+    /// Reads the code from "English [en]".
+    fn extract_language(&self, text: &str) -> Option<LanguageCode> {
+        regex!(r"(\w+)\s+\[([a-z]{2})\]")?.captures(text).and_then(|caps| LanguageCode::new(&caps[2]))
     }
     
-    fn extract_format(&self, text: &str) -> Option<String> {
-        let re = regex::Regex::new(r"\b(EPUB|PDF|MOBI|AZW3|TXT|DOC|DOCX)\b").ok()?;
-        re.find(text).map(|m| m.as_str().to_string())
+    fn extract_format(&self, text: &str) -> Option<Format> {
+        regex!(r"\b(EPUB|PDF|MOBI|AZW3|FB2|DJVU|TXT|DOCX?|RTF|CBZ|CBR|CB7|M4B|MP3|FLAC)\b")?.find(text).map(|m| Format::from_extension(m.as_str()))
     }
     
-    fn extract_size(&self, text: &str) -> Option<String> {
-        let re = regex::Regex::new(r"(\d+\.?\d*\s*[MKG]B)").ok()?;
-        re.find(text).map(|m| m.as_str().to_string())
+    fn extract_size(&self, text: &str) -> Option<u64> {
+        regex!(r"(\d+\.?\d*\s*[MKGT]B)")?.find(text).and_then(|m| parse_size(m.as_str()))
     }
     
     fn extract_links_from_section(&self, section: &scraper::ElementRef) -> Vec<DownloadLink> {
@@ -267,19 +2070,17 @@ impl AnnaScraper {
         let mut seen_urls = std::collections::HashSet::new();
         
         let link_selectors = [
-            "a[href*='libgen']",
-            "a[href*='download']",
-            "a.download-link",
-            "a[href*='mirror']",
+            selector!("a[href*='libgen']"),
+            selector!("a[href*='download']"),
+            selector!("a.download-link"),
+            selector!("a[href*='mirror']"),
         ];
         
-        for selector_str in &link_selectors {
-            if let Ok(selector) = Selector::parse(selector_str) {
-                for element in section.select(&selector) {
-                    if let Some(link) = self.extract_download_link(element) {
-                        if seen_urls.insert(link.url.clone()) {
-                            links.push(link);
-                        }
+        for selector in link_selectors.into_iter().flatten() {
+            for element in section.select(selector) {
+                if let Some(link) = self.extract_download_link(element) {
+                    if seen_urls.insert(link.url.clone()) {
+                        links.push(link);
                     }
                 }
             }
@@ -294,14 +2095,43 @@ impl AnnaScraper {
         
         Some(DownloadLink {
             text,
-            url: href.clone(),
             source: self.detect_source(&href),
+            kind: LinkKind::from_url(&href),
+            url: href,
         })
     }
     
+    /// Collection torrents and magnet links anywhere on a book page; the
+    /// site lists them apart from the mirrors.
+    fn extract_torrent_links(&self, document: &Html) -> Vec<DownloadLink> {
+        let Some(selector) = selector!("a[href^='magnet:'], a[href*='.torrent']") else {
+            return Vec::new();
+        };
+        document.select(selector)
+            .filter_map(|element| self.extract_download_link(element))
+            .filter(|link| link.kind != LinkKind::Http)
+            .map(|mut link| {
+                if link.url.starts_with('/') {
+                    link.url = format!("{}{}", self.base_url, link.url);
+                }
+                if link.text.is_empty() {
+                    link.text = link.source.clone();
+                }
+                link
+            })
+            .collect()
+    }
+    
     fn detect_source(&self, href: &str) -> String {
+        match LinkKind::from_url(href) {
+            LinkKind::Magnet => return "Magnet".to_string(),
+            LinkKind::Torrent => return "Torrent".to_string(),
+            LinkKind::Http => {}
+        }
         if href.contains("libgen") {
             "LibGen".to_string()
+        } else if href.contains("/ipfs/") {
+            "IPFS".to_string()
         } else if href.contains("annas") {
             "Anna's Archive".to_string()
         } else if href.contains("mirror") {
@@ -310,18 +2140,57 @@ impl AnnaScraper {
             "Unknown".to_string()
         }
     }
-    
-    fn random_user_agent() -> String {
-        use rand::seq::SliceRandom;
-        
-        let user_agents = [
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-        ];
-        
-        user_agents.choose(&mut rand::thread_rng()).unwrap().to_string()
+}
+
+impl SourceProvider for AnnaScraper {
+    fn name(&self) -> &'static str {
+        "Anna's Archive"
+    }
+
+    fn search<'a>(&'a self, query: &'a str, filters: &'a SearchFilters, max_results: usize) -> BoxFuture<'a, Result<SearchResults>> {
+        Box::pin(AnnaScraper::search(self, query, filters, max_results))
+    }
+
+    fn search_paged<'a>(&'a self, query: &'a str, filters: &'a SearchFilters, max_results: usize, pages: PageSink) -> BoxFuture<'a, Result<SearchResults>> {
+        Box::pin(async move { AnnaScraper::search_paged(self, query, filters, max_results, Some(&pages)).await })
+    }
+
+    fn details<'a>(&'a self, book_url: &'a str) -> BoxFuture<'a, Result<BookPage>> {
+        Box::pin(self.book_page(book_url))
+    }
+
+    /// Pages on any of the site's mirrors, and LibGen's when it is the
+    /// fallback.
+    fn owns(&self, book_url: &str) -> bool {
+        self.mirror_path(book_url).is_some() || self.libgen.as_ref().is_some_and(|libgen| libgen.owns(book_url))
+    }
+}
+
+/// IPFS content IDs on a book page, from gateway links and the technical
+/// details: CIDv0 (`Qm…`) and base32 CIDv1 (`baf…`).
+pub fn parse_ipfs_cids(html: &str) -> Vec<String> {
+    let mut cids: Vec<String> = Vec::new();
+    let Some(re) = regex!(r"\b(Qm[1-9A-HJ-NP-Za-km-z]{44}|baf[a-z2-7]{56,})\b") else {
+        return cids;
+    };
+    for m in re.find_iter(html) {
+        if !cids.iter().any(|c| c == m.as_str()) {
+            cids.push(m.as_str().to_string());
+        }
     }
+    cids
+}
+
+/// A link to each CID through each gateway, gateway by gateway.
+fn ipfs_links(cids: &[String], gateways: &[String]) -> Vec<DownloadLink> {
+    gateways.iter()
+        .flat_map(|gateway| cids.iter().map(move |cid| DownloadLink {
+            text: format!("IPFS ({})", gateway.split("://").last().unwrap_or(gateway)),
+            url: format!("{}/ipfs/{}", gateway, cid),
+            source: "IPFS".to_string(),
+            kind: LinkKind::Http,
+        }))
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -329,53 +2198,358 @@ pub struct DownloadLink {
     pub text: String,
     pub url: String,
     pub source: String,
+    pub kind: LinkKind,
+}
+
+/// How a link delivers the file. Only `Http` links can be downloaded
+/// directly; the others are for a torrent client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    #[default]
+    Http,
+    /// A `.torrent` of the collection the file is in.
+    Torrent,
+    /// A `magnet:` link.
+    Magnet,
+}
+
+impl LinkKind {
+    pub fn from_url(url: &str) -> Self {
+        if url.trim_start().to_ascii_lowercase().starts_with("magnet:") {
+            LinkKind::Magnet
+        } else if url.split(['?', '#']).next().is_some_and(|path| path.to_ascii_lowercase().ends_with(".torrent")) {
+            LinkKind::Torrent
+        } else {
+            LinkKind::Http
+        }
+    }
+}
+
+/// Index of the first link matching the earliest preference, or 0.
+/// Preferences match the link text, source or URL, case-insensitively.
+pub fn preferred_link<S: AsRef<str>>(links: &[DownloadLink], preferences: &[S]) -> usize {
+    preferences
+        .iter()
+        .find_map(|pref| links.iter().position(|link| link.is_http() && link.matches_source(pref.as_ref())))
+        .unwrap_or(0)
 }
 
 impl DownloadLink {
-    pub fn is_reliable(&self) -> bool {
-        self.source == "LibGen" && self.text.to_lowercase().contains("libgen")
+    /// Whether the file can be downloaded from this link, rather than being
+    /// a torrent or magnet link.
+    pub fn is_http(&self) -> bool {
+        self.kind == LinkKind::Http
+    }
+
+    fn matches_source(&self, preference: &str) -> bool {
+        let preference = preference.trim().to_lowercase();
+        !preference.is_empty()
+            && [&self.text, &self.source, &self.url]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&preference))
+    }
+
+    /// How likely a link of this kind is to work, from 0 to 1: LibGen's
+    /// files first, slow-download queues and unknown hosts last.
+    pub fn source_score(&self) -> f64 {
+        let text = format!("{} {}", self.text, self.url).to_lowercase();
+        match self.source.as_str() {
+            "LibGen" if text.contains("libgen") => 1.0,
+            "LibGen" => 0.9,
+            "Anna's Archive" if text.contains("fast") => 0.9,
+            "Anna's Archive" if text.contains("slow") => 0.4,
+            "Anna's Archive" => 0.6,
+            "IPFS" | "Mirror" => 0.5,
+            _ => 0.3,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server;
+
+    #[test]
+    fn test_scrape_error_from_status() {
+        assert_eq!(ScrapeError::from_status(429, "https://a/search"), ScrapeError::RateLimited { retry_after: None });
+        assert_eq!(ScrapeError::from_status(403, "https://a/search"), ScrapeError::Blocked { status: 403 });
+        assert_eq!(ScrapeError::from_status(404, "https://a/md5/x"), ScrapeError::NotFound("https://a/md5/x".to_string()));
+        let err = ScrapeError::from_status(502, "https://a/search");
+        assert_eq!(err.to_string(), "HTTP error: 502 (https://a/search)");
+    }
+
+    #[test]
+    fn test_parse_fast_download() {
+        let json = r#"{"download_url": "https://fast.example/file.epub", "account_fast_download_info": {"downloads_left": 24, "downloads_per_day": 25, "recently_downloaded_md5s": []}}"#;
+        let fast = AnnaScraper::parse_fast_download(json).unwrap();
+        assert_eq!(fast.url, "https://fast.example/file.epub");
+        assert_eq!(fast.downloads_left, Some(24));
+        assert_eq!(fast.downloads_per_day, Some(25));
+        assert_eq!(fast.link().source, "Anna's Archive");
+
+        let json = r#"{"download_url": null, "error": "No downloads left"}"#;
+        let err = AnnaScraper::parse_fast_download(json).unwrap_err();
+        assert!(err.to_string().contains("No downloads left"));
+
+        assert!(AnnaScraper::parse_fast_download("<html>").is_err());
+    }
+
+    #[test]
+    fn test_parse_book_details() {
+        let html = r#"<html><head>
+            <meta name="description" content="Fallback description">
+        </head><body>
+            <img src="/covers/dune.jpg" alt="Cover">
+            <div class="js-md5-top-box-description">
+                Set on the desert planet
+                Arrakis.
+            </div>
+            <div>Publisher: Ace Books</div>
+            <dl><dt>Edition</dt><dd>40th anniversary edition</dd><dt>Pages</dt><dd>604 pages</dd></dl>
+            <div>Publisher: Chilton</div>
+        </body></html>"#;
+        let details = AnnaScraper::parse_book_details("https://annas-archive.org/md5/abc", html);
+        assert_eq!(details, BookDetails {
+            description: Some("Set on the desert planet Arrakis.".to_string()),
+            publisher: Some("Ace Books".to_string()),
+            edition: Some("40th anniversary edition".to_string()),
+            pages: Some(604),
+            cover_url: Some("https://annas-archive.org/covers/dune.jpg".to_string()),
+        });
+        assert_eq!(details.summary().as_deref(), Some("Ace Books, 40th anniversary edition, 604 pages"));
+
+        let details = AnnaScraper::parse_book_details("https://annas-archive.org/md5/abc", r#"<head><meta name="description" content=" Fallback  description "></head>"#);
+        assert_eq!(details.description.as_deref(), Some("Fallback description"));
+        assert_eq!(details.summary(), None);
+
+        let mut merged = BookDetails { publisher: Some("Ace".to_string()), ..BookDetails::default() };
+        merged.merge(BookDetails { publisher: Some("Chilton".to_string()), pages: Some(412), ..BookDetails::default() });
+        assert_eq!(merged.publisher.as_deref(), Some("Ace"));
+        assert_eq!(merged.pages, Some(412));
+    }
+
+    #[test]
+    fn test_patterns_compile_once() {
+        let year = || regex!(r"^(?:19|20)\d{2}$");
+        let row = || selector!("a[href*='md5']");
+        assert!(std::ptr::eq(year().unwrap(), year().unwrap()));
+        assert!(std::ptr::eq(row().unwrap(), row().unwrap()));
+        assert!(year().unwrap().is_match("1965"));
+
+        // A bad pattern is None rather than a panic
+        assert!(selector!("a >> > b").is_none());
+    }
+
+    #[test]
+    fn test_editions_section() {
+        let html = r#"<body>
+            <div class="text-3xl">Other editions of Dune are rare</div>
+            <div><h2>Same title</h2>
+                <div class="flex"><a href="/md5/0cc175b9c0f1b6a831c399e269772661">Dune</a></div>
+                <h2>Lists</h2>
+                <div><a href="/md5/1f0e3dad99908345f7439f8ffabdffc4">Dune Messiah</a></div>
+            </div>
+        </body>"#;
+        let section = editions_section(html).unwrap();
+        assert!(section.contains("0cc175b9c0f1b6a831c399e269772661"));
+        assert!(!section.contains("1f0e3dad99908345f7439f8ffabdffc4"));
+
+        assert_eq!(editions_section("<div>Other editions</div>"), None);
+        assert!(editions_section("<h3>Other editions (1)</h3><div><a href='/md5/x'>x</a></div>").is_some());
+        assert_eq!(editions_section("<p>No other editions here</p><a href='/md5/x'>x</a>"), None);
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        let json = r#"{"id": "md5:d41d8cd98f00b204e9800998ecf8427e", "file_unified_data": {
+            "title_best": "Don Quixote", "author_best": "Miguel de Cervantes", "year_best": "2003",
+            "language_codes": ["en", "es"], "extension_best": "epub", "filesize_best": 2202009,
+            "title_additional": [],
+            "identifiers_unified": {"isbn13": ["978-0-14-243723-0"], "oclc": ["55044254"], "md5": ["ignored"]},
+            "publisher_best": "Penguin Classics", "edition_varia_best": " ", "cover_url_best": "https://covers.test/q.jpg"
+        }}"#;
+        let scraper = AnnaScraper::new(&Config { archive_url: "https://mirror.test".to_string(), ..Config::default() }).unwrap();
+        let book = scraper.parse_record(md5, json).unwrap();
+        assert_eq!(book.title, "Don Quixote");
+        assert_eq!(book.url, format!("https://mirror.test/md5/{}", md5));
+        assert_eq!(book.author.as_deref(), Some("Miguel de Cervantes"));
+        assert_eq!(book.year, Some(2003));
+        assert_eq!(book.language, LanguageCode::new("en"));
+        assert_eq!(book.format, Some(Format::Epub));
+        assert_eq!(book.size_bytes, Some(2202009));
+        assert_eq!(book.display_size(), "2.1MB");
+        assert_eq!(book.md5(), Some(md5));
+        assert_eq!(book.metadata.identifiers.isbn(), Some("9780142437230"));
+        assert_eq!(book.metadata.identifiers.oclc, vec!["55044254"]);
+        assert_eq!(book.metadata.details.publisher.as_deref(), Some("Penguin Classics"));
+        assert_eq!(book.metadata.details.edition, None);
+        assert_eq!(book.metadata.details.cover_url.as_deref(), Some("https://covers.test/q.jpg"));
+
+        // Sparse records still produce a usable book
+        let book = scraper.parse_record(md5, r#"{"file_unified_data": {}}"#).unwrap();
+        assert_eq!(book.title, md5);
+        assert_eq!(book.author, None);
+        assert_eq!(book.size_bytes, None);
+        assert_eq!(book.display_year(), "Unknown");
+
+        assert!(scraper.parse_record(md5, "{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_md5s_rejects_invalid_and_dedupes() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let results = scraper.lookup_md5s(&["not-a-hash", " not-a-hash", "NOT-A-HASH"]).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "not-a-hash");
+        assert!(results[0].1.is_err());
+    }
+
+    #[test]
+    fn test_domain_filter() {
+        let filter = DomainFilter::from_config(&Config {
+            blocked_domains: vec!["Bad-Mirror.com".to_string()],
+            ..Config::default()
+        });
+        assert!(filter.allows("https://libgen.li/ads.php?md5=x"));
+        assert!(!filter.allows("https://bad-mirror.com/file"));
+        assert!(!filter.allows("https://cdn.bad-mirror.com/file"));
+        assert!(filter.allows("https://notbad-mirror.com/file"));
+
+        let filter = DomainFilter::from_config(&Config {
+            allowed_domains: vec!["libgen.li".to_string(), ".ipfs.io".to_string()],
+            blocked_domains: vec!["dweb.ipfs.io".to_string()],
+            ..Config::default()
+        });
+        assert!(filter.allows("https://libgen.li/ads.php?md5=x"));
+        assert!(filter.allows("https://gateway.ipfs.io/ipfs/Qm"));
+        assert!(!filter.allows("https://dweb.ipfs.io/ipfs/Qm"));
+        assert!(!filter.allows("https://libgen.rs/get.php"));
+        assert!(!filter.allows("/relative/path"));
+
+        assert!(DomainFilter::default().allows("/relative/path"));
+    }
+
+    #[test]
+    fn test_preferred_link() {
+        let link = |text: &str, url: &str, source: &str| DownloadLink {
+            text: text.to_string(),
+            url: url.to_string(),
+            source: source.to_string(),
+            kind: LinkKind::Http,
+        };
+        let links = vec![
+            link("Slow Partner Server #1", "https://annas-archive.org/slow_download/x/0/0", "Anna's Archive"),
+            link("IPFS Gateway", "https://ipfs.io/ipfs/Qm", "IPFS"),
+            link("Libgen.li", "https://libgen.li/ads.php?md5=x", "LibGen"),
+        ];
+
+        assert_eq!(preferred_link(&links, &["libgen"]), 2);
+        assert_eq!(preferred_link(&links, &["IPFS", "libgen"]), 1);
+        assert_eq!(preferred_link(&links, &["zlib", "slow"]), 0);
+        assert_eq!(preferred_link(&links, &["zlib"]), 0);
+        assert_eq!(preferred_link::<&str>(&links, &[]), 0);
+    }
+
+    #[test]
+    fn test_md5_from_reference() {
+        let md5_from_reference = |input| md5_from_reference(input, &Config::default());
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        assert_eq!(md5_from_reference(md5).as_deref(), Some(md5));
+        assert_eq!(md5_from_reference("  D41D8CD98F00B204E9800998ECF8427E ").as_deref(), Some(md5));
+        assert_eq!(
+            md5_from_reference("https://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e").as_deref(),
+            Some(md5)
+        );
+        assert_eq!(
+            md5_from_reference("https://annas-archive.li/md5/d41d8cd98f00b204e9800998ecf8427e?tab=downloads#x").as_deref(),
+            Some(md5)
+        );
+
+        assert_eq!(md5_from_reference("rust programming"), None);
+        assert_eq!(md5_from_reference("https://annas-archive.org/search?q=rust"), None);
+        assert_eq!(md5_from_reference("https://libgen.rs/md5/d41d8cd98f00b204e9800998ecf8427e"), None);
+
+        assert_eq!(Book::from_md5("https://annas-archive.org", md5).md5(), Some(md5));
+
+        // Links from a configured mirror or onion service open too
+        let config = Config {
+            archive_url: "http://books.lan:8080".to_string(),
+            archive_mirrors: vec!["https://annas.example/".to_string()],
+            onion_url: Some("http://annasarchive.onion".to_string()),
+            tor: true,
+            ..Config::default()
+        };
+        for link in ["http://books.lan:8080/md5/", "https://annas.example/md5/", "http://annasarchive.onion/md5/", "https://annas-archive.se/md5/"] {
+            assert_eq!(super::md5_from_reference(&format!("{}{}", link, md5), &config).as_deref(), Some(md5), "{}", link);
+        }
+        assert_eq!(super::md5_from_reference(&format!("http://books.lan/md5/{}", md5), &config), None);
+    }
+
+    #[test]
+    fn test_parse_result_count() {
+        let html = r#"<div class="text-sm">1,340 results (approx)</div>"#;
+        let total = AnnaScraper::parse_result_count(html).unwrap();
+        assert_eq!(total, ResultCount { count: 1340, approximate: true });
+        assert_eq!(total.to_string(), "~1,340");
+
+        let total = AnnaScraper::parse_result_count("<p>Found <b>42</b> results</p>").unwrap();
+        assert_eq!(total, ResultCount { count: 42, approximate: false });
+        assert_eq!(total.to_string(), "42");
+
+        let total = AnnaScraper::parse_result_count("<p>10000+ results</p>").unwrap();
+        assert!(total.approximate);
+        assert_eq!(total.to_string(), "~10,000");
+
+        assert_eq!(AnnaScraper::parse_result_count("<p>No results</p>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fast_download_requires_login() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let err = scraper.fast_download("d41d8cd98f00b204e9800998ecf8427e").await.unwrap_err();
+        assert_eq!(err.to_string(), "Not logged in");
+    }
 
     #[test]
     fn test_extract_year() {
-        let scraper = AnnaScraper::new().unwrap();
-        assert_eq!(scraper.extract_year("Some Book (2023)"), Some("2023".to_string()));
-        assert_eq!(scraper.extract_year("Old Book [1999]"), Some("1999".to_string()));
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        assert_eq!(scraper.extract_year("Some Book (2023)"), Some(2023));
+        assert_eq!(scraper.extract_year("Old Book [1999]"), Some(1999));
         assert_eq!(scraper.extract_year("No Year Here"), None);
     }
 
     #[test]
     fn test_extract_language() {
-        let scraper = AnnaScraper::new().unwrap();
-        assert_eq!(scraper.extract_language("English [en]"), Some("English".to_string()));
-        assert_eq!(scraper.extract_language("Russian [ru]"), Some("Russian".to_string()));
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        assert_eq!(scraper.extract_language("English [en]"), LanguageCode::new("en"));
+        assert_eq!(scraper.extract_language("Russian [ru]"), LanguageCode::new("ru"));
         assert_eq!(scraper.extract_language("No Lang"), None);
     }
 
     #[test]
     fn test_extract_format() {
-        let scraper = AnnaScraper::new().unwrap();
-        assert_eq!(scraper.extract_format("File.PDF"), Some("PDF".to_string()));
-        assert_eq!(scraper.extract_format("Book in EPUB format"), Some("EPUB".to_string()));
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        assert_eq!(scraper.extract_format("File.PDF"), Some(Format::Pdf));
+        assert_eq!(scraper.extract_format("Book in EPUB format"), Some(Format::Epub));
+        assert_eq!(scraper.extract_format("English [en], CB7, 120.5MB"), Some(Format::Cb7));
+        assert_eq!(scraper.extract_format("English [en], M4B, 1.2GB"), Some(Format::M4b));
         assert_eq!(scraper.extract_format("Unknown format"), None);
     }
 
     #[test]
     fn test_extract_size() {
-        let scraper = AnnaScraper::new().unwrap();
-        assert_eq!(scraper.extract_size("Size: 1.5MB"), Some("1.5MB".to_string()));
-        assert_eq!(scraper.extract_size("100KB"), Some("100KB".to_string()));
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        assert_eq!(scraper.extract_size("Size: 1.5MB"), Some(1572864));
+        assert_eq!(scraper.extract_size("100KB"), Some(102400));
         assert_eq!(scraper.extract_size("No size"), None);
     }
 
     #[test]
     fn test_detect_source() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         assert_eq!(scraper.detect_source("http://libgen.rs/book"), "LibGen");
         assert_eq!(scraper.detect_source("https://annas-archive.org/md5/..."), "Anna's Archive");
         assert_eq!(scraper.detect_source("http://example.com/mirror/1"), "Mirror");
@@ -384,7 +2558,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_parse_search_results() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = r#"
         <html>
             <body>
@@ -420,20 +2594,270 @@ mod tests {
         assert_eq!(books[0].title, "Test Book");
         assert_eq!(books[0].url, "https://annas-archive.org/md5/12345");
         // extract_year regex: r"\b(19|20)\d{2}\b"
-        assert_eq!(books[0].year.as_deref(), Some("2023"));
+        assert_eq!(books[0].year, Some(2023));
         // extract_language regex: r"(\w+)\s+\[([a-z]{2})\]" -> matches "English [en]"
-        assert_eq!(books[0].language.as_deref(), Some("English"));
-        assert_eq!(books[0].format.as_deref(), Some("PDF"));
-        assert_eq!(books[0].size.as_deref(), Some("1.5MB"));
+        assert_eq!(books[0].language, LanguageCode::new("en"));
+        assert_eq!(books[0].display_language(), "English [en]");
+        assert_eq!(books[0].format, Some(Format::Pdf));
+        assert_eq!(books[0].size_bytes, parse_size("1.5MB"));
+        assert_eq!(books[0].display_size(), "1.5MB");
 
         assert_eq!(books[1].title, "Another Book");
         assert_eq!(books[1].author.as_deref(), Some("John Doe"));
-        assert_eq!(books[1].format.as_deref(), Some("EPUB"));
+        assert_eq!(books[1].format, Some(Format::Epub));
+    }
+
+    /// Serves numbered search pages of `per_page` books each, up to `books`
+    /// in all; pages past the end repeat the last one as the site does.
+    /// Also returns the requests seen.
+    async fn serve_search_pages(books: usize, per_page: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let last_page = books.div_ceil(per_page);
+        test_server::serve_with(move |request| {
+            let page: usize = request_path(request).split("&page=").nth(1).map_or(1, |p| p.parse().unwrap()).min(last_page);
+            let links: String = ((page - 1) * per_page..(page * per_page).min(books))
+                .map(|i| format!("<div class=\"book-item\"><a href=\"/md5/{:032x}\" class=\"js-vim-focus custom-a\">Book {}</a><div>EPUB, {}MB</div></div>", i, i, i + 1))
+                .collect();
+            test_server::response("200 OK", "text/html", &format!("<html><body><div>{} results</div>{}</body></html>", books, links))
+        }).await
+    }
+
+    fn request_path(request: &str) -> &str {
+        request.split_whitespace().nth(1).unwrap()
+    }
+
+    #[test]
+    fn test_mirror_list() {
+        let mirrors = mirror_list(&Config::default());
+        assert_eq!(mirrors, ARCHIVE_DOMAINS.to_vec());
+
+        let config = Config {
+            archive_url: "https://annas-archive.se/".to_string(),
+            archive_mirrors: vec!["https://annas.example/".to_string(), "https://annas-archive.org".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(mirror_list(&config), vec![
+            "https://annas-archive.se", "https://annas.example", "https://annas-archive.org", "https://annas-archive.li",
+        ]);
+
+        // A self-hosted or test address doesn't fall back to the public site
+        let config = Config { archive_url: "http://127.0.0.1:8080".to_string(), ..Config::default() };
+        assert_eq!(mirror_list(&config), vec!["http://127.0.0.1:8080"]);
+
+        // The onion service comes first, and only in Tor mode
+        let onion = "http://annasarchive.onion/";
+        let config = Config { onion_url: Some(onion.to_string()), ..Config::default() };
+        assert_eq!(mirror_list(&config), ARCHIVE_DOMAINS.to_vec());
+        let config = Config { tor: true, ..config };
+        assert_eq!(mirror_list(&config)[..2], ["http://annasarchive.onion", "https://annas-archive.org"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_html_fails_over_to_next_mirror() {
+        let dead_url = test_server::unused_url().await;
+        let (live_url, requests) = serve_search_pages(3, 20).await;
+
+        let config = Config { archive_url: dead_url, archive_mirrors: vec![live_url], retry_max: 0, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        let results = scraper.search("dune", &SearchFilters::default(), 5).await.unwrap();
+        assert_eq!(results.books.len(), 3);
+        // Book URLs stay on archive_url and are moved when fetched
+        assert!(results.books[0].url.starts_with(&config.archive_url));
+        assert_eq!(scraper.active_mirror.load(Ordering::Relaxed), 1);
+
+        // The working mirror is tried first from then on, also by the
+        // scraper the next command creates
+        let scraper = AnnaScraper::new(&config).unwrap();
+        assert_eq!(scraper.active_mirror.load(Ordering::Relaxed), 1);
+        scraper.search("dune", &SearchFilters::default(), 5).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_falls_back_to_libgen() {
+        let dead_url = test_server::unused_url().await;
+        let (libgen_url, _server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<table class=c>\
+             <tr><td>1</td><td>Frank Herbert</td><td><a href='book/index.php?md5=5C1B4EE5D18A5C6A9E047FA74B8F37D1'>Dune</a></td>\
+             <td>Ace</td><td>2005</td><td>528</td><td>English</td><td>1 Mb</td><td>epub</td></tr></table>",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n<a href='/get.php?md5=5c1b4ee5d18a5c6a9e047fa74b8f37d1'>GET</a>",
+        ]).await;
+
+        let config = Config { archive_url: dead_url.clone(), libgen_mirrors: vec![libgen_url.clone()], retry_max: 0, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        let results = scraper.search("dune", &SearchFilters::default(), 5).await.unwrap();
+        assert_eq!(results.books.len(), 1);
+        assert_eq!(results.books[0].md5(), Some("5c1b4ee5d18a5c6a9e047fa74b8f37d1"));
+        assert!(results.books[0].url.starts_with(&libgen_url));
+
+        // LibGen books open on LibGen
+        let page = scraper.book_page(&results.books[0].url).await.unwrap();
+        assert_eq!(page.links[0].url, format!("{}/get.php?md5=5c1b4ee5d18a5c6a9e047fa74b8f37d1", libgen_url));
+
+        // Without the fallback the site's error stands
+        let config = Config { libgen_fallback: false, ..config };
+        let err = AnnaScraper::new(&config).unwrap().search("dune", &SearchFilters::default(), 5).await.unwrap_err();
+        assert!(retry::classify(&err).is_some());
+    }
+
+    #[test]
+    fn test_parse_ipfs_cids() {
+        let html = r#"<a href="https://ipfs.io/ipfs/QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG?filename=dune.epub">IPFS</a>
+            <div>ipfs_cid: bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi</div>
+            <div>QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG Qmshort</div>"#;
+        assert_eq!(parse_ipfs_cids(html), vec![
+            "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG",
+            "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+        ]);
+        assert!(parse_ipfs_cids("<div>No CIDs here</div>").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_book_page_adds_ipfs_gateways() {
+        let (base, _server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n\
+             <ul id=\"external-downloads\"><li><a href=\"https://annas-archive.org/slow_download/abc/0/0\">Slow Partner Server #1</a></li></ul>\
+             <div>IPFS CID: QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG</div>",
+        ]).await;
+        let config = Config {
+            archive_url: base.clone(),
+            ipfs_gateways: vec!["https://ipfs.io/".to_string(), " https://dweb.link".to_string(), "https://blocked.example".to_string()],
+            blocked_domains: vec!["blocked.example".to_string()],
+            ..Config::default()
+        };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        let page = scraper.book_page(&format!("{}/md5/abc", base)).await.unwrap();
+        let links: Vec<(&str, &str)> = page.links.iter().map(|l| (l.source.as_str(), l.url.as_str())).collect();
+        assert_eq!(links, vec![
+            ("Anna's Archive", "https://annas-archive.org/slow_download/abc/0/0"),
+            ("IPFS", "https://ipfs.io/ipfs/QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"),
+            ("IPFS", "https://dweb.link/ipfs/QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"),
+        ]);
+        assert_eq!(page.links[2].text, "IPFS (dweb.link)");
+    }
+
+    #[test]
+    fn test_is_challenge() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let page = r#"<!DOCTYPE html><html><head><title>Just a moment...</title></head><body><script>window._cf_chl_opt={}</script></body></html>"#;
+        assert!(is_challenge(&headers, page));
+        assert!(!is_challenge(&headers, "<html><title>Search - Anna's Archive</title>Just a moment of your time</html>"));
+        headers.insert("cf-mitigated", "challenge".parse().unwrap());
+        assert!(is_challenge(&headers, ""));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_html_detects_challenges_and_rate_limits() {
+        let (base, _server) = test_server::serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 60\r\nConnection: close\r\n\r\n<html><script src=\"/cdn-cgi/challenge-platform/h/b\"></script>",
+            "HTTP/1.1 403 Forbidden\r\ncf-mitigated: challenge\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let config = Config { archive_url: base.clone(), retry_max: 0, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        let url = format!("{}/search?q=dune", base);
+
+        let err = scraper.fetch_html(&url).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ScrapeError::Challenge { status: 200 }));
+        let err = scraper.fetch_html(&url).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ScrapeError::Challenge { status: 403 }));
+        let err = scraper.fetch_html(&url).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ScrapeError::RateLimited { retry_after: Some(120) }));
+        assert!(err.to_string().contains("it asks to wait 120s"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_html_waits_out_rate_limits() {
+        let (base, _server) = test_server::serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 13\r\nConnection: close\r\n\r\n<html></html>",
+        ]).await;
+        let config = Config { archive_url: base.clone(), retry_base_ms: 10, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        assert_eq!(scraper.fetch_html(&format!("{}/", base)).await.unwrap(), "<html></html>");
+    }
+
+    #[test]
+    fn test_fails_over() {
+        assert!(fails_over(&anyhow::Error::new(ScrapeError::Blocked { status: 403 })));
+        assert!(fails_over(&anyhow::Error::new(ScrapeError::Http { status: 451, url: "u".to_string() })));
+        assert!(fails_over(&anyhow::Error::new(Stalled(30))));
+        assert!(!fails_over(&anyhow::Error::new(ScrapeError::NotFound("u".to_string()))));
+        assert!(!fails_over(&anyhow::Error::new(ScrapeError::LayoutChanged { saved: None })));
+    }
+
+    #[tokio::test]
+    async fn test_search_fetches_further_pages() {
+        let (base, requests) = serve_search_pages(45, 20).await;
+        let scraper = AnnaScraper::new(&Config { archive_url: base, ..Config::default() }).unwrap();
+
+        let results = scraper.search("dune", &SearchFilters::default(), 30).await.unwrap();
+        assert_eq!(results.books.len(), 30);
+        assert_eq!(results.books[29].title, "Book 29");
+
+        // Asking for more than there are stops at the result count
+        let results = scraper.search("dune", &SearchFilters::default(), 50).await.unwrap();
+        assert_eq!(results.books.len(), 45);
+
+        // Size filters keep paging until enough books pass
+        let filters = SearchFilters { max_size_mb: Some(5.0), ..Default::default() };
+        let results = scraper.search("dune", &filters, 10).await.unwrap();
+        assert_eq!(results.books.len(), 5);
+
+        let requests = requests.lock().unwrap();
+        let paths: Vec<&str> = requests.iter().map(|r| request_path(r)).collect();
+        assert_eq!(paths, vec![
+            "/search?q=dune", "/search?q=dune&page=2",
+            "/search?q=dune", "/search?q=dune&page=2", "/search?q=dune&page=3",
+            "/search?q=dune", "/search?q=dune&page=2", "/search?q=dune&page=3",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_search_keeps_books_when_a_later_page_fails() {
+        let first_page: String = (0..20)
+            .map(|i| format!("<div class=\"book-item\"><a href=\"/md5/{:032x}\" class=\"js-vim-focus custom-a\">Book {}</a><div>EPUB, 1MB</div></div>", i, i))
+            .collect();
+        let first_page = test_server::response("200 OK", "text/html", &format!("<html><body><div>45 results</div>{}</body></html>", first_page));
+        let (base, _requests) = test_server::serve_with(move |request| {
+            if request_path(request).contains("&page=") {
+                test_server::response("500 Internal Server Error", "text/html", "")
+            } else {
+                first_page.clone()
+            }
+        }).await;
+        // The LibGen fallback must not add its books to the ones shown
+        let config = Config { archive_url: base, libgen_mirrors: vec![test_server::unused_url().await], retry_max: 0, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let results = scraper.search_paged("dune", &SearchFilters::default(), 30, Some(&tx)).await.unwrap();
+        assert_eq!(results.books.len(), 20);
+        assert_eq!(rx.try_recv().unwrap().books.len(), 20);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_sends_each_page() {
+        let (base, _requests) = serve_search_pages(45, 20).await;
+        let scraper = AnnaScraper::new(&Config { archive_url: base, ..Config::default() }).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let results = scraper.search_paged("dune", &SearchFilters::default(), 30, Some(&tx)).await.unwrap();
+        assert_eq!(results.books.len(), 30);
+
+        // The second page is cut at what was asked for
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.books.len(), 20);
+        assert_eq!(first.total.map(|t| t.count), Some(45));
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.books.len(), 10);
+        assert_eq!(second.books[0].title, "Book 20");
+        assert!(rx.try_recv().is_err());
     }
 
     #[tokio::test]
     async fn test_parse_download_links() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = r#"
         <html>
             <body>
@@ -454,9 +2878,43 @@ mod tests {
         assert_eq!(links[1].source, "Anna's Archive");
     }
 
+    #[tokio::test]
+    async fn test_parse_download_links_torrents() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let html = r#"<html><body>
+            <div id="external-downloads">
+                <a href="http://libgen.li/ads" class="download-link">Libgen.li</a>
+                <a href="/dyn/small_file/torrents/managed_by_aa/libgen_rs_fic.torrent" class="download-link">libgen_rs_fic</a>
+            </div>
+            <h3>Bulk torrent downloads</h3>
+            <a href="/dyn/small_file/torrents/managed_by_aa/libgen_rs_fic.torrent">libgen_rs_fic</a>
+            <a href="magnet:?xt=urn:btih:c9e15763f722f23e98a29decdfae341b98d53056&amp;dn=dune"></a>
+        </body></html>"#;
+        let links = scraper.parse_download_links(html).await.unwrap();
+        let summary: Vec<(&str, &str, LinkKind)> = links.iter().map(|l| (l.text.as_str(), l.source.as_str(), l.kind)).collect();
+        assert_eq!(summary, vec![
+            ("Libgen.li", "LibGen", LinkKind::Http),
+            ("libgen_rs_fic", "Torrent", LinkKind::Torrent),
+            ("Magnet", "Magnet", LinkKind::Magnet),
+        ]);
+        assert_eq!(links[1].url, "https://annas-archive.org/dyn/small_file/torrents/managed_by_aa/libgen_rs_fic.torrent");
+        assert!(links[2].url.starts_with("magnet:?xt=urn:btih:c9e15763f722f23e98a29decdfae341b98d53056&dn=dune"));
+
+        // Preferences never pick a torrent
+        assert_eq!(preferred_link(&links, &["torrent", "magnet", "libgen"]), 0);
+    }
+
+    #[test]
+    fn test_link_kind_from_url() {
+        assert_eq!(LinkKind::from_url("magnet:?xt=urn:btih:abc"), LinkKind::Magnet);
+        assert_eq!(LinkKind::from_url("https://annas-archive.org/torrents/x.TORRENT?download=1"), LinkKind::Torrent);
+        assert_eq!(LinkKind::from_url("https://annas-archive.org/torrents"), LinkKind::Http);
+        assert_eq!(LinkKind::from_url("https://libgen.li/get.php?md5=x&name=a.torrent"), LinkKind::Http);
+    }
+
     #[test]
     fn test_extract_author_basic() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Test Book\nJohn Doe\n2023\nPDF";
         let result = scraper.extract_author(text, "Test Book");
         assert_eq!(result, Some("John Doe".to_string()));
@@ -464,7 +2922,7 @@ mod tests {
 
     #[test]
     fn test_extract_author_with_comma() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Book Title\nSmith, Jane\nEnglish";
         let result = scraper.extract_author(text, "Book Title");
         assert_eq!(result, Some("Smith, Jane".to_string()));
@@ -472,7 +2930,7 @@ mod tests {
 
     #[test]
     fn test_extract_author_filters_urls() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Title\nhttp://example.com\nReal Author\n2020";
         let result = scraper.extract_author(text, "Title");
         assert_eq!(result, Some("Real Author".to_string()));
@@ -480,7 +2938,7 @@ mod tests {
 
     #[test]
     fn test_extract_author_filters_brackets() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Title\n[Special Edition]\nAuthor Name";
         let result = scraper.extract_author(text, "Title");
         assert_eq!(result, Some("Author Name".to_string()));
@@ -488,7 +2946,7 @@ mod tests {
 
     #[test]
     fn test_extract_author_too_long() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let long_text = "This is a very long line that exceeds fifty characters and should be filtered out";
         let text = format!("Title\n{}\nShort Author", long_text);
         let result = scraper.extract_author(&text, "Title");
@@ -497,7 +2955,7 @@ mod tests {
 
     #[test]
     fn test_extract_author_with_special_chars() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Title\nAuthor123\nO'Brien\n2020";
         // "Author123" contains digits, should be filtered
         // "O'Brien" contains apostrophe, which passes the alphabetic check
@@ -511,52 +2969,465 @@ mod tests {
 
     #[test]
     fn test_extract_author_no_valid_author() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let text = "Title\n2023\nPDF\n1.5MB";
-        let result = scraper.extract_author(text, "Title");
-        // Current implementation finds "PDF" as it's all alphabetic
-        // In a real scenario, this would be filtered by better heuristics
-        assert!(result.is_some() || result == Some("PDF".to_string()));
+        assert_eq!(scraper.extract_author(text, "Title"), None);
+    }
+
+    #[test]
+    fn test_result_info() {
+        let info = ResultInfo::parse("English [en], Spanish [es], .pdf, 🚀/lgli/zlib, 5.2MB, 📘 Book (non-fiction), 2019, lgli/dune.pdf");
+        assert_eq!(info, ResultInfo {
+            language: LanguageCode::new("en"),
+            format: Some(Format::Pdf),
+            size_bytes: parse_size("5.2MB"),
+            year: Some(2019),
+        });
+        assert_eq!(ResultInfo::parse(""), ResultInfo::default());
+    }
+
+    #[tokio::test]
+    async fn test_parse_search_results_structured() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let html = r#"<html><body>
+            <div class="h-[125] flex flex-col justify-center">
+                <a href="/md5/5c1b4ee5d18a5c6a9e047fa74b8f37d1" class="js-vim-focus custom-a flex items-center">
+                    <div class="flex-none"><img src="https://covers.test/dune.jpg"></div>
+                    <div class="relative top-[-1] pl-4 grow overflow-hidden">
+                        <div class="line-clamp-[2] text-xs text-gray-500">English [en], .epub, 🚀/zlib, 0.8MB, 📘 Book (fiction), zlib/dune.epub</div>
+                        <h3 class="text-xl font-bold">Dune</h3>
+                        <div class="truncate leading-[1.2]">Ace Books, 1990</div>
+                        <div class="truncate leading-[1.2] italic">Frank Herbert</div>
+                    </div>
+                </a>
+            </div>
+            <div class="h-[125] flex flex-col justify-center">
+                <a href="/md5/1f0e3dad99908345f7439f8ffabdffc4" class="js-vim-focus custom-a flex items-center">
+                    <div class="line-clamp-[2] text-xs text-gray-500">.pdf, 1.2MB</div>
+                    <h3 class="text-xl font-bold">PDF Untitled</h3>
+                </a>
+            </div>
+        </body></html>"#;
+
+        let books = scraper.parse_search_results(html, 10).await.unwrap();
+        assert_eq!(books.len(), 2);
+        assert_eq!(books[0].title, "Dune");
+        assert_eq!(books[0].author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(books[0].year, Some(1990));
+        assert_eq!(books[0].language, LanguageCode::new("en"));
+        assert_eq!(books[0].format, Some(Format::Epub));
+        assert_eq!(books[0].size_bytes, parse_size("0.8MB"));
+        assert_eq!(books[0].metadata.details.publisher.as_deref(), Some("Ace Books"));
+        assert_eq!(books[0].md5(), Some("5c1b4ee5d18a5c6a9e047fa74b8f37d1"));
+
+        assert_eq!(books[1].author, None);
+        assert_eq!(books[1].year, None);
+        assert_eq!(books[1].format, Some(Format::Pdf));
+    }
+
+    #[tokio::test]
+    async fn test_parse_search_results_embedded_record() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let html = r#"<html><body>
+            <div class="flex pt-3">
+                <a href="/md5/5c1b4ee5d18a5c6a9e047fa74b8f37d1" class="js-vim-focus custom-a">Dune PDF 2021</a>
+                <script type="application/json">{"file_unified_data": {"title_best": "Dune", "author_best": "Frank Herbert",
+                    "year_best": "1965", "language_codes": ["en"], "extension_best": "pdf", "filesize_best": 1258291}}</script>
+            </div>
+        </body></html>"#;
+
+        let books = scraper.parse_search_results(html, 10).await.unwrap();
+        assert_eq!(books[0].title, "Dune");
+        assert_eq!(books[0].author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(books[0].year, Some(1965));
+        assert_eq!(books[0].format, Some(Format::Pdf));
+        assert_eq!(books[0].url, "https://annas-archive.org/md5/5c1b4ee5d18a5c6a9e047fa74b8f37d1");
+    }
+
+    #[test]
+    fn test_book_md5() {
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        assert_eq!(Book::from_md5("https://annas-archive.org", md5).md5(), Some(md5));
+        assert_eq!(md5_from_url(&format!("https://annas-archive.org/md5/{}", md5)).as_deref(), Some(md5));
+        assert_eq!(md5_from_url("https://annas-archive.org/md5/12345"), None);
+        assert_eq!(md5_from_url("https://annas-archive.org/search?q=test"), None);
+
+        // The MD5 identifies a book whichever mirror listed it
+        let mut book = Book::from_md5("https://annas-archive.org", md5);
+        assert_eq!(book.id(), md5);
+        book.url = format!("https://annas-archive.se/md5/{}", md5);
+        assert_eq!(book.id(), Book::from_md5("https://annas-archive.org", md5).id());
+        book.md5 = None;
+        assert_eq!(book.id(), book.url);
+    }
+
+    #[tokio::test]
+    async fn test_parse_search_results_merges_links_to_one_book() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let html = r#"<html><body>
+            <div class="book-item"><a href="/md5/d41d8cd98f00b204e9800998ecf8427e">Dune</a></div>
+            <div class="book-item"><a href="https://annas-archive.se/md5/D41D8CD98F00B204E9800998ECF8427E">Dune</a></div>
+            <div class="book-item"><a href="/md5/5c1b4ee5d18a5c6a9e047fa74b8f37d1">Dune Messiah</a></div>
+        </body></html>"#;
+        let books = scraper.parse_search_results(html, 10).await.unwrap();
+        let md5s: Vec<_> = books.iter().map(|b| b.md5()).collect();
+        assert_eq!(md5s, vec![Some("d41d8cd98f00b204e9800998ecf8427e"), Some("5c1b4ee5d18a5c6a9e047fa74b8f37d1")]);
+    }
+
+    #[test]
+    fn test_parse_identifiers() {
+        let text = "ISBN-13: 978-0-441-01359-3 ISBN-10 0441013597 ISBN 1234567890 \
+            9780142437230 ASIN: B00B7NPRY8 OCLC number: 12345678 DOI 10.1000/xyz123.";
+        let ids = parse_identifiers(text);
+        assert_eq!(ids.isbn13, vec!["9780441013593", "9780142437230"]);
+        // The bad check digit is dropped
+        assert_eq!(ids.isbn10, vec!["0441013597"]);
+        assert_eq!(ids.asin, vec!["B00B7NPRY8"]);
+        assert_eq!(ids.oclc, vec!["12345678"]);
+        assert_eq!(ids.doi, vec!["10.1000/xyz123"]);
+        assert_eq!(ids.isbn(), Some("9780441013593"));
+        assert!(parse_identifiers("Dune by Frank Herbert, 1965").is_empty());
+
+        let mut merged = Identifiers { isbn10: vec!["0441013597".to_string()], ..Default::default() };
+        merged.merge(ids);
+        assert_eq!(merged.isbn10, vec!["0441013597"]);
+        assert_eq!(merged.to_string(), "ISBN 9780441013593, ISBN 9780142437230, ISBN 0441013597, ASIN B00B7NPRY8, OCLC 12345678, DOI 10.1000/xyz123");
+    }
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(parse_month("3"), Some(3));
+        assert_eq!(parse_month("13"), None);
+        assert_eq!(parse_month("December"), Some(12));
+        assert_eq!(parse_month("sep"), Some(9));
+        assert_eq!(parse_month("Sept."), Some(9));
+        assert_eq!(parse_month("ma"), None);
+        assert_eq!(parse_month("Monday"), None);
+    }
+
+    #[test]
+    fn test_parse_issue() {
+        assert_eq!(parse_issue("Wired No. 12 (December 2019)"), Some(Issue {
+            volume: None,
+            number: Some(12),
+            year: Some(2019),
+            month: Some(12),
+        }));
+        assert_eq!(parse_issue("National Geographic Vol. 240 #3 Mar 2021"), Some(Issue {
+            volume: Some(240),
+            number: Some(3),
+            year: Some(2021),
+            month: Some(3),
+        }));
+        let economist = parse_issue("The Economist - 2023-05-13").unwrap();
+        assert_eq!((economist.year, economist.month), (Some(2023), Some(5)));
+        assert_eq!(economist.to_string(), "May 2023");
+        // An out-of-range month from a saved record is left out, not a panic
+        let zero = Issue { volume: None, number: Some(4), year: Some(2020), month: Some(0) };
+        assert_eq!(zero.to_string(), "No. 4, 2020");
+        // Ordinary book titles aren't issues
+        assert_eq!(parse_issue("Dune"), None);
+        assert_eq!(parse_issue("Nineteen Eighty-Four, 1949"), None);
+        assert_eq!(parse_issue("Notes from 2019"), None);
+    }
+
+    #[test]
+    fn test_book_file_name() {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "Dune".to_string();
+        book.author = Some("Frank Herbert".to_string());
+        assert_eq!(book.file_name(), "Dune - Frank Herbert");
+        book.format = Some(Format::from_extension("epub"));
+        assert_eq!(book.file_name(), "Dune - Frank Herbert.epub");
+    }
+
+    #[test]
+    fn test_issue_filter() {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "Wired Issue 7".to_string();
+        book.year = Some(2019);
+        assert_eq!(book.issue(), None);
+        book.metadata.periodical = true;
+        assert_eq!(book.issue().unwrap().to_string(), "No. 7, 2019");
+
+        assert!(!SearchFilters::default().periodicals());
+        assert!(SearchFilters { index: SearchIndex::Comics, ..Default::default() }.periodicals());
+        assert!(SearchFilters { content: vec![ContentType::Magazine], ..Default::default() }.periodicals());
+
+        let filters = SearchFilters { issue_year: Some(2019), ..Default::default() };
+        assert!(filters.matches_issue(&book));
+        let filters = SearchFilters { issue_year: Some(2019), issue_month: Some(5), ..Default::default() };
+        assert!(!filters.matches_issue(&book));
+        assert!(SearchFilters::default().matches_issue(&book));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::from_extension(".EPUB"), Format::Epub);
+        assert_eq!(Format::from_extension("lit"), Format::Other("lit".to_string()));
+        assert_eq!(Format::Azw3.to_string(), "AZW3");
+        assert_eq!(Format::from_extension("cb7"), Format::Cb7);
+        assert_eq!(Format::from_extension("M4B"), Format::M4b);
+        assert_eq!(Format::Other("lit".to_string()).extension(), "lit");
+        assert_eq!(serde_json::to_string(&Format::Pdf).unwrap(), "\"pdf\"");
+        assert_eq!(serde_json::from_str::<Format>("\"CBZ\"").unwrap(), Format::Cbz);
+    }
+
+    #[test]
+    fn test_language_code() {
+        let en = LanguageCode::new(" EN ").unwrap();
+        assert_eq!(en, LanguageCode("en".to_string()));
+        assert_eq!(en.to_string(), "English [en]");
+        assert_eq!(LanguageCode::new("zh-Hant").unwrap().name(), Some("Chinese"));
+        assert_eq!(LanguageCode::new("tlh").unwrap().to_string(), "tlh");
+        assert_eq!(LanguageCode::new("english"), None);
+        assert_eq!(LanguageCode::new("e1"), None);
+    }
+
+    #[test]
+    fn test_search_url() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        assert_eq!(
+            scraper.search_url("dune messiah", &SearchFilters::default()),
+            "https://annas-archive.org/search?q=dune%20messiah"
+        );
+        let filters = SearchFilters {
+            format: Some("epub,mobi".to_string()),
+            language: Some("en, DE, english".to_string()),
+            content: vec![ContentType::Fiction, ContentType::Comic],
+            magazines: true,
+            sort: SortOrder::Smallest,
+            ..Default::default()
+        };
+        assert_eq!(
+            scraper.search_url("dune", &filters),
+            "https://annas-archive.org/search?q=dune&ext=epub&ext=mobi&lang=en&lang=de&content=book_fiction&content=book_comic&content=magazine&sort=smallest"
+        );
+    }
+
+    #[test]
+    fn test_content_type_from_str() {
+        assert_eq!("fiction".parse(), Ok(ContentType::Fiction));
+        assert_eq!("Non-Fiction".parse(), Ok(ContentType::Nonfiction));
+        assert_eq!("comics".parse(), Ok(ContentType::Comic));
+        assert_eq!("journal_article".parse(), Ok(ContentType::Article));
+        assert_eq!("standards".parse(), Ok(ContentType::Standard));
+        assert!("poetry".parse::<ContentType>().unwrap_err().contains("nonfiction, fiction"));
+        // Saved searches store the site's values
+        assert_eq!(serde_json::to_string(&ContentType::Score).unwrap(), "\"musical_score\"");
+    }
+
+    #[test]
+    fn test_query_identifier_parse() {
+        let dune = Some(QueryIdentifier::Isbn("9780441172719".to_string()));
+        assert_eq!(QueryIdentifier::parse("9780441172719"), dune);
+        assert_eq!(QueryIdentifier::parse(" 978-0-441-17271-9 "), dune);
+        assert_eq!(QueryIdentifier::parse("ISBN-13: 978 0 441 17271 9"), dune);
+        assert_eq!(QueryIdentifier::parse("0441172717"), dune);
+        assert_eq!(QueryIdentifier::parse("isbn 0-8044-2957-x"), Some(QueryIdentifier::Isbn("9780804429573".to_string())));
+        assert_eq!(QueryIdentifier::parse("9780441172718"), None);
+        assert_eq!(QueryIdentifier::parse("dune 1965"), None);
+        assert_eq!(QueryIdentifier::parse("1984"), None);
+
+        let doi = Some(QueryIdentifier::Doi("10.1038/nature14539".to_string()));
+        assert_eq!(QueryIdentifier::parse("10.1038/nature14539"), doi);
+        assert_eq!(QueryIdentifier::parse("doi:10.1038/Nature14539"), doi);
+        assert_eq!(QueryIdentifier::parse("https://doi.org/10.1038/nature14539"), doi);
+        assert_eq!(QueryIdentifier::parse("10.1038 nature"), None);
+    }
+
+    #[test]
+    fn test_parse_paper() {
+        let url = "https://annas-archive.org/scidb/10.1038/nature14539";
+        let html = r#"<html><body>
+            <h1>Deep
+                learning</h1>
+            <a href="/md5/5c1b4ee5d18a5c6a9e047fa74b8f37d1">Record in Anna's Archive</a>
+            <embed src="/scidb_pdf/10.1038/nature14539.pdf" type="application/pdf">
+        </body></html>"#;
+        let paper = AnnaScraper::parse_paper("10.1038/nature14539", url, html).unwrap();
+        assert_eq!(paper.title.as_deref(), Some("Deep learning"));
+        assert_eq!(paper.pdf_url, "https://annas-archive.org/scidb_pdf/10.1038/nature14539.pdf");
+        assert_eq!(paper.md5.as_deref(), Some("5c1b4ee5d18a5c6a9e047fa74b8f37d1"));
+        assert_eq!(paper.filename(), "Deep learning");
+
+        // Download links work too, and the DOI stands in for a missing title
+        let html = r#"<a download href="https://mirror.test/paper.pdf">Download</a>"#;
+        let paper = AnnaScraper::parse_paper("10.1038/nature14539", url, html).unwrap();
+        assert_eq!(paper.pdf_url, "https://mirror.test/paper.pdf");
+        assert_eq!(paper.filename(), "10.1038_nature14539");
+        assert_eq!(paper.to_book().metadata.identifiers.doi, vec!["10.1038/nature14539"]);
+
+        let err = AnnaScraper::parse_paper("10.1038/nature14539", url, "<p>Not found</p>").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ScrapeError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_paper_rejects_non_dois() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let err = scraper.paper("nature14539").await.unwrap_err();
+        assert!(err.to_string().contains("is not a DOI"));
+    }
+
+    #[test]
+    fn test_isbn_conversion() {
+        assert_eq!(isbn10_to_13("0441172717"), "9780441172719");
+        assert_eq!(isbn13_to_10("9780804429573").as_deref(), Some("080442957X"));
+        assert_eq!(isbn13_to_10("9791032305690"), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_identifier_matches_listing() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let html = r#"<html><body>
+            <div class="book-item"><a href="/md5/1" class="js-vim-focus custom-a">Dune Messiah</a><div>EPUB ISBN 9780593098233</div></div>
+            <div class="book-item"><a href="/md5/2" class="js-vim-focus custom-a">Dune</a><div>EPUB ISBN: 0-441-17271-7</div></div>
+        </body></html>"#;
+        let books = scraper.parse_search_results(html, 10).await.unwrap();
+        let id = QueryIdentifier::parse("978-0-441-17271-9").unwrap();
+        assert!(!id.matches(&books[0]));
+        assert!(id.matches(&books[1]));
+
+        assert_eq!(
+            scraper.search_url("ISBN 0441172717", &SearchFilters::default()),
+            "https://annas-archive.org/search?q=isbn13%3A9780441172719"
+        );
+        assert_eq!(
+            scraper.search_url("https://doi.org/10.1038/nature14539", &SearchFilters::default()),
+            "https://annas-archive.org/search?q=doi%3A10.1038%2Fnature14539"
+        );
     }
 
     #[test]
-    fn test_download_link_is_reliable() {
+    fn test_search_index() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+        let url = |index| scraper.search_url("dune", &SearchFilters { index, ..Default::default() });
+        assert_eq!(url(SearchIndex::Books), "https://annas-archive.org/search?q=dune");
+        assert_eq!(url(SearchIndex::Journals), "https://annas-archive.org/search?q=dune&index=journals");
+        assert_eq!(url(SearchIndex::Comics), "https://annas-archive.org/search?q=dune&content=book_comic");
+
+        // The journals index narrows the search alongside the content types
+        let filters = SearchFilters { index: SearchIndex::Journals, content: vec![ContentType::Article], ..Default::default() };
+        assert_eq!(scraper.search_url("protein", &filters), "https://annas-archive.org/search?q=protein&content=journal_article&index=journals");
+
+        // --magazine, --content and the magazine or comic index don't send
+        // the content type twice
+        let filters = SearchFilters { index: SearchIndex::Magazines, magazines: true, ..Default::default() };
+        assert_eq!(scraper.search_url("wired", &filters), "https://annas-archive.org/search?q=wired&content=magazine");
+        let filters = SearchFilters { index: SearchIndex::Comics, content: vec![ContentType::Comic], ..Default::default() };
+        assert_eq!(scraper.search_url("saga", &filters), "https://annas-archive.org/search?q=saga&content=book_comic");
+
+        assert_eq!("Papers".parse(), Ok(SearchIndex::Journals));
+        assert!("music".parse::<SearchIndex>().is_err());
+        assert_eq!(SearchIndex::Comics.next(), SearchIndex::Books);
+    }
+
+    #[test]
+    fn test_sort_order_from_str() {
+        assert_eq!("Newest".parse(), Ok(SortOrder::Newest));
+        assert_eq!("small".parse(), Ok(SortOrder::Smallest));
+        assert!("random".parse::<SortOrder>().is_err());
+        assert_eq!(SortOrder::default(), SortOrder::Relevance);
+    }
+
+    #[test]
+    fn test_format_filter_extensions() {
+        assert_eq!(format_filter_extensions(""), Vec::<String>::new());
+        assert_eq!(format_filter_extensions("PDF"), vec!["pdf"]);
+        assert_eq!(format_filter_extensions("cbz, .cbr"), vec!["cbz", "cbr"]);
+        assert_eq!(format_filter_extensions("comics,cbz"), vec!["cbz", "cbr", "cb7"]);
+        assert_eq!(format_filter_extensions("audiobooks"), vec!["m4b", "mp3", "flac"]);
+    }
+
+    #[test]
+    fn test_download_link_source_score() {
         let link = DownloadLink {
             text: "Libgen.li Fast Download".to_string(),
             url: "http://libgen.li/ads/12345".to_string(),
             source: "LibGen".to_string(),
+            kind: LinkKind::Http,
         };
-        assert!(link.is_reliable());
+        assert_eq!(link.source_score(), 1.0);
 
         let unreliable = DownloadLink {
             text: "Slow Mirror".to_string(),
             url: "http://example.com/mirror".to_string(),
             source: "Mirror".to_string(),
+            kind: LinkKind::Http,
         };
-        assert!(!unreliable.is_reliable());
+        assert!(unreliable.source_score() < link.source_score());
+
+        let slow = DownloadLink {
+            text: "Slow Partner Server #1".to_string(),
+            url: "https://annas-archive.org/slow_download/abc/0/0".to_string(),
+            source: "Anna's Archive".to_string(),
+            kind: LinkKind::Http,
+        };
+        let fast = DownloadLink { text: "Fast download (member)".to_string(), ..slow.clone() };
+        assert!(slow.source_score() < fast.source_score());
     }
 
     #[test]
-    fn test_download_link_is_reliable_case_insensitive() {
+    fn test_download_link_source_score_case_insensitive() {
         let link = DownloadLink {
             text: "LIBGEN Fast".to_string(),
             url: "http://libgen.rs/get.php".to_string(),
             source: "LibGen".to_string(),
+            kind: LinkKind::Http,
+        };
+        assert_eq!(link.source_score(), 1.0);
+    }
+
+    fn filler_page(body: &str) -> String {
+        format!(
+            "<html><head><style>{}</style></head><body>{}</body></html>",
+            "x".repeat(LAYOUT_CHECK_MIN_BYTES),
+            body,
+        )
+    }
+
+    #[test]
+    fn test_check_layout() {
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
+
+        let err = scraper.check_layout(&filler_page("<div class=\"new-layout\">Dune</div>"), None).unwrap_err();
+        assert_eq!(err.downcast_ref::<ScrapeError>(), Some(&ScrapeError::LayoutChanged { saved: None }));
+        assert!(err.to_string().contains("layout likely changed"));
+
+        // Genuinely empty results, or a page too small to be a result list
+        assert!(scraper.check_layout(&filler_page("<p>No files found.</p>"), None).is_ok());
+        let none = ResultCount { count: 0, approximate: false };
+        assert!(scraper.check_layout(&filler_page(""), Some(none)).is_ok());
+        assert!(scraper.check_layout("<html><body></body></html>", None).is_ok());
+    }
+
+    #[test]
+    fn test_check_layout_saves_page() {
+        let config = Config { save_unparsed_pages: true, ..Config::default() };
+        let scraper = AnnaScraper::new(&config).unwrap();
+        let page = filler_page("<main>changed</main>");
+
+        let err = scraper.check_layout(&page, None).unwrap_err();
+        let Some(ScrapeError::LayoutChanged { saved: Some(path) }) = err.downcast_ref::<ScrapeError>() else {
+            panic!("expected a saved page: {}", err);
         };
-        assert!(link.is_reliable());
+        assert!(err.to_string().contains(&path.display().to_string()));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), page);
+        std::fs::remove_file(path).unwrap();
     }
 
     #[tokio::test]
     async fn test_parse_search_results_empty_html() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = "<html><body></body></html>";
         let books = scraper.parse_search_results(html, 10).await.unwrap();
         assert_eq!(books.len(), 0);
     }
 
     #[tokio::test]
+    #[allow(clippy::len_zero)]
     async fn test_parse_search_results_malformed_html() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = "<html><body><div><a href=unclosed";
         let books = scraper.parse_search_results(html, 10).await.unwrap();
         // Should handle malformed HTML gracefully
@@ -565,7 +3436,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_parse_search_results_no_matching_selectors() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = r#"
         <html>
             <body>
@@ -581,7 +3452,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_parse_download_links_empty_section() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = r#"
         <html>
             <body>
@@ -597,7 +3468,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_parse_download_links_no_section() {
-        let scraper = AnnaScraper::new().unwrap();
+        let scraper = AnnaScraper::new(&Config::default()).unwrap();
         let html = r#"
         <html>
             <body>
@@ -610,29 +3481,12 @@ mod tests {
     }
 
     #[test]
-    fn test_random_user_agent() {
-        let agent1 = AnnaScraper::random_user_agent();
-        let agent2 = AnnaScraper::random_user_agent();
-
-        // Should return valid user agent strings
-        assert!(agent1.contains("Mozilla"));
-        assert!(agent2.contains("Mozilla"));
-
-        // User agents should be from the list
-        let valid_agents = [
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-        ];
-        assert!(valid_agents.contains(&agent1.as_str()));
-    }
-
-    #[test]
-    fn test_parse_size_mb() {
-        assert_eq!(AnnaScraper::parse_size_mb("1.5MB"), Some(1.5));
-        assert_eq!(AnnaScraper::parse_size_mb("500KB"), Some(500.0 / 1024.0));
-        assert_eq!(AnnaScraper::parse_size_mb("1GB"), Some(1024.0));
-        assert_eq!(AnnaScraper::parse_size_mb("10.5 MB"), Some(10.5));
-        assert_eq!(AnnaScraper::parse_size_mb("Invalid"), None);
+    fn test_parse_size() {
+        assert_eq!(parse_size("1.5MB"), Some(1572864));
+        assert_eq!(parse_size("500KB"), Some(512000));
+        assert_eq!(parse_size("1GB"), Some(1 << 30));
+        assert_eq!(parse_size("10.5 MB"), Some(11010048));
+        assert_eq!(parse_size("Invalid"), None);
+        assert_eq!(parse_size("3 parsecs"), None);
     }
 }
@@ -0,0 +1,155 @@
+use crate::account::Credentials;
+use crate::config::Config;
+use crate::libgen::LibgenScraper;
+use crate::scraper::{AnnaScraper, BookPage, PageSink, SearchFilters, SearchResults};
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A catalogue that can be searched and lists download links per book.
+/// Book URLs are the provider's own and go back to the same provider.
+pub trait SourceProvider: Send + Sync {
+    /// Shown in messages, e.g. "Anna's Archive".
+    fn name(&self) -> &'static str;
+
+    fn search<'a>(&'a self, query: &'a str, filters: &'a SearchFilters, max_results: usize) -> BoxFuture<'a, Result<SearchResults>>;
+
+    /// `search`, also sending each results page to `pages` as soon as it is
+    /// parsed. Sources that read a single page send their results once.
+    fn search_paged<'a>(&'a self, query: &'a str, filters: &'a SearchFilters, max_results: usize, pages: PageSink) -> BoxFuture<'a, Result<SearchResults>> {
+        Box::pin(async move {
+            let results = self.search(query, filters, max_results).await?;
+            if !results.books.is_empty() {
+                let _ = pages.send(results.clone());
+            }
+            Ok(results)
+        })
+    }
+
+    /// The links, identifiers and details on a book's page.
+    fn details<'a>(&'a self, book_url: &'a str) -> BoxFuture<'a, Result<BookPage>>;
+
+    /// Whether `book_url` is one of this source's book pages.
+    fn owns(&self, book_url: &str) -> bool;
+}
+
+/// The registered sources, chosen with `source` or `--source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Anna's Archive at `archive_url`, falling back to LibGen when
+    /// `libgen_fallback` is on.
+    #[default]
+    Anna,
+    /// Library Genesis mirrors from `libgen_mirrors` only.
+    Libgen,
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Anna => "anna",
+            Self::Libgen => "libgen",
+        }
+    }
+}
+
+impl std::fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "anna" | "annas-archive" | "aa" => Ok(Self::Anna),
+            "libgen" | "lg" => Ok(Self::Libgen),
+            other => Err(format!("'{}' is not one of anna, libgen", other)),
+        }
+    }
+}
+
+/// The configured source, logged in where the source supports accounts.
+pub fn provider(config: &Config, credentials: Option<Credentials>) -> Result<Box<dyn SourceProvider>> {
+    provider_of(config.source, config, credentials)
+}
+
+/// The source `book_url` belongs to: the configured one, or Anna's Archive
+/// for books opened by MD5 or link, which always come from there.
+pub fn provider_for(config: &Config, credentials: Option<Credentials>, book_url: &str) -> Result<Box<dyn SourceProvider>> {
+    let configured = provider(config, credentials.clone())?;
+    if configured.owns(book_url) {
+        return Ok(configured);
+    }
+    provider_of(SourceKind::Anna, config, credentials)
+}
+
+fn provider_of(kind: SourceKind, config: &Config, credentials: Option<Credentials>) -> Result<Box<dyn SourceProvider>> {
+    Ok(match kind {
+        SourceKind::Anna => Box::new(
+            AnnaScraper::new(config)
+                .context("Failed to create scraper")?
+                .with_credentials(credentials),
+        ),
+        SourceKind::Libgen => Box::new(LibgenScraper::new(config).context("Failed to create LibGen scraper")?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[test]
+    fn test_source_kind() {
+        for kind in [SourceKind::Anna, SourceKind::Libgen] {
+            assert_eq!(kind.as_str().parse::<SourceKind>(), Ok(kind));
+        }
+        assert_eq!("LG".parse::<SourceKind>(), Ok(SourceKind::Libgen));
+        assert!("zlib".parse::<SourceKind>().is_err());
+
+        let config: Config = serde_json::from_str(r#"{"source":"libgen"}"#).unwrap();
+        assert_eq!(config.source, SourceKind::Libgen);
+        assert_eq!(Config::default().source, SourceKind::Anna);
+    }
+
+    #[test]
+    fn test_provider() {
+        assert_eq!(provider(&Config::default(), None).unwrap().name(), "Anna's Archive");
+        let config = Config { source: SourceKind::Libgen, ..Config::default() };
+        assert_eq!(provider(&config, None).unwrap().name(), "LibGen");
+
+        // Pasted MD5s open on Anna's Archive whatever the source
+        let md5 = "5c1b4ee5d18a5c6a9e047fa74b8f37d1";
+        let libgen_book = format!("https://libgen.is/book/index.php?md5={}", md5);
+        assert_eq!(provider_for(&config, None, &libgen_book).unwrap().name(), "LibGen");
+        assert_eq!(provider_for(&config, None, &format!("https://annas-archive.org/md5/{}", md5)).unwrap().name(), "Anna's Archive");
+    }
+
+    #[tokio::test]
+    async fn test_libgen_source_drops_blocked_mirrors() {
+        let md5 = "5c1b4ee5d18a5c6a9e047fa74b8f37d1";
+        let body = format!(
+            r#"<a href="http://library.lol/main/{md5}">Gen.lib.rus.ec</a><a href="http://libgen.li/ads.php?md5={md5}">Libgen.li</a>"#
+        );
+        let (mirror, _server) = test_server::serve(vec![test_server::response("200 OK", "text/html", &body)]).await;
+
+        let config = Config {
+            source: SourceKind::Libgen,
+            libgen_mirrors: vec![mirror.clone()],
+            blocked_domains: vec!["library.lol".to_string()],
+            retry_max: 0,
+            ..Config::default()
+        };
+        let book_url = format!("{}/book/index.php?md5={}", mirror, md5);
+        let source = provider_for(&config, None, &book_url).unwrap();
+        assert_eq!(source.name(), "LibGen");
+        let page = source.details(&book_url).await.unwrap();
+        let urls: Vec<&str> = page.links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(urls, vec![format!("http://libgen.li/ads.php?md5={}", md5).as_str()]);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::locale;
+use crate::scraper::Book;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const GAP: &str = "  ";
+const YEAR_WIDTH: usize = 4;
+const LANG_WIDTH: usize = 4;
+const FORMAT_WIDTH: usize = 4;
+const SIZE_WIDTH: usize = 7;
+/// Title and author never shrink below this, even on narrow terminals.
+const MIN_TEXT_WIDTH: usize = 24;
+
+/// Renders books as one aligned row each, fitted to `width` columns, with a
+/// header row first. Callers indenting the rows should pass a smaller width.
+pub fn render(books: &[Book], width: usize) -> Vec<String> {
+    let index_width = books.len().to_string().len();
+    let fixed = index_width + YEAR_WIDTH + LANG_WIDTH + FORMAT_WIDTH + SIZE_WIDTH + GAP.len() * 6;
+    let text_width = width.saturating_sub(fixed).max(MIN_TEXT_WIDTH);
+    let title_width = text_width * 3 / 5;
+    let author_width = text_width - title_width;
+
+    let row = |cells: [&str; 7]| {
+        let widths = [index_width, title_width, author_width, YEAR_WIDTH, LANG_WIDTH, FORMAT_WIDTH, SIZE_WIDTH];
+        let line = cells.iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, w))| if i == 0 { format!("{:>w$}", cell, w = w) } else { fit(cell, w) })
+            .collect::<Vec<_>>()
+            .join(GAP);
+        line.trim_end().to_string()
+    };
+
+    let mut lines = vec![row(["#", "Title", "Author", "Year", "Lang", "Fmt", "Size"])];
+    for (i, book) in books.iter().enumerate() {
+        let index = (i + 1).to_string();
+        let year = book.year.map(|y| y.to_string()).unwrap_or_else(|| "-".to_string());
+        let language = book.language.as_ref().map_or("-", |l| l.as_str());
+        let format = book.format.as_ref().map(|f| f.to_string()).unwrap_or_else(|| "-".to_string());
+        let size = book.size_bytes.map(|bytes| locale::current().size(bytes)).unwrap_or_else(|| "-".to_string());
+        lines.push(row([
+            &index,
+            &book.title,
+            book.author.as_deref().unwrap_or("-"),
+            &year,
+            language,
+            &format,
+            &size,
+        ]));
+    }
+    lines
+}
+
+/// Pads or truncates to exactly `width` display columns, marking cuts with
+/// an ellipsis.
+fn fit(text: &str, width: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.width() <= width {
+        return format!("{}{}", text, " ".repeat(width - text.width()));
+    }
+
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    if width > 0 {
+        out.push('…');
+        used += 1;
+    }
+    out.push_str(&" ".repeat(width.saturating_sub(used)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{Format, LanguageCode};
+
+    fn book(title: &str, author: Option<&str>) -> Book {
+        Book {
+            title: title.to_string(),
+            author: author.map(str::to_string),
+            year: Some(1965),
+            language: LanguageCode::new("en"),
+            format: Some(Format::Epub),
+            size_bytes: Some(1572864),
+            md5: None,
+            url: String::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("Dune", 6), "Dune  ");
+        assert_eq!(fit("Dune Messiah", 6), "Dune …");
+        assert_eq!(fit("multi\n  line", 10), "multi line");
+        // Wide characters count double
+        assert_eq!(fit("三体问题", 5), "三体…");
+        assert_eq!(fit("三体问题", 5).width(), 5);
+    }
+
+    #[test]
+    fn test_render_fits_width() {
+        let books = vec![
+            book("Dune", Some("Frank Herbert")),
+            book("A Very Long Title That Will Not Fit In The Available Space At All", None),
+        ];
+        let lines = render(&books, 80);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("#  Title"));
+        assert!(lines[1].starts_with("1  Dune"));
+        assert!(lines[1].ends_with("1965  en    EPUB  1.5MB"));
+        assert!(lines[2].contains('…'));
+        assert!(lines[2].contains("  -  "));
+        assert!(lines.iter().all(|l| l.width() <= 80));
+
+        // Columns stay aligned across rows
+        let year_at = |line: &str| line[..line.find("1965").unwrap()].width();
+        assert_eq!(year_at(&lines[1]), year_at(&lines[2]));
+    }
+
+    #[test]
+    fn test_render_narrow_terminal() {
+        let lines = render(&[book("Dune", Some("Frank Herbert"))], 10);
+        assert!(lines[1].contains("Dune"));
+        assert!(lines[1].contains("EPUB"));
+    }
+}
@@ -0,0 +1,167 @@
+//! `--template` templates: one line per search result with `{field}`
+//! placeholders, for shell pipelines that want a few fields without
+//! parsing JSON.
+
+use crate::locale;
+use crate::schema::BookJson;
+use crate::scraper::Book;
+
+/// Placeholders a template may use.
+pub const FIELDS: [&str; 16] = [
+    "index", "title", "author", "year", "language", "format", "size", "size_bytes",
+    "md5", "url", "issue", "series", "subjects", "isbn13", "isbn10", "doi",
+];
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unknown field {{{0}}}; fields are {fields}", fields = FIELDS.join(", "))]
+    UnknownField(String),
+    #[error("unclosed {{ at position {0}; write {{{{ for a literal brace")]
+    Unclosed(usize),
+    #[error("unmatched }} at position {0}; write }}}} for a literal brace")]
+    Unmatched(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Reads `{field}` placeholders, `{{` and `}}` for literal braces, and
+    /// `\t`, `\n` and `\\` escapes, since shells pass those through as typed.
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => text.push('}'),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(TemplateError::Unclosed(at)),
+                        }
+                    }
+                    let field = FIELDS.iter()
+                        .find(|f| **f == name.trim())
+                        .ok_or(TemplateError::UnknownField(name))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(TemplateError::Unmatched(at)),
+                '\\' => match chars.next_if(|&(_, c)| matches!(c, 't' | 'n' | '\\')) {
+                    Some((_, 't')) => text.push('\t'),
+                    Some((_, 'n')) => text.push('\n'),
+                    Some(_) => text.push('\\'),
+                    None => text.push('\\'),
+                },
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The template filled in for the `index`th result (counting from 1).
+    /// Unknown values are empty, and tabs and line breaks inside values
+    /// become spaces so each book stays on its own line.
+    pub fn render(&self, index: usize, book: &Book) -> String {
+        let json = BookJson::from(book);
+        self.parts.iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => value(field, index, book, &json)
+                    .split(['\t', '\n', '\r'])
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect()
+    }
+}
+
+fn value(field: &str, index: usize, book: &Book, json: &BookJson) -> String {
+    let or_empty = |value: Option<String>| value.unwrap_or_default();
+    match field {
+        "index" => index.to_string(),
+        "title" => json.title.clone(),
+        "author" => or_empty(json.author.clone()),
+        "year" => or_empty(json.year.map(|y| y.to_string())),
+        "language" => or_empty(json.language.clone()),
+        "format" => or_empty(json.format.clone()),
+        "size" => or_empty(book.size_bytes.map(|b| locale::current().size(b))),
+        "size_bytes" => or_empty(json.size_bytes.map(|b| b.to_string())),
+        "md5" => or_empty(json.md5.clone()),
+        "url" => json.url.clone(),
+        "issue" => or_empty(json.issue.as_ref().map(|i| i.to_string())),
+        "series" => or_empty(json.series.clone()),
+        "subjects" => json.subjects.join(", "),
+        "isbn13" => json.identifiers.isbn13.join(","),
+        "isbn10" => json.identifiers.isbn10.join(","),
+        "doi" => json.identifiers.doi.join(","),
+        _ => unreachable!("fields are checked when the template is parsed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{Format, LanguageCode};
+
+    fn dune() -> Book {
+        Book {
+            title: "Dune".to_string(),
+            author: Some("Frank Herbert".to_string()),
+            year: Some(1965),
+            language: LanguageCode::new("en"),
+            format: Some(Format::Epub),
+            size_bytes: Some(1572864),
+            md5: Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+            url: "https://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(r"{index}\t{title}\t{md5}\t{url}").unwrap();
+        assert_eq!(
+            template.render(1, &dune()),
+            "1\tDune\td41d8cd98f00b204e9800998ecf8427e\thttps://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e"
+        );
+
+        let template = Template::parse("{title} ({year}, {format}, {size}) {{{size_bytes}}} by { author }").unwrap();
+        assert_eq!(template.render(1, &dune()), "Dune (1965, epub, 1.5MB) {1572864} by Frank Herbert");
+    }
+
+    #[test]
+    fn test_render_missing_and_multiline_values() {
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "Dune\nMessiah\tDeluxe".to_string();
+        let template = Template::parse("{title}|{author}|{isbn13}").unwrap();
+        assert_eq!(template.render(2, &book), "Dune Messiah Deluxe||");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Template::parse("{titel}"), Err(TemplateError::UnknownField("titel".to_string())));
+        assert_eq!(Template::parse("{title"), Err(TemplateError::Unclosed(0)));
+        assert_eq!(Template::parse("title}"), Err(TemplateError::Unmatched(5)));
+        assert!(TemplateError::UnknownField("titel".to_string()).to_string().contains("md5, url"));
+        assert_eq!(Template::parse(r"a\\tb\x").unwrap().render(1, &dune()), r"a\tb\x");
+    }
+}
@@ -0,0 +1,146 @@
+//! Terminals that can't show Unicode symbols or emoji (the legacy Windows
+//! console, `TERM=dumb`, non-UTF-8 locales) get ASCII stand-ins instead, so
+//! output isn't rendered as mojibake. Book titles and other text are left
+//! alone; only the program's own symbols are replaced.
+
+use crate::config::Config;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+static ASCII: OnceLock<bool> = OnceLock::new();
+
+/// Sets whether `ascii()` is on. Only the first call has an effect.
+pub fn init(config: &Config) {
+    let _ = ASCII.set(config.ascii.unwrap_or_else(detect_ascii));
+}
+
+/// Whether output should stick to ASCII. Tests always get Unicode.
+pub fn ascii() -> bool {
+    *ASCII.get_or_init(|| !cfg!(test) && detect_ascii())
+}
+
+fn detect_ascii() -> bool {
+    let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+    if var("TERM").is_some_and(|term| term == "dumb") {
+        return true;
+    }
+    if cfg!(windows) {
+        // Windows Terminal, VS Code and ConEmu handle Unicode; conhost doesn't
+        return var("WT_SESSION").is_none() && var("TERM_PROGRAM").is_none() && var("ConEmuANSI").is_none();
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().find_map(|key| var(key));
+    locale.is_some_and(|locale| !is_utf8_locale(&locale))
+}
+
+fn is_utf8_locale(locale: &str) -> bool {
+    let locale = locale.to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// The ASCII stand-in for one of our symbols, or None for characters that
+/// are kept. Pictographs map to an empty string.
+pub fn glyph(c: char) -> Option<&'static str> {
+    let ascii = match c {
+        '…' => "...",
+        '—' | '–' => "-",
+        '•' | '·' => "*",
+        '→' => "->",
+        '←' => "<-",
+        '↑' => "^",
+        '↓' => "v",
+        '▶' | '►' => ">",
+        '✓' | '✔' => "+",
+        '✗' | '✘' => "x",
+        '⚠' => "!",
+        '\u{fe0f}' => "",
+        // Box drawing
+        '─' | '━' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '═' => "-",
+        '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '║' => "|",
+        '\u{2500}'..='\u{257f}' => "+",
+        // Sparkline and gauge blocks, lightest to fullest
+        '▁' | '▂' => ".",
+        '▃' | '▄' => ":",
+        '▅' | '▆' => "=",
+        '\u{2580}'..='\u{259f}' => "#",
+        // Emoji and other pictographs
+        '\u{2300}'..='\u{23ff}' | '\u{2600}'..='\u{27bf}' | '\u{2b00}'..='\u{2bff}' | '\u{1f000}'..='\u{1faff}' => "",
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+/// `text` with our symbols replaced when `ascii()` is on. A dropped
+/// pictograph takes the spaces after it along, so "📚 Found" becomes "Found".
+pub fn plain(text: &str) -> Cow<'_, str> {
+    if !ascii() {
+        return Cow::Borrowed(text);
+    }
+    to_ascii(text)
+}
+
+fn to_ascii(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| glyph(c).is_some()) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match glyph(c) {
+            Some("") if c != '\u{fe0f}' => {
+                chars.next_if_eq(&'\u{fe0f}');
+                while chars.next_if_eq(&' ').is_some() {}
+            }
+            Some(ascii) => out.push_str(ascii),
+            None => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// `println!` through `plain`, for messages carrying emoji or symbols.
+macro_rules! outln {
+    () => { println!() };
+    ($($arg:tt)*) => { println!("{}", $crate::term::plain(&format!($($arg)*))) };
+}
+
+/// `eprintln!` through `plain`.
+macro_rules! eoutln {
+    ($($arg:tt)*) => { eprintln!("{}", $crate::term::plain(&format!($($arg)*))) };
+}
+
+pub(crate) use {eoutln, outln};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ascii() {
+        assert_eq!(to_ascii("\n📚 Found 5 results:"), "\nFound 5 results:");
+        assert_eq!(to_ascii("⚠️  Send to device failed"), "!  Send to device failed");
+        assert_eq!(to_ascii("✅ Download complete"), "Download complete");
+        assert_eq!(to_ascii("⬇️  Downloading"), "Downloading");
+        assert_eq!(to_ascii("[✓] Config: ok"), "[+] Config: ok");
+        assert_eq!(to_ascii("Dune — Frank Herbert"), "Dune - Frank Herbert");
+        assert_eq!(to_ascii("checking…"), "checking...");
+        assert_eq!(to_ascii("┌──┐"), "+--+");
+        assert_eq!(to_ascii("▁▄█"), ".:#");
+        // Text that isn't one of our symbols is kept
+        assert_eq!(to_ascii("Мастер и Маргарита, 三体"), "Мастер и Маргарита, 三体");
+        assert!(matches!(to_ascii("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_is_utf8_locale() {
+        assert!(is_utf8_locale("en_US.UTF-8"));
+        assert!(is_utf8_locale("de_DE.utf8"));
+        assert!(!is_utf8_locale("C"));
+        assert!(!is_utf8_locale("en_US.ISO-8859-1"));
+    }
+
+    #[test]
+    fn test_tests_get_unicode() {
+        assert!(!ascii());
+        assert_eq!(plain("📚 Found"), "📚 Found");
+    }
+}
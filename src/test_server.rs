@@ -0,0 +1,117 @@
+//! Plain HTTP servers on a local port for tests that need a real socket:
+//! canned responses, truncated or stalled bodies, proxies and redirects
+//! that a mock framework would smooth over.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Answers each connection with the next of `responses`, written as is.
+/// Returns the base URL and the requests seen, once all were answered.
+pub async fn serve<R>(responses: Vec<R>) -> (String, JoinHandle<Vec<String>>)
+where
+    R: AsRef<[u8]> + Send + 'static,
+{
+    let (listener, url) = bind().await;
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            requests.push(read_request(&mut socket).await);
+            let _ = socket.write_all(response.as_ref()).await;
+        }
+        requests
+    });
+    (url, handle)
+}
+
+/// Answers every connection with `respond(request)` for as long as the
+/// test runs. Returns the base URL and the requests seen so far, each
+/// recorded before its answer goes out.
+pub async fn serve_with<F, R>(mut respond: F) -> (String, Arc<Mutex<Vec<String>>>)
+where
+    F: FnMut(&str) -> R + Send + 'static,
+    R: AsRef<[u8]> + Send,
+{
+    let (listener, url) = bind().await;
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let request = read_request(&mut socket).await;
+            let response = respond(&request);
+            seen.lock().unwrap().push(request);
+            let _ = socket.write_all(response.as_ref()).await;
+        }
+    });
+    (url, requests)
+}
+
+/// Sends `head` to the first connection and then leaves it open without
+/// another byte.
+pub async fn stall(head: &'static [u8]) -> String {
+    let (listener, url) = bind().await;
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        read_request(&mut socket).await;
+        socket.write_all(head).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+    url
+}
+
+/// An address nothing listens on: a port that was just freed.
+pub async fn unused_url() -> String {
+    let (_listener, url) = bind().await;
+    url
+}
+
+/// A canned response with `body` and a matching Content-Length.
+pub fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    (listener, url)
+}
+
+/// Reads the head and whatever body Content-Length or chunked encoding
+/// announces, which may arrive after the headers.
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !complete(&request) {
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    String::from_utf8_lossy(&request).into_owned()
+}
+
+fn complete(request: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(request);
+    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+        return false;
+    };
+    let head = head.to_ascii_lowercase();
+    if head.contains("transfer-encoding: chunked") {
+        return body.ends_with("0\r\n\r\n");
+    }
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+    body.len() >= length
+}
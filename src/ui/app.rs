@@ -1,28 +1,52 @@
+use crate::account::{AccountStatus, Credentials};
 use crate::config::Config;
-use crate::downloader::Downloader;
-use crate::scraper::{AnnaScraper, Book, DownloadLink, SearchFilters};
+use crate::delivery;
+use crate::config::LinkCheck;
+use crate::downloader::{self, DownloadProgress, Downloader, LinkHealth, TransferStats, TransferView};
+use crate::locale;
+use crate::opener;
+use crate::openlibrary;
+use crate::reliability::Scorer;
+use crate::saved::{self, SavedSearch};
+use crate::scraper::{self, AnnaScraper, Book, BookPage, DownloadLink, ResultCount, ScrapeError, SearchFilters, SearchResults};
+use crate::source;
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use futures::StreamExt;
+use indicatif::HumanDuration;
 use ratatui::{
-    backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Wrap},
-    Frame, Terminal,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Sparkline, Wrap},
+    Frame,
 };
-use std::io;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// Book pages fetched at once by a background prefetch.
+const PREFETCH_CONCURRENCY: usize = 2;
+/// Gap between the starts of prefetch requests, so a search doesn't send a
+/// burst of page loads to the site.
+const PREFETCH_SPACING: Duration = Duration::from_millis(300);
+/// How long the selection must rest on a result before its page is fetched.
+const HOVER_PREFETCH_DELAY: Duration = Duration::from_secs(1);
+
 pub enum AppMode {
     Search,
     Results,
     DownloadSelection,
     Downloading,
     Error(String),
+    /// The site's markup no longer matches the scraper.
+    LayoutChanged(String),
     Help,
     Filters,
+    /// Typing a name for the current query and filters.
+    SaveSearch,
+    SavedSearches,
 }
 
 pub struct App {
@@ -30,12 +54,20 @@ pub struct App {
     pub mode: AppMode,
     pub query: String,
     pub books: Vec<Book>,
+    /// The site's overall match count for the current query.
+    pub total_results: Option<ResultCount>,
+    /// How many results a search asks for: `-n`, else the config's default.
+    pub num_results: usize,
     pub selected_book_index: usize,
     pub download_links: Vec<DownloadLink>,
     pub download_link_index: usize,
     pub download_path: PathBuf,
     pub error_message: String,
     pub results_scroll: usize,
+    /// Preferred page size; None shows as many books as fit.
+    pub results_per_page: Option<usize>,
+    /// How many books fit the results list at the last draw.
+    fit_results: usize,
     pub help_scroll: usize,
     pub command_tx: mpsc::UnboundedSender<AppCommand>,
     pub command_rx: mpsc::UnboundedReceiver<AppCommand>,
@@ -45,15 +77,62 @@ pub struct App {
     pub filter_format_input: String,
     pub filter_language_input: String,
     pub filter_size_input: String,
+    pub transfers: TransferStats,
+    pub credentials: Option<Credentials>,
+    pub account_status: Option<AccountStatus>,
+    /// What the Open Library lookup for the open book found, for the
+    /// status bar.
+    pub enrich_status: Option<String>,
+    /// Sizes reported by the mirrors, by link URL; None while checking.
+    pub link_health: HashMap<String, Option<LinkHealth>>,
+    /// Whether the links get listed again once every mirror has answered.
+    pub checking_links: bool,
+    /// The running link check, stopped when its links are left.
+    link_probe: Option<tokio::task::JoinHandle<()>>,
+    /// The running search or pasted-book lookup and its id, stopped when
+    /// another one starts or its results are left.
+    search: Option<(usize, tokio::task::JoinHandle<()>)>,
+    next_search_id: usize,
+    /// Book pages fetched ahead of time, by book URL; None while fetching.
+    pub book_pages: HashMap<String, Option<BookPage>>,
+    /// Other editions listed on the open book's page.
+    pub editions: Vec<Book>,
+    /// URL of the selected result and when the selection reached it; the
+    /// time is cleared once its prefetch has started.
+    hover: Option<(String, Option<Instant>)>,
+    /// The database saved searches are kept in.
+    pub saved_db: PathBuf,
+    pub saved_name_input: String,
+    pub saved_searches: Vec<SavedSearch>,
+    pub saved_index: usize,
+    next_download_id: usize,
 }
 
 #[derive(Debug, Clone)]
 pub enum AppCommand {
     Search(String, SearchFilters, usize),
-    FetchDownloadLinks(String),
-    Download(String, usize),
+    /// A page of search `id`'s results, added to the list as it arrives.
+    SearchPage(usize, SearchResults),
+    /// Search `id`'s final list, which replaces the pages.
+    SearchDone(usize, SearchResults),
+    /// Search `id` failed.
+    SearchFailed(usize, String),
+    /// Search `id` got a page the scraper's selectors no longer match.
+    LayoutChanged(usize, String),
+    /// A pasted book link or MD5, opened without searching.
+    OpenBook(String),
+    /// The book opened by `OpenBook`, tagged like a search.
+    Opened(usize, Box<Book>),
+    FetchDownloadLinks(Box<Book>),
     ShowError(String),
     CompleteDownload(PathBuf),
+    DownloadProgress(DownloadProgress),
+    AccountStatus(AccountStatus),
+    LinkHealth(String, LinkHealth),
+    /// An Open Library lookup's copy of a book and the fields it changed.
+    Enriched(Box<Book>, Result<Vec<&'static str>, String>),
+    /// A prefetched book page by book URL; None when the fetch failed.
+    PrefetchedPage(String, Option<Box<BookPage>>),
 }
 
 impl App {
@@ -61,10 +140,14 @@ impl App {
         let (tx, rx) = mpsc::unbounded_channel();
         
         Self {
+            results_per_page: config.results_per_page,
+            num_results: config.num_results(None),
+            fit_results: 10,
             config,
             mode: AppMode::Search,
             query: String::new(),
             books: Vec::new(),
+            total_results: None,
             selected_book_index: 0,
             download_links: Vec::new(),
             download_link_index: 0,
@@ -80,19 +163,23 @@ impl App {
             filter_format_input: String::new(),
             filter_language_input: String::new(),
             filter_size_input: String::new(),
-        }
-    }
-
-    pub async fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
-        loop {
-            terminal.draw(|f| self.draw(f))?;
-
-            if let Event::Key(key) = event::read()? {
-                match self.handle_keypress(key).await? {
-                    ControlFlow::Continue => continue,
-                    ControlFlow::Exit => return Ok(()),
-                }
-            }
+            transfers: TransferStats::default(),
+            credentials: None,
+            account_status: None,
+            enrich_status: None,
+            link_health: HashMap::new(),
+            checking_links: false,
+            link_probe: None,
+            search: None,
+            next_search_id: 0,
+            book_pages: HashMap::new(),
+            editions: Vec::new(),
+            hover: None,
+            saved_db: saved::default_path(),
+            saved_name_input: String::new(),
+            saved_searches: Vec::new(),
+            saved_index: 0,
+            next_download_id: 0,
         }
     }
 
@@ -101,10 +188,12 @@ impl App {
             AppMode::Search => self.handle_search_input(key).await,
             AppMode::Results => self.handle_results_navigation(key).await,
             AppMode::DownloadSelection => self.handle_download_selection(key).await,
-            AppMode::Error(_) => self.handle_error(key).await,
+            AppMode::Error(_) | AppMode::LayoutChanged(_) => self.handle_error(key).await,
             AppMode::Downloading => self.handle_downloading(key).await,
             AppMode::Help => self.handle_help(key).await,
             AppMode::Filters => self.handle_filters(key).await,
+            AppMode::SaveSearch => self.handle_save_search(key).await,
+            AppMode::SavedSearches => self.handle_saved_searches(key).await,
         }
     }
 
@@ -113,14 +202,25 @@ impl App {
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 return Ok(ControlFlow::Exit);
             }
-            KeyCode::Enter => {
-                if !self.query.is_empty() {
-                    self.perform_search().await?;
-                }
+            KeyCode::Enter if !self.query.is_empty() => {
+                self.perform_search().await?;
             }
             KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 self.mode = AppMode::Filters;
             }
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) && !self.query.is_empty() => {
+                self.saved_name_input.clear();
+                self.mode = AppMode::SaveSearch;
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.open_saved_searches();
+            }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.open_download_dir();
+            }
+            KeyCode::Tab => {
+                self.filters.index = self.filters.index.next();
+            }
             KeyCode::Char(c) => {
                 self.query.push(c);
             }
@@ -140,28 +240,45 @@ impl App {
 
     async fn handle_results_navigation(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.selected_book_index < self.books.len().saturating_sub(1) {
-                    self.selected_book_index += 1;
-                    if self.selected_book_index >= self.results_scroll + 10 {
-                        self.results_scroll += 1;
-                    }
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.selected_book_index < self.books.len().saturating_sub(1) =>
+            {
+                self.selected_book_index += 1;
+                self.keep_selection_visible();
             }
-            KeyCode::Up | KeyCode::Char('k') => {
-                if self.selected_book_index > 0 {
-                    self.selected_book_index = self.selected_book_index.saturating_sub(1);
-                    if self.selected_book_index < self.results_scroll {
-                        self.results_scroll = self.selected_book_index;
-                    }
-                }
+            KeyCode::Up | KeyCode::Char('k') if self.selected_book_index > 0 => {
+                self.selected_book_index = self.selected_book_index.saturating_sub(1);
+                self.keep_selection_visible();
             }
-            KeyCode::Enter => {
-                if !self.books.is_empty() {
-                    self.fetch_download_links().await?;
+            KeyCode::PageDown if !self.books.is_empty() => {
+                self.selected_book_index = (self.selected_book_index + self.page_size()).min(self.books.len() - 1);
+                self.keep_selection_visible();
+            }
+            KeyCode::PageUp => {
+                self.selected_book_index = self.selected_book_index.saturating_sub(self.page_size());
+                self.keep_selection_visible();
+            }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.open_download_dir();
+            }
+            KeyCode::Char('+') => {
+                if let Some(n) = self.results_per_page {
+                    self.results_per_page = Some((n + 1).min(self.fit_results));
                 }
             }
+            KeyCode::Char('-') => {
+                self.results_per_page = Some(self.page_size().saturating_sub(1).max(1));
+                self.keep_selection_visible();
+            }
+            KeyCode::Char('0') => {
+                self.results_per_page = None;
+                self.keep_selection_visible();
+            }
+            KeyCode::Enter if !self.books.is_empty() => {
+                self.fetch_download_links().await?;
+            }
             KeyCode::Esc => {
+                self.stop_search();
                 self.mode = AppMode::Search;
                 self.query.clear();
                 self.books.clear();
@@ -181,23 +298,33 @@ impl App {
 
     async fn handle_download_selection(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
-            KeyCode::Down | KeyCode::Char('j') => {
-                if self.download_link_index < self.download_links.len().saturating_sub(1) {
-                    self.download_link_index += 1;
-                }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.download_link_index < self.download_links.len().saturating_sub(1) =>
+            {
+                self.download_link_index += 1;
             }
             KeyCode::Up | KeyCode::Char('k') => {
                 self.download_link_index = self.download_link_index.saturating_sub(1);
             }
-            KeyCode::Enter => {
-                if !self.download_links.is_empty() {
-                    self.perform_download().await?;
+            KeyCode::Enter => match self.download_links.get(self.download_link_index) {
+                Some(link) if !link.is_http() => {
+                    if let Err(e) = opener::open_url(&link.url) {
+                        self.error_message = format!("Could not open the torrent link: {}", e);
+                        self.mode = AppMode::Error(self.error_message.clone());
+                    }
                 }
+                Some(_) => self.perform_download().await?,
+                None => {}
+            },
+            KeyCode::Char('e') if !self.editions.is_empty() => {
+                self.show_editions();
             }
             KeyCode::Esc => {
                 self.mode = AppMode::Results;
                 self.download_links.clear();
                 self.download_link_index = 0;
+                self.stop_link_checks();
+                self.enrich_status = None;
             }
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 return Ok(ControlFlow::Exit);
@@ -210,6 +337,19 @@ impl App {
         Ok(ControlFlow::Continue)
     }
 
+    /// Replaces the results with the open book's other editions, so a book
+    /// with dead links can be swapped without searching again.
+    pub fn show_editions(&mut self) {
+        self.books = std::mem::take(&mut self.editions);
+        self.total_results = None;
+        self.selected_book_index = 0;
+        self.results_scroll = 0;
+        self.download_links.clear();
+        self.download_link_index = 0;
+        self.mode = AppMode::Results;
+        self.prefetch_top_results();
+    }
+
     async fn handle_error(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
             KeyCode::Esc | KeyCode::Enter => {
@@ -295,6 +435,97 @@ impl App {
         Ok(ControlFlow::Continue)
     }
 
+    async fn handle_save_search(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(ControlFlow::Exit);
+            }
+            KeyCode::Esc => {
+                self.mode = AppMode::Search;
+            }
+            KeyCode::Enter if !self.saved_name_input.trim().is_empty() => {
+                let search = SavedSearch {
+                    name: self.saved_name_input.trim().to_string(),
+                    query: self.query.clone(),
+                    filters: self.filters.clone(),
+                    saved_at: None,
+                };
+                match saved::Store::open(&self.saved_db).and_then(|store| store.save(&search)) {
+                    Ok(()) => self.mode = AppMode::Search,
+                    Err(e) => {
+                        self.error_message = format!("Could not save the search: {:#}", e);
+                        self.mode = AppMode::Error(self.error_message.clone());
+                    }
+                }
+            }
+            KeyCode::Char(c) => {
+                self.saved_name_input.push(c);
+            }
+            KeyCode::Backspace => {
+                self.saved_name_input.pop();
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    async fn handle_saved_searches(&mut self, key: KeyEvent) -> Result<ControlFlow> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(ControlFlow::Exit);
+            }
+            KeyCode::Esc => {
+                self.mode = AppMode::Search;
+            }
+            KeyCode::Down | KeyCode::Char('j') if self.saved_index + 1 < self.saved_searches.len() => {
+                self.saved_index += 1;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.saved_index = self.saved_index.saturating_sub(1);
+            }
+            KeyCode::Enter if !self.saved_searches.is_empty() => {
+                let search = self.saved_searches[self.saved_index].clone();
+                self.query = search.query;
+                self.set_filters(search.filters);
+                self.perform_search().await?;
+            }
+            KeyCode::Char('d') | KeyCode::Delete if !self.saved_searches.is_empty() => {
+                let name = self.saved_searches[self.saved_index].name.clone();
+                if let Err(e) = saved::Store::open(&self.saved_db).and_then(|store| store.remove(&name)) {
+                    self.error_message = format!("Could not delete the search: {:#}", e);
+                    self.mode = AppMode::Error(self.error_message.clone());
+                    return Ok(ControlFlow::Continue);
+                }
+                self.saved_searches.remove(self.saved_index);
+                self.saved_index = self.saved_index.min(self.saved_searches.len().saturating_sub(1));
+            }
+            _ => {}
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn open_saved_searches(&mut self) {
+        match saved::Store::open(&self.saved_db).and_then(|store| store.list()) {
+            Ok(searches) => {
+                self.saved_searches = searches;
+                self.saved_index = 0;
+                self.mode = AppMode::SavedSearches;
+            }
+            Err(e) => {
+                self.error_message = format!("Could not load saved searches: {:#}", e);
+                self.mode = AppMode::Error(self.error_message.clone());
+            }
+        }
+    }
+
+    /// Replaces the active filters, keeping the filter form in step.
+    fn set_filters(&mut self, filters: SearchFilters) {
+        self.filter_format_input = filters.format.clone().unwrap_or_default();
+        self.filter_language_input = filters.language.clone().unwrap_or_default();
+        self.filter_size_input = filters.max_size_mb.map(|mb| mb.to_string()).unwrap_or_default();
+        self.filters = filters;
+    }
+
     async fn handle_help(&mut self, key: KeyEvent) -> Result<ControlFlow> {
         match key.code {
             KeyCode::Esc | KeyCode::F(1) => {
@@ -315,18 +546,62 @@ impl App {
     }
 
     pub fn draw(&mut self, f: &mut Frame) {
+        let area = if self.transfers.is_empty() && self.account_status.is_none() && self.enrich_status.is_none() {
+            f.size()
+        } else {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(f.size());
+            self.draw_status_bar(f, chunks[1]);
+            chunks[0]
+        };
+
         match &self.mode {
-            AppMode::Search => self.draw_search(f),
-            AppMode::Results => self.draw_results(f),
-            AppMode::DownloadSelection => self.draw_download_selection(f),
-            AppMode::Error(msg) => self.draw_error(f, msg),
-            AppMode::Downloading => self.draw_downloading(f),
-            AppMode::Help => self.draw_help(f),
-            AppMode::Filters => self.draw_filters(f),
+            AppMode::Search => self.draw_search(f, area),
+            AppMode::Results => self.draw_results(f, area),
+            AppMode::DownloadSelection => self.draw_download_selection(f, area),
+            AppMode::Error(msg) => self.draw_error(f, area, "ERROR", Color::Red, msg),
+            AppMode::LayoutChanged(msg) => self.draw_error(f, area, "SITE LAYOUT CHANGED", Color::Yellow, msg),
+            AppMode::Downloading => self.draw_downloading(f, area),
+            AppMode::Help => self.draw_help(f, area),
+            AppMode::Filters => self.draw_filters(f, area),
+            AppMode::SaveSearch => self.draw_save_search(f, area),
+            AppMode::SavedSearches => self.draw_saved_searches(f, area),
+        }
+    }
+
+    fn draw_status_bar(&self, f: &mut Frame, area: Rect) {
+        let mut parts = Vec::new();
+        if !self.transfers.is_empty() {
+            let remaining = self.transfers.remaining()
+                .map(|b| locale::current().size(b))
+                .unwrap_or_else(|| "unknown".to_string());
+            let eta = self.transfers.eta()
+                .map(|d| HumanDuration(d).to_string())
+                .unwrap_or_else(|| "--".to_string());
+
+            parts.push(format!(
+                "{} active | {}/s | {} left | ETA {}",
+                self.transfers.active_count(),
+                locale::current().size(self.transfers.speed() as u64),
+                remaining,
+                eta
+            ));
+        }
+        if let Some(ref status) = self.account_status {
+            parts.push(status.to_string());
+        }
+        if let Some(ref status) = self.enrich_status {
+            parts.push(status.clone());
         }
+
+        let bar = Paragraph::new(format!(" {}", parts.join(" | ")))
+            .style(Style::default().fg(Color::Black).bg(Color::Cyan));
+        f.render_widget(bar, area);
     }
 
-    fn draw_search(&self, f: &mut Frame) {
+    fn draw_search(&self, f: &mut Frame, area: Rect) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -335,7 +610,7 @@ impl App {
                 Constraint::Length(3),
                 Constraint::Min(0),
             ])
-            .split(f.size());
+            .split(area);
 
         let title = Paragraph::new("Anna's Archive Downloader")
             .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -343,7 +618,7 @@ impl App {
         f.render_widget(title, chunks[0]);
 
         let input = Paragraph::new(self.query.as_str())
-            .block(Block::default().borders(Borders::ALL).title("Search Query (Enter: search, Ctrl+F: filters, Ctrl+C: quit, F1: Help)"))
+            .block(Block::default().borders(Borders::ALL).title("Search Query (Enter: search, Tab: index, Ctrl+F: filters, Ctrl+S/R: save/run saved, Ctrl+O: open folder, Ctrl+C: quit, F1: Help)"))
             .style(Style::default().fg(Color::White));
         f.render_widget(input, chunks[1]);
 
@@ -357,6 +632,19 @@ impl App {
         if let Some(size) = self.filters.max_size_mb {
             filter_text.push_str(&format!("Size < {}MB | ", size));
         }
+        if !self.filters.content.is_empty() {
+            let content: Vec<&str> = self.filters.content.iter().map(|c| c.name()).collect();
+            filter_text.push_str(&format!("Content: {} | ", content.join(", ")));
+        }
+        if self.filters.magazines {
+            filter_text.push_str("Magazines | ");
+        }
+        if self.filters.index != scraper::SearchIndex::Books {
+            filter_text.push_str(&format!("Index: {} | ", self.filters.index.as_str()));
+        }
+        if self.filters.sort != scraper::SortOrder::Relevance {
+            filter_text.push_str(&format!("Sort: {} | ", self.filters.sort.as_str()));
+        }
 
         if filter_text.is_empty() {
             filter_text = "No active filters".to_string();
@@ -370,7 +658,7 @@ impl App {
         f.render_widget(filters_info, chunks[2]);
     }
 
-    fn draw_filters(&self, f: &mut Frame) {
+    fn draw_filters(&self, f: &mut Frame, area: Rect) {
          let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -380,7 +668,7 @@ impl App {
                 Constraint::Length(3), // Size
                 Constraint::Min(0),
             ])
-            .split(f.size());
+            .split(area);
 
         let title = Paragraph::new("Search Filters")
             .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -389,7 +677,7 @@ impl App {
 
         let format_style = if self.filter_input_idx == 0 { Style::default().fg(Color::Yellow) } else { Style::default().fg(Color::White) };
         let format_input = Paragraph::new(self.filter_format_input.as_str())
-            .block(Block::default().borders(Borders::ALL).title("Format (e.g. pdf, epub)"))
+            .block(Block::default().borders(Borders::ALL).title("Format (e.g. pdf, epub, cbz,cbr, comics or audiobooks)"))
             .style(format_style);
         f.render_widget(format_input, chunks[1]);
 
@@ -411,7 +699,107 @@ impl App {
         f.render_widget(footer, chunks[4]);
     }
 
-    fn draw_results(&mut self, f: &mut Frame) {
+    fn draw_save_search(&self, f: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(4),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ])
+            .split(area);
+
+        let title = Paragraph::new("Save Search")
+            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center);
+        f.render_widget(title, chunks[0]);
+
+        let summary = Paragraph::new(Text::from(vec![
+            Line::from(vec![Span::raw("Query: "), Span::styled(self.query.as_str(), Style::default().fg(Color::Yellow))]),
+            Line::from(vec![Span::raw("Filters: "), Span::raw(saved::describe(&self.filters))]),
+        ]))
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(summary, chunks[1]);
+
+        let input = Paragraph::new(self.saved_name_input.as_str())
+            .block(Block::default().borders(Borders::ALL).title("Name (Enter: save, Esc: cancel)"))
+            .style(Style::default().fg(Color::Yellow));
+        f.render_widget(input, chunks[2]);
+    }
+
+    fn draw_saved_searches(&self, f: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+
+        let title = Paragraph::new("Saved Searches")
+            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center);
+        f.render_widget(title, chunks[0]);
+
+        let block = Block::default().borders(Borders::ALL).title("k/j to navigate, Enter to search, d to delete, Esc to go back");
+        if self.saved_searches.is_empty() {
+            let empty = Paragraph::new("No saved searches yet. Type a query and press Ctrl+S to save it.")
+                .block(block)
+                .style(Style::default().fg(Color::Gray));
+            f.render_widget(empty, chunks[1]);
+            return;
+        }
+
+        let items: Vec<ListItem> = self.saved_searches.iter()
+            .enumerate()
+            .map(|(i, search)| {
+                let style = if i == self.saved_index {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                ListItem::new(Text::from(vec![
+                    Line::from(Span::styled(search.name.as_str(), style)),
+                    Line::from(format!(
+                        "  \"{}\" ({}{})",
+                        search.query,
+                        saved::describe(&search.filters),
+                        search.saved_at.map(|t| format!(", saved {}", locale::current().date(t))).unwrap_or_default()
+                    )),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().bg(Color::DarkGray));
+        let mut state = ListState::default();
+        state.select(Some(self.saved_index));
+        f.render_stateful_widget(list, chunks[1], &mut state);
+    }
+
+    fn open_download_dir(&mut self) {
+        if let Err(e) = opener::open_dir(&self.download_path) {
+            self.error_message = format!("Could not open download folder: {}", e);
+            self.mode = AppMode::Error(self.error_message.clone());
+        }
+    }
+
+    /// Books shown per page: the preferred size, limited to what fits.
+    fn page_size(&self) -> usize {
+        self.results_per_page
+            .map_or(self.fit_results, |n| n.min(self.fit_results))
+            .max(1)
+    }
+
+    /// Scrolls so the selected book stays on the current page.
+    fn keep_selection_visible(&mut self) {
+        let page = self.page_size();
+        if self.selected_book_index < self.results_scroll {
+            self.results_scroll = self.selected_book_index;
+        } else if self.selected_book_index >= self.results_scroll + page {
+            self.results_scroll = self.selected_book_index + 1 - page;
+        }
+    }
+
+    fn draw_results(&mut self, f: &mut Frame, area: Rect) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -419,7 +807,7 @@ impl App {
                 Constraint::Min(10),
                 Constraint::Length(3),
             ])
-            .split(f.size());
+            .split(area);
 
         let header = Paragraph::new(format!("Search Results for: {}", self.query))
             .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -427,9 +815,14 @@ impl App {
         f.render_widget(header, chunks[0]);
 
         let results_area = chunks[1];
+        // Each book takes four lines inside the list borders
+        self.fit_results = (results_area.height.saturating_sub(2) / 4).max(1) as usize;
+        self.keep_selection_visible();
+        let page = self.page_size();
+
         let items: Vec<ListItem> = self.books.iter()
             .skip(self.results_scroll)
-            .take(10)
+            .take(page)
             .enumerate()
             .map(|(i, book)| {
                 let real_index = self.results_scroll + i;
@@ -439,24 +832,28 @@ impl App {
                     Style::default().fg(Color::White)
                 };
 
+                let mut title = vec![
+                    Span::styled(format!("{}. ", real_index + 1), style),
+                    Span::styled(&book.title, style.add_modifier(Modifier::BOLD)),
+                ];
+                if let Some(issue) = book.issue() {
+                    title.push(Span::styled(format!("  [{}]", issue), Style::default().fg(Color::Cyan)));
+                }
                 let lines = vec![
-                    Line::from(vec![
-                        Span::styled(format!("{}. ", real_index + 1), style),
-                        Span::styled(&book.title, style.add_modifier(Modifier::BOLD)),
-                    ]),
+                    Line::from(title),
                     Line::from(vec![
                         Span::raw("  Author: "),
                         Span::raw(book.author.as_deref().unwrap_or("Unknown")),
                     ]),
                     Line::from(vec![
                         Span::raw("  Year: "),
-                        Span::raw(book.year.as_deref().unwrap_or("Unknown")),
+                        Span::raw(book.display_year()),
                         Span::raw(" | Language: "),
-                        Span::raw(book.language.as_deref().unwrap_or("Unknown")),
+                        Span::raw(book.display_language()),
                         Span::raw(" | Format: "),
-                        Span::raw(book.format.as_deref().unwrap_or("Unknown")),
+                        Span::raw(book.display_format()),
                         Span::raw(" | Size: "),
-                        Span::raw(book.size.as_deref().unwrap_or("Unknown")),
+                        Span::raw(book.display_size()),
                     ]),
                     Line::from(""),
                 ];
@@ -473,10 +870,14 @@ impl App {
         list_state.select(Some(self.selected_book_index.saturating_sub(self.results_scroll)));
         f.render_stateful_widget(list, results_area, &mut list_state);
 
+        let total = self.total_results
+            .map(|total| format!(" ({} results on the site)", total))
+            .unwrap_or_default();
         let footer_text = format!(
-            "Showing {} of {} books | Press Enter to see download options",
-            self.books.len().min(self.results_scroll + 10) - self.results_scroll,
-            self.books.len()
+            "Showing {} of {} books{} | Press Enter to see download options",
+            self.books.len().min(self.results_scroll + page) - self.results_scroll,
+            self.books.len(),
+            total
         );
         let footer = Paragraph::new(footer_text)
             .style(Style::default().fg(Color::Gray))
@@ -484,24 +885,46 @@ impl App {
         f.render_widget(footer, chunks[2]);
     }
 
-    fn draw_download_selection(&self, f: &mut Frame) {
+    fn draw_download_selection(&self, f: &mut Frame, area: Rect) {
+        let book = &self.books[self.selected_book_index];
+        let mut book_info = vec![
+            Line::from(vec![Span::raw("Title: "), Span::styled(&book.title, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))]),
+            Line::from(vec![Span::raw("Author: "), Span::raw(book.author.as_deref().unwrap_or("Unknown"))]),
+            Line::from(vec![Span::raw("Year: "), Span::raw(book.display_year())]),
+            Line::from(vec![Span::raw("Language: "), Span::raw(book.display_language())]),
+            Line::from(vec![Span::raw("Format: "), Span::raw(book.display_format())]),
+            Line::from(vec![Span::raw("Size: "), Span::raw(book.display_size())]),
+        ];
+        if let Some(issue) = book.issue() {
+            book_info.insert(1, Line::from(vec![Span::raw("Issue: "), Span::raw(issue.to_string())]));
+        }
+        if !book.metadata.identifiers.is_empty() {
+            book_info.push(Line::from(vec![Span::raw("Identifiers: "), Span::raw(book.metadata.identifiers.to_string())]));
+        }
+        let details = &book.metadata.details;
+        if let Some(summary) = details.summary() {
+            book_info.push(Line::from(vec![Span::raw("Published: "), Span::raw(summary)]));
+        }
+        if let Some(ref cover) = details.cover_url {
+            book_info.push(Line::from(vec![Span::raw("Cover: "), Span::styled(cover.as_str(), Style::default().fg(Color::Gray))]));
+        }
+        if let Some(ref description) = details.description {
+            // One line, clipped by the panel
+            let line = description.split_whitespace().collect::<Vec<_>>().join(" ");
+            book_info.push(Line::from(vec![Span::raw("Description: "), Span::raw(line)]));
+        }
+
+        // Torrents get a panel of their own below the mirrors, up to four rows
+        let torrents = self.download_links.iter().filter(|link| !link.is_http()).count();
+        let torrent_rows = if torrents == 0 { 0 } else { torrents.min(4) as u16 * 2 + 2 };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(8),
+                Constraint::Length(book_info.len() as u16 + 2),
                 Constraint::Min(10),
+                Constraint::Length(torrent_rows),
             ])
-            .split(f.size());
-
-        let book = &self.books[self.selected_book_index];
-        let book_info = vec![
-            Line::from(vec![Span::raw("Title: "), Span::styled(&book.title, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))]),
-            Line::from(vec![Span::raw("Author: "), Span::raw(book.author.as_deref().unwrap_or("Unknown"))]),
-            Line::from(vec![Span::raw("Year: "), Span::raw(book.year.as_deref().unwrap_or("Unknown"))]),
-            Line::from(vec![Span::raw("Language: "), Span::raw(book.language.as_deref().unwrap_or("Unknown"))]),
-            Line::from(vec![Span::raw("Format: "), Span::raw(book.format.as_deref().unwrap_or("Unknown"))]),
-            Line::from(vec![Span::raw("Size: "), Span::raw(book.size.as_deref().unwrap_or("Unknown"))]),
-        ];
+            .split(area);
 
         let info_panel = Paragraph::new(Text::from(book_info))
             .block(Block::default().borders(Borders::ALL).title("Book Info"))
@@ -510,6 +933,7 @@ impl App {
 
         let items: Vec<ListItem> = self.download_links.iter()
             .enumerate()
+            .filter(|(_, link)| link.is_http())
             .map(|(i, link)| {
                 let style = if i == self.download_link_index {
                     Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
@@ -517,17 +941,19 @@ impl App {
                     Style::default().fg(Color::White)
                 };
 
+                let mut details = vec![
+                    Span::raw("  Source: "),
+                    Span::raw(&link.source),
+                    Span::raw(" | URL: "),
+                    Span::raw(&link.url[..50.min(link.url.len())]),
+                ];
+                details.extend(self.link_health_spans(&link.url, book.size_bytes));
                 let lines = vec![
                     Line::from(vec![
                         Span::styled(format!("{}. ", i + 1), style),
                         Span::styled(&link.text, style),
                     ]),
-                    Line::from(vec![
-                        Span::raw("  Source: "),
-                        Span::raw(&link.source),
-                        Span::raw(" | URL: "),
-                        Span::raw(&link.url[..50.min(link.url.len())]),
-                    ]),
+                    Line::from(details),
                     Line::from(""),
                 ];
 
@@ -535,16 +961,69 @@ impl App {
             })
             .collect();
 
+        let mut title = if self.editions.is_empty() {
+            "Download Links (k/j to navigate, Enter to download, Esc to go back)".to_string()
+        } else {
+            format!("Download Links (k/j to navigate, Enter to download, e for {} other editions, Esc to go back)", self.editions.len())
+        };
+        if self.checking_links {
+            title.push_str(" - checking mirrors…");
+        }
         let list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("Download Links (k/j to navigate, Enter to download, Esc to go back)"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .highlight_style(Style::default().bg(Color::DarkGray));
         f.render_widget(list, chunks[1]);
+
+        if torrents > 0 {
+            let items: Vec<ListItem> = self.download_links.iter()
+                .enumerate()
+                .filter(|(_, link)| !link.is_http())
+                .map(|(i, link)| {
+                    let style = if i == self.download_link_index {
+                        Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    ListItem::new(Text::from(vec![
+                        Line::from(vec![Span::styled(&link.text, style), Span::raw(format!(" ({})", link.source))]),
+                        Line::from(Span::styled(format!("  {}", link.url), Style::default().fg(Color::Gray))),
+                    ]))
+                })
+                .collect();
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title("Torrents (Enter to open in your torrent client)"));
+            f.render_widget(list, chunks[2]);
+        }
     }
 
-    fn draw_error(&self, f: &mut Frame, error: &str) {
+    /// The mirror's reported size for a link, once it has been checked.
+    fn link_health_spans(&self, url: &str, listed: Option<u64>) -> Vec<Span<'static>> {
+        let health = match self.link_health.get(url) {
+            None => return Vec::new(),
+            Some(None) => return vec![Span::styled(" | Size: checking…", Style::default().fg(Color::DarkGray))],
+            Some(Some(health)) => health,
+        };
+        if health.is_dead() {
+            return vec![Span::styled(format!(" | Dead: {}", health), Style::default().fg(Color::Red))];
+        }
+        let size = health.size;
+        let label = match (size.bytes, size.is_html) {
+            (_, true) => "web page".to_string(),
+            (Some(bytes), false) => locale::current().size(bytes),
+            (None, false) => "unknown".to_string(),
+        };
+        let latency = Span::styled(format!(" | {} ms", health.latency.as_millis()), Style::default().fg(Color::Gray));
+        if size.looks_wrong(listed) {
+            vec![Span::styled(format!(" | Size: {} ⚠ likely an error page", label), Style::default().fg(Color::Red)), latency]
+        } else {
+            vec![Span::raw(format!(" | Size: {}", label)), latency]
+        }
+    }
+
+    fn draw_error(&self, f: &mut Frame, area: Rect, heading: &str, color: Color, error: &str) {
         let block = Block::default()
             .borders(Borders::ALL)
-            .style(Style::default().fg(Color::Red));
+            .style(Style::default().fg(color));
 
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -553,11 +1032,11 @@ impl App {
                 Constraint::Percentage(20),
                 Constraint::Percentage(40),
             ])
-            .split(f.size());
+            .split(area);
 
         let error_text = vec![
             Line::from(""),
-            Line::from(Span::styled("ERROR", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))),
+            Line::from(Span::styled(heading, Style::default().fg(color).add_modifier(Modifier::BOLD))),
             Line::from(""),
             Line::from(error),
             Line::from(""),
@@ -571,7 +1050,7 @@ impl App {
         f.render_widget(error_paragraph, chunks[1]);
     }
 
-    fn draw_downloading(&self, f: &mut Frame) {
+    fn draw_downloading(&self, f: &mut Frame, area: Rect) {
         let block = Block::default()
             .borders(Borders::ALL)
             .style(Style::default().fg(Color::Yellow))
@@ -584,7 +1063,7 @@ impl App {
                 Constraint::Percentage(20),
                 Constraint::Percentage(40),
             ])
-            .split(f.size());
+            .split(area);
 
         let status = vec![
             Line::from(""),
@@ -599,16 +1078,45 @@ impl App {
             .block(block)
             .alignment(Alignment::Center);
         f.render_widget(status_paragraph, chunks[1]);
+
+        self.draw_transfers(f, chunks[2]);
+    }
+
+    /// A speed sparkline with size, smoothed speed and ETA per download.
+    fn draw_transfers(&self, f: &mut Frame, area: Rect) {
+        let transfers = self.transfers.transfers();
+        if transfers.is_empty() {
+            return;
+        }
+        // One summary line and a two-row sparkline each
+        let fit = (area.height / 3) as usize;
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(3); transfers.len().min(fit)])
+            .split(area);
+
+        for (transfer, row) in transfers.iter().zip(rows.iter()) {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Length(2)])
+                .split(*row);
+            f.render_widget(Paragraph::new(transfer_summary(transfer)), parts[0]);
+
+            let width = parts[1].width as usize;
+            let history = &transfer.history[transfer.history.len().saturating_sub(width)..];
+            let color = if transfer.stalled { Color::Red } else { Color::Green };
+            f.render_widget(Sparkline::default().data(history).style(Style::default().fg(color)), parts[1]);
+        }
     }
 
-    fn draw_help(&self, f: &mut Frame) {
+    fn draw_help(&self, f: &mut Frame, area: Rect) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Length(3),
                 Constraint::Min(0),
             ])
-            .split(f.size());
+            .split(area);
 
         let title = Paragraph::new("Help - Anna's Archive Downloader")
             .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -620,7 +1128,7 @@ impl App {
             Line::from(vec![Span::raw("• Search Mode: "), Span::styled("Type to search", Style::default().fg(Color::Green))]),
             Line::from(vec![Span::raw("• Navigate Results: "), Span::styled("↑/↓ or k/j", Style::default().fg(Color::Green))]),
             Line::from(vec![Span::raw("• Select Book: "), Span::styled("Enter", Style::default().fg(Color::Green))]),
-            Line::from(vec![Span::raw("• Select Download: "), Span::styled("Enter", Style::default().fg(Color::Green))]),
+            Line::from(vec![Span::raw("• Select Download or Open Torrent: "), Span::styled("Enter", Style::default().fg(Color::Green))]),
             Line::from(vec![Span::raw("• Go Back: "), Span::styled("Esc", Style::default().fg(Color::Green))]),
             Line::from(vec![Span::raw("• Help: "), Span::styled("F1", Style::default().fg(Color::Green))]),
             Line::from(vec![Span::raw("• Quit: "), Span::styled("Ctrl+C", Style::default().fg(Color::Red))]),
@@ -628,7 +1136,17 @@ impl App {
             Line::from(vec![Span::raw("Key Bindings:")]),
             Line::from(vec![Span::raw("  k/↑ - Move up")]),
             Line::from(vec![Span::raw("  j/↓ - Move down")]),
+            Line::from(vec![Span::raw("  PgUp/PgDn - Move by a page of results")]),
+            Line::from(vec![Span::raw("  +/- - More/fewer results per page, 0 to fit the screen")]),
             Line::from(vec![Span::raw("  Enter - Confirm/Select")]),
+            Line::from(vec![Span::raw(format!(
+                "  Tab - Switch search index ({})",
+                scraper::SearchIndex::ALL.map(|index| index.as_str()).join(", ")
+            ))]),
+            Line::from(vec![Span::raw("  Ctrl+S - Save the query and filters under a name")]),
+            Line::from(vec![Span::raw("  Ctrl+R - Run or delete a saved search")]),
+            Line::from(vec![Span::raw("  Ctrl+O - Open the download folder")]),
+            Line::from(vec![Span::raw("  e - List the book's other editions, from its download links")]),
             Line::from(vec![Span::raw("  Esc - Go back/Cancel")]),
             Line::from(vec![Span::raw("  F1 - Toggle help")]),
             Line::from(vec![Span::raw("  Ctrl+C - Force quit")]),
@@ -650,9 +1168,15 @@ impl App {
 
     async fn perform_search(&mut self) -> Result<()> {
         self.mode = AppMode::Downloading;
-        self.downloading_message = "Searching...".to_string();
         
-        let _ = self.command_tx.send(AppCommand::Search(self.query.clone(), self.filters.clone(), 20));
+        if let Some(md5) = scraper::md5_from_reference(&self.query, &self.config) {
+            self.downloading_message = "Fetching download links...".to_string();
+            let _ = self.command_tx.send(AppCommand::OpenBook(md5));
+            return Ok(());
+        }
+        
+        self.downloading_message = "Searching...".to_string();
+        let _ = self.command_tx.send(AppCommand::Search(self.query.clone(), self.filters.clone(), self.num_results));
         
         Ok(())
     }
@@ -661,66 +1185,401 @@ impl App {
         self.mode = AppMode::Downloading;
         self.downloading_message = "Fetching download links...".to_string();
         
-        let book_url = self.books[self.selected_book_index].url.clone();
-        let tx = self.command_tx.clone();
+        let book = self.books[self.selected_book_index].clone();
+        let _ = self.command_tx.send(AppCommand::FetchDownloadLinks(Box::new(book)));
         
+        Ok(())
+    }
+
+    /// Fetches the membership status in the background when logged in.
+    pub fn refresh_account_status(&self) {
+        let Some(credentials) = self.credentials.clone() else {
+            return;
+        };
+        let config = self.config.clone();
+        let tx = self.command_tx.clone();
         tokio::spawn(async move {
-            let scraper = match AnnaScraper::new() {
-                Ok(s) => s,
-                Err(e) => {
-                    let _ = tx.send(AppCommand::ShowError(format!("Failed to create scraper: {}", e)));
-                    return;
+            let Ok(scraper) = AnnaScraper::new(&config) else {
+                return;
+            };
+            // The status bar simply stays empty if the account page fails
+            if let Ok(status) = scraper.with_credentials(Some(credentials)).account_status().await {
+                let _ = tx.send(AppCommand::AccountStatus(status));
+            }
+        });
+    }
+
+    /// Looks `book` up on Open Library in the background; the answer comes
+    /// back as `AppCommand::Enriched`.
+    pub fn enrich_in_background(&mut self, book: &Book) {
+        self.enrich_status = Some("Looking up Open Library…".to_string());
+        let config = self.config.clone();
+        let tx = self.command_tx.clone();
+        let mut book = book.clone();
+        tokio::spawn(async move {
+            let result = openlibrary::enrich_book(&config, &mut book).await.map_err(|e| format!("{:#}", e));
+            let _ = tx.send(AppCommand::Enriched(Box::new(book), result));
+        });
+    }
+
+    /// Merges what an Open Library lookup changed into the listed book and
+    /// says how it went in the status bar.
+    pub fn apply_enrichment(&mut self, enriched: Book, result: Result<Vec<&'static str>, String>) {
+        self.enrich_status = match result {
+            Ok(changed) if changed.is_empty() => None,
+            Ok(changed) => {
+                if let Some(shown) = self.books.iter_mut().find(|b| b.id() == enriched.id()) {
+                    openlibrary::copy_changed(shown, &enriched, &changed);
+                }
+                Some(format!("Open Library filled in {}", changed.join(", ")))
+            }
+            Err(e) => Some(format!("Open Library lookup failed: {}", e)),
+        };
+    }
+
+    /// Searches in the background, stopping any search still running. Each
+    /// parsed page comes back as `AppCommand::SearchPage`, then the final
+    /// list as `SearchDone`, or the failure as `SearchFailed` or
+    /// `LayoutChanged`, all tagged with the search's id.
+    pub fn search_in_background(&mut self, query: String, filters: SearchFilters, num_results: usize) {
+        self.stop_search();
+        self.books.clear();
+        self.total_results = None;
+        self.selected_book_index = 0;
+        self.results_scroll = 0;
+        self.book_pages.clear();
+        let source = match source::provider(&self.config, self.credentials.clone()) {
+            Ok(source) => source,
+            Err(e) => {
+                let _ = self.command_tx.send(AppCommand::ShowError(format!("Search error: {}", e)));
+                return;
+            }
+        };
+        let id = self.next_search_id;
+        self.next_search_id += 1;
+        let tx = self.command_tx.clone();
+        self.search = Some((id, tokio::spawn(async move {
+            let (pages_tx, mut pages_rx) = mpsc::unbounded_channel();
+            let search = source.search_paged(&query, &filters, num_results, pages_tx);
+            // Pages are forwarded until the search drops its sink, so they
+            // all arrive ahead of the final list
+            let forward = async {
+                while let Some(page) = pages_rx.recv().await {
+                    let _ = tx.send(AppCommand::SearchPage(id, page));
                 }
             };
-            
-            match scraper.get_book_details(&book_url).await {
-                Ok(links) => {
-                    if links.is_empty() {
-                        let _ = tx.send(AppCommand::ShowError("No download links found".to_string()));
-                    } else {
-                        // Channel communication would go here in full implementation
-                    }
+            let (result, ()) = futures::join!(search, forward);
+            let command = match result {
+                Ok(results) => AppCommand::SearchDone(id, results),
+                Err(e) if matches!(e.downcast_ref(), Some(ScrapeError::LayoutChanged { .. })) => {
+                    AppCommand::LayoutChanged(id, e.to_string())
                 }
-                Err(e) => {
-                    let _ = tx.send(AppCommand::ShowError(format!("Error fetching links: {}", e)));
+                Err(e) if matches!(
+                    e.downcast_ref(),
+                    Some(ScrapeError::Blocked { .. } | ScrapeError::RateLimited { .. } | ScrapeError::Challenge { .. })
+                ) => AppCommand::SearchFailed(id, e.to_string()),
+                Err(e) => AppCommand::SearchFailed(id, format!("Search error: {}", e)),
+            };
+            let _ = tx.send(command);
+        })));
+    }
+
+    /// Looks up a pasted MD5's book in the background, in place of a
+    /// search. It comes back as `AppCommand::Opened`.
+    pub fn open_in_background(&mut self, md5: String) {
+        self.stop_search();
+        let scraper = match AnnaScraper::new(&self.config) {
+            Ok(scraper) => scraper.with_credentials(self.credentials.clone()),
+            Err(e) => {
+                let _ = self.command_tx.send(AppCommand::ShowError(format!("Failed to create scraper: {}", e)));
+                return;
+            }
+        };
+        let id = self.next_search_id;
+        self.next_search_id += 1;
+        let tx = self.command_tx.clone();
+        self.search = Some((id, tokio::spawn(async move {
+            let book = scraper.open_md5(&md5).await;
+            let _ = tx.send(AppCommand::Opened(id, Box::new(book)));
+        })));
+    }
+
+    /// Lists the opened book on its own and fetches its download links.
+    pub fn show_opened(&mut self, id: usize, book: Book) {
+        if !self.end_search(id) {
+            return;
+        }
+        self.books = vec![book.clone()];
+        self.total_results = None;
+        self.selected_book_index = 0;
+        self.results_scroll = 0;
+        let _ = self.command_tx.send(AppCommand::FetchDownloadLinks(Box::new(book)));
+    }
+
+    /// Stops the running search; whatever it already sent is ignored.
+    pub fn stop_search(&mut self) {
+        if let Some((_, search)) = self.search.take() {
+            search.abort();
+        }
+    }
+
+    fn is_running_search(&self, id: usize) -> bool {
+        self.search.as_ref().is_some_and(|(running, _)| *running == id)
+    }
+
+    /// Whether `id` is the running search, which it then no longer is.
+    /// Answers from a stopped or replaced search are dropped with this.
+    pub fn end_search(&mut self, id: usize) -> bool {
+        let running = self.is_running_search(id);
+        if running {
+            self.search = None;
+        }
+        running
+    }
+
+    /// Adds a page of the running search to the list. The first one shows
+    /// the results.
+    pub fn append_search_page(&mut self, id: usize, page: SearchResults) {
+        if !self.is_running_search(id) {
+            return;
+        }
+        let first = self.books.is_empty();
+        self.books.extend(page.books);
+        self.total_results = page.total.or(self.total_results);
+        if first {
+            self.mode = AppMode::Results;
+            self.selected_book_index = 0;
+            self.prefetch_top_results();
+        }
+    }
+
+    /// Replaces the pages with the search's final list, keeping the
+    /// selected book selected.
+    pub fn finish_search(&mut self, id: usize, results: SearchResults) {
+        if !self.end_search(id) {
+            return;
+        }
+        let selected = self.books.get(self.selected_book_index).map(|b| b.id().to_string());
+        let first = self.books.is_empty();
+        self.books = results.books;
+        self.total_results = results.total;
+        if first {
+            self.mode = AppMode::Results;
+        }
+        self.selected_book_index = selected
+            .and_then(|id| self.books.iter().position(|b| b.id() == id))
+            .unwrap_or(0);
+        self.prefetch_top_results();
+    }
+
+    /// Fetches the pages of the top results in the background, so opening
+    /// one of them doesn't wait on the network.
+    pub fn prefetch_top_results(&mut self) {
+        let urls = self.books.iter()
+            .take(self.config.prefetch_results)
+            .map(|book| book.url.clone())
+            .collect();
+        self.prefetch_pages(urls);
+    }
+
+    /// Prefetches the selected result's page once the selection has rested
+    /// on it for `HOVER_PREFETCH_DELAY`. Called on every tick of the UI loop.
+    pub fn prefetch_hovered(&mut self) {
+        if !self.config.hover_prefetch || !matches!(self.mode, AppMode::Results) {
+            return;
+        }
+        let Some(url) = self.books.get(self.selected_book_index).map(|book| book.url.clone()) else {
+            return;
+        };
+        match self.hover {
+            Some((ref hovered, since)) if *hovered == url => {
+                if since.is_some_and(|since| since.elapsed() >= HOVER_PREFETCH_DELAY) {
+                    self.hover = Some((url.clone(), None));
+                    self.prefetch_pages(vec![url]);
                 }
             }
+            _ => self.hover = Some((url, Some(Instant::now()))),
+        }
+    }
+
+    fn prefetch_pages(&mut self, urls: Vec<String>) {
+        let urls: Vec<String> = urls.into_iter()
+            .filter(|url| !url.is_empty() && !self.book_pages.contains_key(url))
+            .collect();
+        if urls.is_empty() {
+            return;
+        }
+        for url in &urls {
+            self.book_pages.insert(url.clone(), None);
+        }
+        let (config, credentials) = (self.config.clone(), self.credentials.clone());
+        let tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            let (config, credentials) = (&config, &credentials);
+            futures::stream::iter(urls.into_iter().enumerate())
+                .map(|(i, url)| async move {
+                    tokio::time::sleep(PREFETCH_SPACING * i as u32).await;
+                    let page = match source::provider_for(config, credentials.clone(), &url) {
+                        Ok(source) => source.details(&url).await.ok(),
+                        Err(_) => None,
+                    };
+                    (url, page)
+                })
+                .buffer_unordered(PREFETCH_CONCURRENCY)
+                .for_each(|(url, page)| {
+                    let _ = tx.send(AppCommand::PrefetchedPage(url, page.map(Box::new)));
+                    async {}
+                })
+                .await;
         });
-        
-        Ok(())
+    }
+
+    /// Asks the mirrors behind all the links for the real file size at
+    /// once, once per link; each size shows as its mirror answers.
+    pub fn probe_links(&mut self) {
+        let urls: Vec<String> = self.download_links.iter()
+            .filter(|link| link.is_http() && !self.link_health.contains_key(&link.url))
+            .map(|link| link.url.clone())
+            .collect();
+        if urls.is_empty() {
+            return;
+        }
+        for url in &urls {
+            self.link_health.insert(url.clone(), None);
+        }
+        let config = self.config.clone();
+        let download_path = self.download_path.clone();
+        let tx = self.command_tx.clone();
+        self.link_probe = Some(tokio::spawn(async move {
+            let Ok(downloader) = Downloader::new(download_path, &config) else {
+                for url in urls {
+                    let _ = tx.send(AppCommand::LinkHealth(url, LinkHealth::default()));
+                }
+                return;
+            };
+            downloader.check_links(&urls)
+                .for_each(|(i, health)| {
+                    let _ = tx.send(AppCommand::LinkHealth(urls[i].clone(), health));
+                    async {}
+                })
+                .await;
+        }));
+    }
+
+    /// With `link_check` on, has the probe's answers list the links again
+    /// once every mirror has answered: dead mirrors last or left out.
+    pub fn preflight_links(&mut self) {
+        self.checking_links = self.config.link_check != LinkCheck::Off
+            && self.download_links.iter().any(DownloadLink::is_http);
+    }
+
+    /// Stops checking the links being left and forgets what was learned.
+    pub fn stop_link_checks(&mut self) {
+        if let Some(probe) = self.link_probe.take() {
+            probe.abort();
+        }
+        self.link_health.clear();
+        self.checking_links = false;
+    }
+
+    /// Records a mirror's answer. Once the last one a preflight waits for
+    /// is in, the links are arranged and ranked, keeping the selection.
+    pub fn record_link_health(&mut self, url: String, health: LinkHealth) {
+        // Answers for links already left are dropped
+        if !self.link_health.contains_key(&url) {
+            return;
+        }
+        self.link_health.insert(url, Some(health));
+        if !self.checking_links || self.link_health.values().any(Option::is_none) {
+            return;
+        }
+        self.checking_links = false;
+        let selected = self.download_links.get(self.download_link_index).map(|link| link.url.clone());
+        let (links, torrents): (Vec<_>, Vec<_>) = std::mem::take(&mut self.download_links).into_iter().partition(DownloadLink::is_http);
+        let checked = links.into_iter()
+            .map(|link| {
+                let health = self.link_health.get(&link.url).copied().flatten().unwrap_or_default();
+                (link, health)
+            })
+            .collect();
+        self.download_links = downloader::arrange_links(checked, self.config.link_check).into_iter()
+            .map(|(link, _)| link)
+            .chain(torrents)
+            .collect();
+        self.rank_links();
+        self.download_link_index = selected
+            .and_then(|url| self.download_links.iter().position(|link| link.url == url))
+            .unwrap_or(0);
+    }
+
+    /// Lists the links by reliability score, best first, using whatever
+    /// the preflight learned about them. Torrents stay at the end.
+    pub fn rank_links(&mut self) {
+        let (links, torrents): (Vec<_>, Vec<_>) = std::mem::take(&mut self.download_links).into_iter().partition(DownloadLink::is_http);
+        let health: Vec<Option<LinkHealth>> = links.iter()
+            .map(|link| self.link_health.get(&link.url).cloned().flatten())
+            .collect();
+        let order = Scorer::load(&self.config, &self.saved_db).rank(&links, &health);
+        self.download_links = order.into_iter().map(|i| links[i].clone()).chain(torrents).collect();
     }
 
     async fn perform_download(&mut self) -> Result<()> {
         self.mode = AppMode::Downloading;
-        let link = &self.download_links[self.download_link_index];
-        let filename = format!(
-            "{} - {}.{}",
-            self.books[self.selected_book_index].title
-                .chars()
-                .take(50)
-                .collect::<String>(),
-            self.books[self.selected_book_index].author.as_deref().unwrap_or("Unknown"),
-            self.books[self.selected_book_index].format.as_deref().unwrap_or("unknown")
-        );
+        let book = &self.books[self.selected_book_index];
+        let filename = book.file_name();
         
         self.downloading_message = format!("Downloading: {}", filename);
         
-        let url = link.url.clone();
+        // Try the selected link first, then fall back to the others in order
+        let urls: Vec<String> = self.download_links.iter()
+            .cycle()
+            .skip(self.download_link_index)
+            .take(self.download_links.len())
+            .filter(|link| link.is_http())
+            .map(|link| link.url.clone())
+            .collect();
+        let config = self.config.clone();
+        let book = self.books[self.selected_book_index].clone();
         let download_path = self.download_path.clone();
+        let saved_db = self.saved_db.clone();
         let tx = self.command_tx.clone();
+        let id = self.next_download_id;
+        self.next_download_id += 1;
         
         tokio::spawn(async move {
-            let downloader = match Downloader::new(download_path) {
-                Ok(d) => d,
+            let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+            let forward_tx = tx.clone();
+            tokio::spawn(async move {
+                while let Some(progress) = progress_rx.recv().await {
+                    let _ = forward_tx.send(AppCommand::DownloadProgress(progress));
+                }
+            });
+
+            let downloader = match Downloader::new(download_path, &config) {
+                Ok(d) => d.with_progress(id, progress_tx).with_history(saved_db),
                 Err(e) => {
                     let _ = tx.send(AppCommand::ShowError(format!("Failed to create downloader: {}", e)));
                     return;
                 }
             };
             
-            match downloader.download(&url, Some(&filename)).await {
+            let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+            match downloader.download_verified(&urls, Some(&filename), book.md5(), config.download_attempts).await {
                 Ok(path) => {
+                    let delivery = delivery::deliver(&config, &book, &path).await;
                     let _ = tx.send(AppCommand::CompleteDownload(path));
+                    if let Some(Err(e)) = delivery.opf {
+                        let _ = tx.send(AppCommand::ShowError(format!("Downloaded, but {:#}", e)));
+                    }
+                    if let Some(Err(e)) = delivery.device {
+                        let _ = tx.send(AppCommand::ShowError(format!("Downloaded, but sending to the device failed: {:#}", e)));
+                    }
+                    if let Some(Err(e)) = delivery.kindle {
+                        let _ = tx.send(AppCommand::ShowError(format!("Downloaded, but Send to Kindle failed: {:#}", e)));
+                    }
+                    if let Some(Err(e)) = delivery.upload {
+                        let _ = tx.send(AppCommand::ShowError(format!("Downloaded, but the upload failed: {:#}", e)));
+                    }
                 }
                 Err(e) => {
                     let _ = tx.send(AppCommand::ShowError(format!("Download failed: {}", e)));
@@ -738,19 +1597,32 @@ pub enum ControlFlow {
     Exit,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum DownloadState {
-    Idle,
-    FetchingLinks,
-    Selecting,
-    Downloading,
-    Complete,
-    Error,
+/// e.g. `Download 1: 3.0MB / 10.0MB | 850.0KB/s | ETA 8 seconds`.
+fn transfer_summary(transfer: &TransferView) -> Line<'static> {
+    let locale = locale::current();
+    let size = match transfer.total {
+        Some(total) => format!("{} / {}", locale.size(transfer.downloaded), locale.size(total)),
+        None => locale.size(transfer.downloaded),
+    };
+    if let Some(left) = transfer.waiting {
+        return Line::from(format!("Download {}: waiting in line, {}s left", transfer.id + 1, left));
+    }
+    let mut spans = vec![Span::raw(format!("Download {}: {} | ", transfer.id + 1, size))];
+    if transfer.stalled {
+        spans.push(Span::styled("stalled", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    } else {
+        let eta = transfer.eta.map(|d| HumanDuration(d).to_string()).unwrap_or_else(|| "--".to_string());
+        spans.push(Span::raw(format!("{}/s | ETA {}", locale.size(transfer.speed as u64), eta)));
+    }
+    Line::from(spans)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::downloader::RemoteSize;
+    use crate::scraper::LinkKind;
+    use crate::test_server;
     use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
     use std::path::PathBuf;
 
@@ -813,6 +1685,20 @@ mod tests {
         assert_eq!(result, ControlFlow::Exit);
     }
 
+    #[tokio::test]
+    async fn test_handle_search_input_tab_switches_index() {
+        let mut app = create_test_app();
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+
+        app.handle_search_input(tab).await.unwrap();
+        assert_eq!(app.filters.index, scraper::SearchIndex::Journals);
+        for _ in 0..3 {
+            app.handle_search_input(tab).await.unwrap();
+        }
+        assert_eq!(app.filters.index, scraper::SearchIndex::Books);
+        assert!(app.query.is_empty());
+    }
+
     #[tokio::test]
     async fn test_handle_search_input_f1_opens_help() {
         let mut app = create_test_app();
@@ -835,8 +1721,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url1".to_string(),
+                metadata: Default::default(),
             },
             Book {
                 title: "Book 2".to_string(),
@@ -844,8 +1732,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url2".to_string(),
+                metadata: Default::default(),
             },
         ];
         app.selected_book_index = 0;
@@ -868,8 +1758,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url1".to_string(),
+                metadata: Default::default(),
             },
             Book {
                 title: "Book 2".to_string(),
@@ -877,8 +1769,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url2".to_string(),
+                metadata: Default::default(),
             },
         ];
         app.selected_book_index = 1;
@@ -901,8 +1795,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url1".to_string(),
+                metadata: Default::default(),
             },
             Book {
                 title: "Book 2".to_string(),
@@ -910,8 +1806,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url2".to_string(),
+                metadata: Default::default(),
             },
         ];
         app.selected_book_index = 0;
@@ -927,6 +1825,191 @@ mod tests {
         assert_eq!(app.selected_book_index, 0);
     }
 
+    #[tokio::test]
+    async fn test_pasted_link_skips_search() {
+        let mut app = create_test_app();
+        app.query = "https://annas-archive.org/md5/d41d8cd98f00b204e9800998ecf8427e".to_string();
+
+        app.handle_search_input(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).await.unwrap();
+
+        match app.command_rx.try_recv().unwrap() {
+            AppCommand::OpenBook(md5) => assert_eq!(md5, "d41d8cd98f00b204e9800998ecf8427e"),
+            other => panic!("expected OpenBook, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_uses_configured_result_count() {
+        let config = Config { default_num_results: 7, ..Config::default() };
+        let mut app = App::new(config, PathBuf::from("/tmp/test"));
+        app.query = "dune".to_string();
+        app.handle_search_input(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).await.unwrap();
+        assert!(matches!(app.command_rx.try_recv(), Ok(AppCommand::Search(_, _, 7))));
+
+        // -n overrides the config
+        app.num_results = 3;
+        app.handle_search_input(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).await.unwrap();
+        assert!(matches!(app.command_rx.try_recv(), Ok(AppCommand::Search(_, _, 3))));
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_top_results() {
+        let body = "<html><body><div>ISBN-13: 9780441013593</div></body></html>";
+        let (base, _server) = test_server::serve_with(move |_| test_server::response("200 OK", "text/html", body)).await;
+
+        let mut app = create_test_app();
+        app.config.prefetch_results = 2;
+        app.books = (1..=3)
+            .map(|i| Book {
+                title: format!("Book {}", i),
+                author: None,
+                year: None,
+                language: None,
+                format: None,
+                size_bytes: None,
+                md5: None,
+                url: format!("{}/md5/{}", base, i),
+                metadata: Default::default(),
+            })
+            .collect();
+
+        app.prefetch_top_results();
+        assert_eq!(app.book_pages.len(), 2);
+        assert!(app.book_pages.values().all(Option::is_none));
+        // Already fetching, so nothing new starts
+        app.prefetch_top_results();
+
+        let mut fetched = Vec::new();
+        for _ in 0..2 {
+            match app.command_rx.recv().await {
+                Some(AppCommand::PrefetchedPage(url, Some(page))) => {
+                    assert_eq!(page.identifiers.isbn13, vec!["9780441013593"]);
+                    fetched.push(url);
+                }
+                _ => panic!("expected a prefetched page"),
+            }
+        }
+        fetched.sort();
+        assert_eq!(fetched, vec![app.books[0].url.clone(), app.books[1].url.clone()]);
+        assert!(app.command_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_hovered_waits_for_selection_to_rest() {
+        let mut app = create_test_app();
+        app.books = (1..=2)
+            .map(|i| Book {
+                title: format!("Book {}", i),
+                author: None,
+                year: None,
+                language: None,
+                format: None,
+                size_bytes: None,
+                md5: None,
+                url: format!("http://127.0.0.1:9/md5/{}", i),
+                metadata: Default::default(),
+            })
+            .collect();
+        let rested = || Some(Instant::now() - HOVER_PREFETCH_DELAY * 2);
+
+        // Only the results list prefetches
+        app.prefetch_hovered();
+        assert!(app.hover.is_none());
+
+        app.mode = AppMode::Results;
+        app.prefetch_hovered();
+        assert!(app.book_pages.is_empty());
+
+        app.hover = Some((app.books[0].url.clone(), rested()));
+        app.prefetch_hovered();
+        assert!(app.book_pages.contains_key(&app.books[0].url));
+        assert_eq!(app.hover, Some((app.books[0].url.clone(), None)));
+
+        // Moving on starts a new wait
+        app.handle_results_navigation(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE)).await.unwrap();
+        app.prefetch_hovered();
+        assert!(matches!(app.hover, Some((ref url, Some(_))) if *url == app.books[1].url));
+        assert!(!app.book_pages.contains_key(&app.books[1].url));
+
+        app.config.hover_prefetch = false;
+        app.hover = Some((app.books[1].url.clone(), rested()));
+        app.prefetch_hovered();
+        assert!(!app.book_pages.contains_key(&app.books[1].url));
+    }
+
+    #[test]
+    fn test_transfer_summary() {
+        let text = |line: Line| line.spans.iter().map(|span| span.content.to_string()).collect::<String>();
+        let mut transfer = TransferView {
+            id: 0,
+            downloaded: 3 * 1024 * 1024,
+            total: Some(10 * 1024 * 1024),
+            history: vec![850 * 1024],
+            speed: 850.0 * 1024.0,
+            eta: Some(Duration::from_secs(8)),
+            stalled: false,
+            waiting: None,
+        };
+        assert_eq!(text(transfer_summary(&transfer)), "Download 1: 3.0MB / 10.0MB | 850.0KB/s | ETA 8 seconds");
+
+        transfer.stalled = true;
+        transfer.total = None;
+        assert_eq!(text(transfer_summary(&transfer)), "Download 1: 3.0MB | stalled");
+
+        transfer.waiting = Some(42);
+        assert_eq!(text(transfer_summary(&transfer)), "Download 1: waiting in line, 42s left");
+    }
+
+    #[tokio::test]
+    async fn test_results_page_size() {
+        let mut app = create_test_app();
+        app.mode = AppMode::Results;
+        app.books = (1..=12)
+            .map(|i| Book {
+                title: format!("Book {}", i),
+                author: None,
+                year: None,
+                language: None,
+                format: None,
+                size_bytes: None,
+                md5: None,
+                url: format!("url{}", i),
+                metadata: Default::default(),
+            })
+            .collect();
+        app.fit_results = 5;
+
+        for _ in 0..6 {
+            app.handle_results_navigation(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE)).await.unwrap();
+        }
+        assert_eq!(app.selected_book_index, 6);
+        assert_eq!(app.results_scroll, 2);
+
+        // Shrinking the page keeps the selection on screen
+        app.handle_results_navigation(KeyEvent::new(KeyCode::Char('-'), KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.page_size(), 4);
+        assert_eq!(app.results_scroll, 3);
+
+        app.handle_results_navigation(KeyEvent::new(KeyCode::PageDown, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.selected_book_index, 10);
+        assert_eq!(app.results_scroll, 7);
+
+        app.handle_results_navigation(KeyEvent::new(KeyCode::PageDown, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.selected_book_index, 11);
+
+        // Growing is capped by what fits; 0 goes back to fitting the screen
+        for _ in 0..3 {
+            app.handle_results_navigation(KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE)).await.unwrap();
+        }
+        assert_eq!(app.page_size(), 5);
+        app.handle_results_navigation(KeyEvent::new(KeyCode::Char('0'), KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.results_per_page, None);
+
+        app.handle_results_navigation(KeyEvent::new(KeyCode::PageUp, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.selected_book_index, 6);
+        assert_eq!(app.results_scroll, 6);
+    }
+
     #[tokio::test]
     async fn test_handle_results_navigation_escape_returns_to_search() {
         let mut app = create_test_app();
@@ -939,8 +2022,10 @@ mod tests {
                 year: None,
                 language: None,
                 format: None,
-                size: None,
+                size_bytes: None,
+                md5: None,
                 url: "url1".to_string(),
+                metadata: Default::default(),
             },
         ];
 
@@ -962,11 +2047,13 @@ mod tests {
                 text: "Link 1".to_string(),
                 url: "url1".to_string(),
                 source: "Source 1".to_string(),
+                kind: LinkKind::Http,
             },
             DownloadLink {
                 text: "Link 2".to_string(),
                 url: "url2".to_string(),
                 source: "Source 2".to_string(),
+                kind: LinkKind::Http,
             },
         ];
         app.download_link_index = 0;
@@ -980,6 +2067,173 @@ mod tests {
         assert_eq!(app.download_link_index, 0);
     }
 
+    #[tokio::test]
+    async fn test_torrent_links_are_not_probed() {
+        let mut app = create_test_app();
+        app.mode = AppMode::DownloadSelection;
+        app.download_links = vec![DownloadLink {
+            text: "libgen_rs_fic".to_string(),
+            url: "https://annas-archive.org/torrents/libgen_rs_fic.torrent".to_string(),
+            source: "Torrent".to_string(),
+            kind: LinkKind::Torrent,
+        }];
+        app.probe_links();
+        assert!(app.link_health.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_arranges_links_as_answers_arrive() {
+        let not_found = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (gone, _gone) = test_server::serve(vec![not_found, not_found]).await;
+        let (live, _live) = test_server::serve(vec!["HTTP/1.1 200 OK\r\nContent-Length: 3000000\r\nConnection: close\r\n\r\n"]).await;
+        let mut app = create_test_app();
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_app_preflight_{}", nanos));
+        app.saved_db = dir.join("annadl.db");
+        app.config.link_check = LinkCheck::Hide;
+        app.mode = AppMode::DownloadSelection;
+        let link = |url: String| DownloadLink { text: url.clone(), url, source: "Mirror".to_string(), kind: LinkKind::Http };
+        app.download_links = vec![link(format!("{}/gone.epub", gone)), link(format!("{}/book.epub", live))];
+
+        // The list is usable while the mirrors are asked
+        app.preflight_links();
+        app.probe_links();
+        assert!(app.checking_links);
+        assert_eq!(app.download_links.len(), 2);
+
+        // An answer about links left earlier changes nothing
+        app.record_link_health("https://elsewhere.example/a".to_string(), LinkHealth::default());
+        assert!(!app.link_health.contains_key("https://elsewhere.example/a"));
+
+        for _ in 0..2 {
+            match app.command_rx.recv().await {
+                Some(AppCommand::LinkHealth(url, health)) => app.record_link_health(url, health),
+                _ => panic!("expected a link check"),
+            }
+        }
+        assert!(!app.checking_links);
+        let urls: Vec<&str> = app.download_links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(urls, [format!("{}/book.epub", live)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_enrichment() {
+        let mut app = create_test_app();
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "dune".to_string();
+        app.books = vec![book.clone()];
+        // The page added an ISBN while Open Library was asked
+        app.books[0].metadata.identifiers.isbn13.push("9780441172719".to_string());
+
+        let mut enriched = book;
+        enriched.title = "Dune".to_string();
+        enriched.author = Some("Frank Herbert".to_string());
+        enriched.year = Some(1900);
+        app.apply_enrichment(enriched.clone(), Ok(vec!["title", "author"]));
+        assert_eq!(app.books[0].title, "Dune");
+        assert_eq!(app.books[0].author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(app.books[0].year, None);
+        assert_eq!(app.books[0].metadata.identifiers.isbn13, vec!["9780441172719"]);
+        assert_eq!(app.enrich_status.as_deref(), Some("Open Library filled in title, author"));
+
+        app.apply_enrichment(enriched, Err("Failed to reach Open Library".to_string()));
+        assert_eq!(app.enrich_status.as_deref(), Some("Open Library lookup failed: Failed to reach Open Library"));
+    }
+
+    fn test_books() -> Vec<Book> {
+        ["a", "b", "c"].iter()
+            .map(|md5| Book::from_md5("https://annas-archive.org", &md5.repeat(32)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_pages_fill_the_list() {
+        let mut app = create_test_app();
+        app.config.prefetch_results = 0;
+        app.mode = AppMode::Downloading;
+        app.search = Some((3, tokio::spawn(std::future::pending())));
+        let books = test_books();
+
+        // The first page shows the results, later ones add to them
+        let total = Some(ResultCount { count: 3, approximate: false });
+        app.append_search_page(3, SearchResults { books: books[..2].to_vec(), total });
+        assert!(matches!(app.mode, AppMode::Results));
+        assert_eq!(app.total_results, total);
+        app.selected_book_index = 1;
+        app.append_search_page(3, SearchResults { books: books[2..].to_vec(), total: None });
+        assert_eq!(app.books.len(), 3);
+        assert_eq!(app.total_results, total);
+
+        // An earlier search's answers are dropped
+        app.append_search_page(2, SearchResults { books: books.clone(), total: None });
+        app.finish_search(2, SearchResults::default());
+        assert_eq!(app.books.len(), 3);
+
+        // The final list may be ordered differently; the selection follows its book
+        let reordered = vec![books[1].clone(), books[2].clone(), books[0].clone()];
+        app.finish_search(3, SearchResults { books: reordered, total });
+        assert_eq!(app.books[0].id(), books[1].id());
+        assert_eq!(app.selected_book_index, 0);
+        assert!(app.search.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_in_background() {
+        let mut app = create_test_app();
+        app.config.archive_url = test_server::unused_url().await;
+        app.config.retry_max = 0;
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        app.open_in_background(md5.to_string());
+
+        // Without a record the book is a placeholder on the configured site
+        let Some(AppCommand::Opened(id, book)) = app.command_rx.recv().await else {
+            panic!("expected the opened book");
+        };
+        assert_eq!(book.md5(), Some(md5));
+        app.show_opened(id, *book);
+        assert_eq!(app.books.len(), 1);
+        assert!(matches!(app.command_rx.try_recv(), Ok(AppCommand::FetchDownloadLinks(_))));
+        assert!(app.search.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_leaving_results_stops_the_search() {
+        let mut app = create_test_app();
+        app.config.prefetch_results = 0;
+        app.search = Some((0, tokio::spawn(std::future::pending())));
+        app.append_search_page(0, SearchResults { books: test_books(), total: None });
+        assert!(matches!(app.mode, AppMode::Results));
+
+        app.handle_keypress(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).await.unwrap();
+        assert!(app.search.is_none());
+        // A page already on its way doesn't pull the user back to results
+        app.append_search_page(0, SearchResults { books: test_books(), total: None });
+        assert!(matches!(app.mode, AppMode::Search));
+        assert!(app.books.is_empty());
+        assert!(!app.end_search(0));
+    }
+
+    #[tokio::test]
+    async fn test_leaving_links_stops_their_check() {
+        let mut app = create_test_app();
+        app.config.link_check = LinkCheck::Demote;
+        app.mode = AppMode::DownloadSelection;
+        app.download_links = vec![DownloadLink {
+            text: "Mirror".to_string(),
+            url: test_server::unused_url().await,
+            source: "Mirror".to_string(),
+            kind: LinkKind::Http,
+        }];
+        app.preflight_links();
+        app.probe_links();
+
+        app.handle_download_selection(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).await.unwrap();
+        assert!(!app.checking_links);
+        assert!(app.link_health.is_empty());
+        assert!(app.link_probe.is_none());
+    }
+
     #[tokio::test]
     async fn test_handle_download_selection_escape_returns_to_results() {
         let mut app = create_test_app();
@@ -989,6 +2243,7 @@ mod tests {
                 text: "Link 1".to_string(),
                 url: "url1".to_string(),
                 source: "Source 1".to_string(),
+                kind: LinkKind::Http,
             },
         ];
 
@@ -1000,6 +2255,27 @@ mod tests {
         assert!(app.download_links.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_other_editions_replace_results() {
+        let mut app = create_test_app();
+        app.config.prefetch_results = 0;
+        app.mode = AppMode::DownloadSelection;
+        app.books = vec![Book::from_md5("https://annas-archive.org", "5c1b4ee5d18a5c6a9e047fa74b8f37d1")];
+        let key = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE);
+
+        // Nothing to switch to
+        app.handle_download_selection(key).await.unwrap();
+        assert!(matches!(app.mode, AppMode::DownloadSelection));
+
+        let edition = Book::from_md5("https://annas-archive.org", "0cc175b9c0f1b6a831c399e269772661");
+        app.editions = vec![edition.clone()];
+        app.selected_book_index = 0;
+        app.handle_download_selection(key).await.unwrap();
+        assert!(matches!(app.mode, AppMode::Results));
+        assert_eq!(app.books.iter().map(|b| b.url.as_str()).collect::<Vec<_>>(), [edition.url.as_str()]);
+        assert!(app.editions.is_empty());
+    }
+
     #[tokio::test]
     async fn test_handle_error_escape_returns_to_search() {
         let mut app = create_test_app();
@@ -1026,6 +2302,48 @@ mod tests {
         assert!(matches!(app.mode, AppMode::Search));
     }
 
+    #[tokio::test]
+    async fn test_save_and_run_saved_search() {
+        let mut app = create_test_app();
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_app_saved_{}", nanos));
+        app.saved_db = dir.join("annadl.db");
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        // Nothing to save without a query
+        app.handle_keypress(ctrl('s')).await.unwrap();
+        assert!(matches!(app.mode, AppMode::Search));
+
+        app.query = "Scheibenwelt".to_string();
+        app.filters.format = Some("epub".to_string());
+        app.handle_keypress(ctrl('s')).await.unwrap();
+        assert!(matches!(app.mode, AppMode::SaveSearch));
+        for c in "de".chars() {
+            app.handle_keypress(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)).await.unwrap();
+        }
+        app.handle_keypress(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).await.unwrap();
+        assert!(matches!(app.mode, AppMode::Search));
+
+        app.query.clear();
+        app.set_filters(SearchFilters::default());
+        app.handle_keypress(ctrl('r')).await.unwrap();
+        assert!(matches!(app.mode, AppMode::SavedSearches));
+        assert_eq!(app.saved_searches.len(), 1);
+
+        app.handle_keypress(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).await.unwrap();
+        assert_eq!(app.query, "Scheibenwelt");
+        assert_eq!(app.filter_format_input, "epub");
+        assert!(matches!(app.command_rx.try_recv(), Ok(AppCommand::Search(q, filters, _)) if q == "Scheibenwelt" && filters.format.as_deref() == Some("epub")));
+
+        app.mode = AppMode::Search;
+        app.handle_keypress(ctrl('r')).await.unwrap();
+        app.handle_keypress(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE)).await.unwrap();
+        assert!(app.saved_searches.is_empty());
+        assert!(saved::Store::open(&app.saved_db).unwrap().list().unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[tokio::test]
     async fn test_handle_help_toggle() {
         let mut app = create_test_app();
@@ -1063,6 +2381,49 @@ mod tests {
         assert_ne!(ControlFlow::Continue, ControlFlow::Exit);
     }
 
+    #[tokio::test]
+    async fn test_rank_links() {
+        let mut app = create_test_app();
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_app_rank_{}", nanos));
+        app.saved_db = dir.join("annadl.db");
+        let link = |source: &str, url: &str, kind| DownloadLink { text: source.to_string(), url: url.to_string(), source: source.to_string(), kind };
+        app.download_links = vec![
+            link("Torrent", "https://annas-archive.org/torrents/a.torrent", LinkKind::Torrent),
+            link("Anna's Archive", "https://annas-archive.org/slow_download/a/0/0", LinkKind::Http),
+            link("LibGen", "https://libgen.li/get.php?md5=a", LinkKind::Http),
+            link("IPFS", "https://ipfs.io/ipfs/Qm", LinkKind::Http),
+        ];
+        app.link_health.insert("https://libgen.li/get.php?md5=a".to_string(), Some(LinkHealth { status: Some(404), ..LinkHealth::default() }));
+
+        app.rank_links();
+        let sources: Vec<&str> = app.download_links.iter().map(|link| link.source.as_str()).collect();
+        assert_eq!(sources, ["IPFS", "Anna's Archive", "LibGen", "Torrent"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_link_health_spans() {
+        let mut app = create_test_app();
+        let text = |spans: Vec<Span>| spans.iter().map(|s| s.content.to_string()).collect::<String>();
+        let answered = |size| Some(LinkHealth { status: Some(200), size, latency: Duration::from_millis(120) });
+
+        assert!(app.link_health_spans("a", None).is_empty());
+
+        app.link_health.insert("a".to_string(), None);
+        assert!(text(app.link_health_spans("a", None)).contains("checking"));
+
+        app.link_health.insert("a".to_string(), answered(RemoteSize { bytes: Some(2 * 1024 * 1024), is_html: false }));
+        assert_eq!(text(app.link_health_spans("a", Some(2_000_000))), " | Size: 2.0MB | 120 ms");
+        assert!(text(app.link_health_spans("a", Some(50_000_000))).contains("likely an error page"));
+
+        app.link_health.insert("a".to_string(), answered(RemoteSize { bytes: None, is_html: true }));
+        assert!(text(app.link_health_spans("a", None)).contains("web page"));
+
+        app.link_health.insert("a".to_string(), Some(LinkHealth { status: Some(404), ..LinkHealth::default() }));
+        assert_eq!(text(app.link_health_spans("a", None)), " | Dead: HTTP 404");
+    }
+
     #[test]
     fn test_app_command_clone() {
         let cmd = AppCommand::Search("test".to_string(), SearchFilters::default(), 5);
@@ -0,0 +1,129 @@
+use crate::term;
+use ratatui::{
+    backend::{Backend, ClearType, WindowSize},
+    buffer::Cell,
+    layout::Rect,
+};
+use std::io;
+use unicode_width::UnicodeWidthStr;
+
+/// Wraps a backend and swaps box drawing, sparkline blocks, arrows and
+/// other symbols for ASCII as cells are drawn, so every widget degrades
+/// without knowing about it. Does nothing unless `enabled`.
+pub struct AsciiBackend<B> {
+    inner: B,
+    enabled: bool,
+}
+
+impl<B> AsciiBackend<B> {
+    pub fn new(inner: B, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+/// The ASCII version of one cell, plus a blank for the second column when
+/// a wide symbol became a narrow one.
+fn to_ascii(x: u16, y: u16, cell: &Cell) -> Vec<(u16, u16, Cell)> {
+    let symbol = cell.symbol();
+    let Some(replacement) = symbol.chars().next().and_then(term::glyph) else {
+        return vec![(x, y, cell.clone())];
+    };
+    let mut ascii = cell.clone();
+    ascii.set_char(replacement.chars().next().unwrap_or(' '));
+    let mut cells = vec![(x, y, ascii)];
+    if symbol.width() > 1 {
+        let mut blank = cell.clone();
+        blank.set_char(' ');
+        cells.push((x + 1, y, blank));
+    }
+    cells
+}
+
+impl<B: Backend> Backend for AsciiBackend<B> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        if !self.enabled {
+            return self.inner.draw(content);
+        }
+        let cells: Vec<(u16, u16, Cell)> = content.flat_map(|(x, y, cell)| to_ascii(x, y, cell)).collect();
+        self.inner.draw(cells.iter().map(|(x, y, cell)| (*x, *y, cell)))
+    }
+
+    fn append_lines(&mut self, n: u16) -> io::Result<()> {
+        self.inner.append_lines(n)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        self.inner.get_cursor()
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.inner.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.inner.clear_region(clear_type)
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        self.inner.size()
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        self.inner.window_size()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{
+        backend::TestBackend,
+        widgets::{Block, Borders, Paragraph, Sparkline},
+        Terminal,
+    };
+
+    fn render(enabled: bool) -> Vec<String> {
+        let mut terminal = Terminal::new(AsciiBackend::new(TestBackend::new(12, 4), enabled)).unwrap();
+        terminal.draw(|f| {
+            let block = Block::default().borders(Borders::ALL).title("Books");
+            let inner = block.inner(f.size());
+            f.render_widget(block, f.size());
+            f.render_widget(Paragraph::new("⚠ Dune…"), Rect { height: 1, ..inner });
+            f.render_widget(Sparkline::default().data(&[1, 4, 8]), Rect { y: inner.y + 1, height: 1, ..inner });
+        }).unwrap();
+        let buffer = terminal.backend().inner.buffer();
+        (0..4)
+            .map(|y| (0..12).map(|x| buffer.get(x, y).symbol().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_ascii_backend() {
+        assert_eq!(render(true), vec![
+            "+Books-----+",
+            "|! Dune.   |",
+            "|.:#       |",
+            "+----------+",
+        ]);
+        assert_eq!(render(false)[0], "┌Books─────┐");
+    }
+}
@@ -1,3 +1,5 @@
 pub mod app;
+pub mod ascii;
+pub mod picker;
 
 pub use app::{App, AppCommand, AppMode, ControlFlow};
@@ -0,0 +1,237 @@
+use super::ascii::AsciiBackend;
+use crate::term;
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{List, ListItem, ListState, Paragraph},
+    Terminal, TerminalOptions, Viewport,
+};
+
+/// Rows of matches shown below the prompt.
+const MAX_VISIBLE: usize = 10;
+
+/// Scores `candidate` against space-separated query terms, fzf style: each
+/// term must match as a subsequence, and consecutive or word-start matches
+/// rank higher. None when any term doesn't match.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    query.to_lowercase().split_whitespace().try_fold(0, |total, term| {
+        let mut score = 0;
+        let mut pos = 0;
+        let mut last_match: Option<usize> = None;
+        for q in term.chars() {
+            let found = pos + candidate[pos..].iter().position(|&c| c == q)?;
+            score += 16;
+            if last_match.is_some_and(|last| last + 1 == found) {
+                score += 8;
+            }
+            if found == 0 || !candidate[found - 1].is_alphanumeric() {
+                score += 8;
+            }
+            score -= (found - pos) as i64;
+            last_match = Some(found);
+            pos = found + 1;
+        }
+        Some(total + score)
+    })
+}
+
+/// Indices of the items matching `query`, best first; ties keep list order.
+pub fn filter(query: &str, items: &[String]) -> Vec<usize> {
+    let mut scored: Vec<(usize, i64)> = items.iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(query, item).map(|score| (i, score)))
+        .collect();
+    scored.sort_by_key(|&(i, score)| (std::cmp::Reverse(score), i));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Picked(usize),
+    Cancelled,
+}
+
+/// Query and cursor state, kept apart from drawing so key handling is testable.
+struct Picker<'a> {
+    items: &'a [String],
+    query: String,
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl<'a> Picker<'a> {
+    fn new(items: &'a [String]) -> Self {
+        Self {
+            items,
+            query: String::new(),
+            matches: (0..items.len()).collect(),
+            selected: 0,
+        }
+    }
+
+    fn refilter(&mut self) {
+        self.matches = filter(&self.query, self.items);
+        self.selected = 0;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Outcome> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(Outcome::Cancelled),
+            KeyCode::Char('c') if ctrl => return Some(Outcome::Cancelled),
+            KeyCode::Enter => return self.matches.get(self.selected).map(|&i| Outcome::Picked(i)),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.move_down(),
+            KeyCode::Char('n') if ctrl => self.move_down(),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.refilter();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refilter();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.refilter();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn move_down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+}
+
+/// Lets the user fuzzy-filter `items` and pick one, drawn inline below the
+/// cursor instead of taking over the screen. Returns the index into `items`,
+/// or None when cancelled.
+pub fn pick(prompt: &str, items: &[String]) -> Result<Option<usize>> {
+    let height = (items.len().min(MAX_VISIBLE) + 1) as u16;
+    let mut terminal = Terminal::with_options(
+        AsciiBackend::new(CrosstermBackend::new(std::io::stdout()), term::ascii()),
+        TerminalOptions { viewport: Viewport::Inline(height) },
+    )?;
+    enable_raw_mode()?;
+    let result = run(&mut terminal, prompt, items);
+    // Leave the scrollback as it was before the picker
+    let _ = terminal.clear();
+    disable_raw_mode()?;
+    result
+}
+
+fn run(terminal: &mut Terminal<AsciiBackend<CrosstermBackend<std::io::Stdout>>>, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+    let mut picker = Picker::new(items);
+    loop {
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(f.size());
+
+            let input = Line::from(vec![
+                Span::styled(format!("{}> ", prompt), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                Span::raw(picker.query.as_str()),
+                Span::styled(format!("  {}/{}", picker.matches.len(), items.len()), Style::default().fg(Color::DarkGray)),
+            ]);
+            f.render_widget(Paragraph::new(input), chunks[0]);
+
+            let rows: Vec<ListItem> = picker.matches.iter()
+                .map(|&i| ListItem::new(items[i].as_str()))
+                .collect();
+            let list = List::new(rows)
+                .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                .highlight_symbol("▶ ");
+            let mut state = ListState::default();
+            state.select((!picker.matches.is_empty()).then_some(picker.selected));
+            f.render_stateful_widget(list, chunks[1], &mut state);
+        })?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match picker.handle_key(key) {
+                Some(Outcome::Picked(i)) => return Ok(Some(i)),
+                Some(Outcome::Cancelled) => return Ok(None),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<String> {
+        [
+            "Dune — Frank Herbert (1965, EPUB)",
+            "Children of Dune — Frank Herbert (1976, PDF)",
+            "The Hobbit — J.R.R. Tolkien (1937, EPUB)",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("dne", "Dune").is_some());
+        assert_eq!(fuzzy_score("xyz", "Dune"), None);
+        // Every term has to match
+        assert_eq!(fuzzy_score("dune tolkien", "Dune — Frank Herbert"), None);
+        // Consecutive word-start matches beat scattered ones
+        assert!(fuzzy_score("dune", "Dune") > fuzzy_score("dune", "dark unknown net"));
+    }
+
+    #[test]
+    fn test_filter_ranks_best_match_first() {
+        let items = items();
+        assert_eq!(filter("", &items), vec![0, 1, 2]);
+        assert_eq!(filter("dune", &items), vec![0, 1]);
+        assert_eq!(filter("hobbit epub", &items), vec![2]);
+        assert_eq!(filter("tolkien", &items), vec![2]);
+    }
+
+    #[test]
+    fn test_picker_keys() {
+        let items = items();
+        let mut picker = Picker::new(&items);
+
+        assert_eq!(picker.handle_key(key(KeyCode::Down)), None);
+        assert_eq!(picker.handle_key(key(KeyCode::Down)), None);
+        assert_eq!(picker.handle_key(key(KeyCode::Down)), None);
+        assert_eq!(picker.selected, 2);
+
+        for c in "child".chars() {
+            picker.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(picker.selected, 0);
+        assert_eq!(picker.handle_key(key(KeyCode::Enter)), Some(Outcome::Picked(1)));
+
+        picker.handle_key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(picker.query, "");
+        assert_eq!(picker.matches.len(), 3);
+
+        picker.handle_key(key(KeyCode::Char('z')));
+        assert_eq!(picker.handle_key(key(KeyCode::Enter)), None);
+        assert_eq!(picker.handle_key(key(KeyCode::Esc)), Some(Outcome::Cancelled));
+    }
+}
@@ -0,0 +1,127 @@
+//! Pushing finished downloads to a WebDAV share or an rclone remote.
+
+use crate::account;
+use crate::config::Config;
+use crate::http;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+const KEYRING_ENTRY: &str = "webdav";
+
+/// Whether any upload target is configured.
+pub fn enabled(config: &Config) -> bool {
+    config.webdav_url.is_some() || config.rclone_remote.is_some()
+}
+
+/// Uploads `file` to every configured target, returning where it went.
+/// Stops at the first failure.
+pub async fn upload(config: &Config, file: &Path) -> Result<Vec<String>> {
+    let mut done = Vec::new();
+    if let Some(ref url) = config.webdav_url {
+        let target = webdav_put(config, url, file).await?;
+        done.push(target);
+    }
+    if let Some(ref remote) = config.rclone_remote {
+        rclone_copy(config, remote, file).await?;
+        done.push(remote.clone());
+    }
+    Ok(done)
+}
+
+/// The file's URL inside the WebDAV collection at `base`.
+fn webdav_target(base: &str, name: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), urlencoding::encode(name))
+}
+
+async fn webdav_put(config: &Config, base: &str, file: &Path) -> Result<String> {
+    let name = file.file_name().context("Not a file")?.to_string_lossy().into_owned();
+    let target = webdav_target(base, &name);
+    let client = http::client_builder(config, config.download_timeouts())?
+        .build()
+        .context("Failed to build HTTP client")?;
+    let body = tokio::fs::File::open(file).await
+        .with_context(|| format!("Failed to open {}", file.display()))?;
+
+    let mut request = client.put(&target).body(body);
+    if let Some(ref username) = config.webdav_username {
+        request = request.basic_auth(username, account::load_password(KEYRING_ENTRY).await?);
+    }
+    let response = request.send().await
+        .with_context(|| format!("Failed to upload to {}", target))?;
+    if !response.status().is_success() {
+        anyhow::bail!("WebDAV upload to {} failed: HTTP {}", target, response.status());
+    }
+    Ok(target)
+}
+
+async fn rclone_copy(config: &Config, remote: &str, file: &Path) -> Result<()> {
+    let rclone = config.rclone_path.clone().unwrap_or_else(|| PathBuf::from("rclone"));
+    let output = tokio::process::Command::new(&rclone)
+        .arg("copy")
+        .arg(file)
+        .arg(remote)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} (is rclone installed?)", rclone.display()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("rclone exited with {}: {}", output.status, stderr.lines().last().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Saves the WebDAV password typed at `annadl webdav-login`.
+pub async fn store_password(password: String) -> Result<()> {
+    account::store_password(KEYRING_ENTRY, "the WebDAV password", password).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("annadl_upload_{}", nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_webdav_target() {
+        assert_eq!(webdav_target("https://nas.local/books/", "Dune - Frank Herbert.epub"), "https://nas.local/books/Dune%20-%20Frank%20Herbert.epub");
+        assert_eq!(webdav_target("https://nas.local/books", "a.pdf"), "https://nas.local/books/a.pdf");
+    }
+
+    #[tokio::test]
+    async fn test_webdav_put() {
+        let (base, server) = test_server::serve(vec!["HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n"]).await;
+
+        let file = temp_file("Dune.pdf", b"%PDF-1.7");
+        let config = Config { webdav_url: Some(format!("{}/books/", base)), ..Config::default() };
+        let uploaded = upload(&config, &file).await.unwrap();
+        assert_eq!(uploaded, vec![format!("{}/books/Dune.pdf", base)]);
+
+        let request = &server.await.unwrap()[0];
+        assert!(request.starts_with("PUT /books/Dune.pdf HTTP/1.1"));
+        assert!(request.contains("%PDF-1.7"));
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rclone_missing() {
+        let file = temp_file("Dune.epub", b"PK");
+        let config = Config {
+            rclone_remote: Some("nas:books".to_string()),
+            rclone_path: Some(PathBuf::from("/nonexistent/rclone")),
+            ..Config::default()
+        };
+        assert!(enabled(&config));
+        let err = upload(&config, &file).await.unwrap_err();
+        assert!(err.to_string().contains("is rclone installed"));
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! `annadl saved watch`: reruns the saved searches on a timer and announces
+//! books that weren't in their results before, on the desktop and to a
+//! webhook.
+
+use crate::config::Config;
+use crate::http;
+use crate::saved::{SavedSearch, Store};
+use crate::schema::WatchJson;
+use crate::scraper::Book;
+use crate::source::SourceProvider;
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+
+/// Books a saved search found that it hadn't seen before.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub search: SavedSearch,
+    pub books: Vec<Book>,
+}
+
+/// Runs every saved search once and records what it found. The first run
+/// of a search only takes note of its results; later runs return the books
+/// that are new. A search that fails is reported and skipped.
+pub async fn check(source: &dyn SourceProvider, store: &Store, max_results: usize) -> Result<Vec<Hit>> {
+    let mut hits = Vec::new();
+    for search in store.list()? {
+        let results = match source.search(&search.query, &search.filters, max_results).await {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("Saved search '{}' failed: {:#}", search.name, e);
+                continue;
+            }
+        };
+        let first = !store.watched(&search.name)?;
+        let md5s: Vec<&str> = results.books.iter().filter_map(Book::md5).collect();
+        let new = store.record_run(&search.name, &md5s)?;
+        if first || new.is_empty() {
+            continue;
+        }
+        let books = results.books.into_iter()
+            .filter(|book| book.md5().is_some_and(|md5| new.iter().any(|n| n == md5)))
+            .collect();
+        hits.push(Hit { search, books });
+    }
+    Ok(hits)
+}
+
+/// Announces a hit on the desktop and to `watch_webhook`, whichever are
+/// configured. Both are tried; the first failure is returned.
+pub async fn notify(config: &Config, hit: &Hit) -> Result<()> {
+    let desktop = if config.watch_desktop {
+        notify_desktop(&title(hit), &summary(&hit.books))
+    } else {
+        Ok(())
+    };
+    let webhook = match config.watch_webhook {
+        Some(ref url) => post_webhook(config, url, hit).await,
+        None => Ok(()),
+    };
+    desktop.and(webhook)
+}
+
+fn title(hit: &Hit) -> String {
+    match hit.books.len() {
+        1 => format!("1 new book for '{}'", hit.search.name),
+        n => format!("{} new books for '{}'", n, hit.search.name),
+    }
+}
+
+/// The first few titles, one per line.
+fn summary(books: &[Book]) -> String {
+    const SHOWN: usize = 3;
+    let mut lines: Vec<String> = books.iter().take(SHOWN).map(|book| match book.author {
+        Some(ref author) => format!("{} by {}", book.title, author),
+        None => book.title.clone(),
+    }).collect();
+    if books.len() > SHOWN {
+        lines.push(format!("and {} more", books.len() - SHOWN));
+    }
+    lines.join("\n")
+}
+
+async fn post_webhook(config: &Config, url: &str, hit: &Hit) -> Result<()> {
+    let client = http::client_builder(config, config.scraper_timeouts())?
+        .build()
+        .context("Failed to build HTTP client")?;
+    let body = WatchJson::new(&hit.search.name, &hit.search.query, &hit.books);
+    let response = client.post(url).json(&body).send().await
+        .with_context(|| format!("Failed to reach the webhook at {}", url))?;
+    if !response.status().is_success() {
+        anyhow::bail!("Webhook at {} answered HTTP {}", url, response.status());
+    }
+    Ok(())
+}
+
+fn notify_desktop(title: &str, body: &str) -> Result<()> {
+    let mut command = desktop_command(title, body);
+    let program = command.get_program().to_string_lossy().into_owned();
+    // Not waited on, like the file opener: the notification outlives the call
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {} for a desktop notification", program))?;
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn desktop_command(title: &str, body: &str) -> Command {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!("display notification {} with title {}", quote(body), quote(title)));
+    command
+}
+
+#[cfg(target_os = "windows")]
+fn desktop_command(title: &str, body: &str) -> Command {
+    // The text goes through the environment so it needs no quoting
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Windows.Forms; \
+        $n = New-Object System.Windows.Forms.NotifyIcon; \
+        $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+        $n.ShowBalloonTip(10000, $env:ANNADL_TITLE, $env:ANNADL_BODY, 'Info'); Start-Sleep 10; $n.Dispose()";
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-Command", SCRIPT]).env("ANNADL_TITLE", title).env("ANNADL_BODY", body);
+    command
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn desktop_command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=annadl", title, body]);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{AnnaScraper, SearchFilters};
+    use crate::test_server;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn temp_db() -> PathBuf {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("annadl_watch_{}", nanos)).join("annadl.db")
+    }
+
+    fn saved(name: &str) -> SavedSearch {
+        SavedSearch { name: name.to_string(), query: name.to_string(), filters: SearchFilters::default(), saved_at: None }
+    }
+
+    fn search_page(books: usize) -> String {
+        let links: String = (0..books)
+            .map(|i| format!("<div class=\"book-item\"><a href=\"/md5/{:032x}\" class=\"js-vim-focus custom-a\">Book {}</a><div>EPUB, 1MB</div></div>", i, i))
+            .collect();
+        test_server::response("200 OK", "text/html", &format!("<html><body><div>{} results</div>{}</body></html>", books, links))
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_only_new_books() {
+        // Each run finds one more book than the last
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let (base, _requests) = test_server::serve_with(move |_| search_page(2 + counter.fetch_add(1, Ordering::SeqCst))).await;
+        let config = Config { archive_url: base, libgen_fallback: false, ..Config::default() };
+        let source = AnnaScraper::new(&config).unwrap();
+        let path = temp_db();
+        let store = Store::open(&path).unwrap();
+        store.save(&saved("dune")).unwrap();
+
+        // The first run only notes what is there
+        assert!(check(&source, &store, 10).await.unwrap().is_empty());
+        let hits = check(&source, &store, 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].search.name, "dune");
+        let titles: Vec<&str> = hits[0].books.iter().map(|b| b.title.as_str()).collect();
+        assert_eq!(titles, vec!["Book 2"]);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_announces_first_books_of_empty_search() {
+        // Nothing matches on the first run, then two books show up
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let (base, _requests) = test_server::serve_with(move |_| search_page(2 * counter.fetch_add(1, Ordering::SeqCst))).await;
+        let config = Config { archive_url: base, libgen_fallback: false, ..Config::default() };
+        let source = AnnaScraper::new(&config).unwrap();
+        let path = temp_db();
+        let store = Store::open(&path).unwrap();
+        store.save(&saved("dune")).unwrap();
+
+        assert!(check(&source, &store, 10).await.unwrap().is_empty());
+        let hits = check(&source, &store, 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].books.len(), 2);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notify_posts_to_webhook() {
+        let (url, server) = test_server::serve(vec![test_server::response("204 No Content", "text/plain", "")]).await;
+        let config = Config { watch_desktop: false, watch_webhook: Some(format!("{}/hook", url)), ..Config::default() };
+        let mut book = Book::from_md5("https://annas-archive.org", "d41d8cd98f00b204e9800998ecf8427e");
+        book.title = "Dune".to_string();
+        let hit = Hit { search: saved("dune"), books: vec![book] };
+        notify(&config, &hit).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /hook "));
+        let body: WatchJson = serde_json::from_str(requests[0].split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body.search, "dune");
+        assert_eq!(body.books[0].title, "Dune");
+
+        let (url, _server) = test_server::serve(vec![test_server::response("500 Internal Server Error", "text/plain", "")]).await;
+        let config = Config { watch_webhook: Some(url), ..config };
+        assert!(notify(&config, &hit).await.unwrap_err().to_string().contains("HTTP 500"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let books: Vec<Book> = (0..5).map(|i| {
+            let mut book = Book::from_md5("https://annas-archive.org", &format!("{:032x}", i));
+            book.title = format!("Book {}", i);
+            book.author = (i == 0).then(|| "Frank Herbert".to_string());
+            book
+        }).collect();
+        assert_eq!(summary(&books), "Book 0 by Frank Herbert\nBook 1\nBook 2\nand 2 more");
+        assert_eq!(summary(&books[..1]), "Book 0 by Frank Herbert");
+        let hit = Hit { search: saved("dune"), books };
+        assert_eq!(title(&hit), "5 new books for 'dune'");
+    }
+}